@@ -0,0 +1,524 @@
+use anyhow::{anyhow, Result};
+use chrono::{Local, TimeZone};
+use clap::ValueEnum;
+use std::{
+    collections::HashSet,
+    fs::{self, File, Permissions},
+    io::{self, Read, Seek, Write},
+    os::unix::fs::{symlink, MetadataExt, PermissionsExt},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use walkdir::WalkDir;
+
+use crate::cmd::write_atomic_with;
+use crate::touch::touch_file;
+
+/// Archive formats understood by `pack` and `unpack`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guess the format from an archive's file name.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        [
+            (".tar.gz", ArchiveFormat::TarGz),
+            (".tgz", ArchiveFormat::TarGz),
+            (".tar.zst", ArchiveFormat::TarZst),
+            (".tzst", ArchiveFormat::TarZst),
+            (".tar", ArchiveFormat::Tar),
+            (".zip", ArchiveFormat::Zip),
+        ]
+        .into_iter()
+        .find(|(ext, _)| name.ends_with(ext))
+        .map(|(_, f)| f)
+    }
+
+    fn resolve(path: &Path, format: Option<Self>) -> Result<Self> {
+        format
+            .or_else(|| Self::from_path(path))
+            .ok_or_else(|| anyhow!("Unknown archive format, use --format: {}", path.display()))
+    }
+}
+
+/// Pack `paths` into a new archive. Each path is stored under its own file
+/// name, symlinks are stored as links, and permissions and modification
+/// times are recorded. The archive is never overwritten.
+pub fn pack(archive: &Path, paths: &[PathBuf], format: Option<ArchiveFormat>) -> Result<String> {
+    let format = ArchiveFormat::resolve(archive, format)?;
+    if archive.exists() {
+        return Err(anyhow!("Destination file exists"));
+    }
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let base = match path.file_name() {
+            Some(n) => PathBuf::from(n),
+            None => fs::canonicalize(path)?
+                .file_name()
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow!("Cannot pack {}", path.display()))?,
+        };
+        for entry in WalkDir::new(path).follow_links(false).sort_by_file_name() {
+            let entry = entry?;
+            let name = base.join(entry.path().strip_prefix(path)?);
+            entries.push((entry.into_path(), name));
+        }
+    }
+
+    // Entries are collected before the temporary archive exists, so it can
+    // never be packed into itself.
+    write_atomic_with(archive, false, |file| {
+        match format {
+            ArchiveFormat::Tar => {
+                write_tar(file, &entries)?;
+            }
+            ArchiveFormat::TarGz => {
+                write_tar(flate2::write::GzEncoder::new(file, flate2::Compression::default()), &entries)?.finish()?;
+            }
+            ArchiveFormat::TarZst => {
+                write_tar(zstd::Encoder::new(file, 0)?, &entries)?.finish()?;
+            }
+            ArchiveFormat::Zip => write_zip(file, &entries)?,
+        }
+        Ok(())
+    })?;
+
+    let msg = format!(
+        "Packed {} entries successfully: {}",
+        entries.len(),
+        archive.to_str().unwrap_or_default()
+    );
+
+    Ok(msg)
+}
+
+fn write_tar<W: Write>(w: W, entries: &[(PathBuf, PathBuf)]) -> Result<W> {
+    let mut builder = tar::Builder::new(w);
+    builder.follow_symlinks(false);
+    builder.mode(tar::HeaderMode::Complete);
+    for (path, name) in entries {
+        builder.append_path_with_name(path, name)?;
+    }
+    Ok(builder.into_inner()?)
+}
+
+fn write_zip<W: Write + Seek>(w: W, entries: &[(PathBuf, PathBuf)]) -> Result<()> {
+    let mut zip = zip::ZipWriter::new(w);
+    for (path, name) in entries {
+        let meta = fs::symlink_metadata(path)?;
+        let name = name.to_str().ok_or_else(|| anyhow!("Non UTF-8 path: {}", name.display()))?;
+        let mut options = zip::write::SimpleFileOptions::default().unix_permissions(meta.mode() & 0o7777);
+        let mtime = chrono::DateTime::<Local>::from(meta.modified()?).naive_local();
+        if let Ok(t) = zip::DateTime::try_from(mtime) {
+            options = options.last_modified_time(t);
+        }
+
+        if meta.file_type().is_symlink() {
+            let target = fs::read_link(path)?;
+            zip.add_symlink(name, target.to_string_lossy(), options)?;
+        } else if meta.is_dir() {
+            zip.add_directory(name, options)?;
+        } else {
+            zip.start_file(name, options)?;
+            io::copy(&mut File::open(path)?, &mut zip)?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[derive(Debug)]
+enum Kind {
+    Dir,
+    File,
+    Symlink(PathBuf),
+    Hardlink(PathBuf),
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    kind: Kind,
+    mode: u32,
+    mtime: Option<SystemTime>,
+}
+
+/// Call `f` for every entry of the archive with a reader over its contents.
+fn for_each_entry(
+    archive: &Path,
+    format: ArchiveFormat,
+    mut f: impl FnMut(Entry, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let file = File::open(archive)?;
+    let reader: Box<dyn Read> = match format {
+        ArchiveFormat::Tar => Box::new(file),
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        ArchiveFormat::TarZst => Box::new(zstd::Decoder::new(file)?),
+        ArchiveFormat::Zip => return for_each_zip_entry(file, f),
+    };
+
+    for entry in tar::Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        let header = entry.header();
+        let link = || -> Result<PathBuf> {
+            Ok(entry
+                .link_name()?
+                .ok_or_else(|| anyhow!("Link without target in archive"))?
+                .into_owned())
+        };
+        let kind = match header.entry_type() {
+            tar::EntryType::Directory => Kind::Dir,
+            tar::EntryType::Regular | tar::EntryType::Continuous => Kind::File,
+            tar::EntryType::Symlink => Kind::Symlink(link()?),
+            tar::EntryType::Link => Kind::Hardlink(link()?),
+            other => return Err(anyhow!("Unsupported entry type {:?}: {}", other, entry.path()?.display())),
+        };
+        let meta = Entry {
+            path: entry.path()?.into_owned(),
+            kind,
+            mode: header.mode()? & 0o7777,
+            mtime: Some(UNIX_EPOCH + Duration::from_secs(header.mtime()?)),
+        };
+        f(meta, &mut entry)?;
+    }
+    Ok(())
+}
+
+fn for_each_zip_entry(file: File, mut f: impl FnMut(Entry, &mut dyn Read) -> Result<()>) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(file)?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let kind = if entry.is_symlink() {
+            let mut target = String::new();
+            entry.read_to_string(&mut target)?;
+            Kind::Symlink(PathBuf::from(target))
+        } else if entry.is_dir() {
+            Kind::Dir
+        } else {
+            Kind::File
+        };
+        let mtime = entry
+            .last_modified()
+            .and_then(|t| chrono::NaiveDateTime::try_from(t).ok())
+            .and_then(|t| Local.from_local_datetime(&t).earliest())
+            .map(SystemTime::from);
+        let default_mode = if matches!(kind, Kind::Dir) { 0o755 } else { 0o644 };
+        let meta = Entry {
+            path: PathBuf::from(entry.name()),
+            kind,
+            mode: entry.unix_mode().map_or(default_mode, |m| m & 0o7777),
+            mtime,
+        };
+        f(meta, &mut entry)?;
+    }
+    Ok(())
+}
+
+/// Normalize an entry path, rejecting anything that is absolute or climbs
+/// out of the extraction directory.
+fn safe_path(path: &Path) -> Result<PathBuf> {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::Normal(n) => out.push(n),
+            Component::CurDir => {}
+            _ => return Err(anyhow!("Unsafe path in archive: {}", path.display())),
+        }
+    }
+    if out.as_os_str().is_empty() {
+        return Err(anyhow!("Empty path in archive"));
+    }
+    Ok(out)
+}
+
+/// Reject symlinks whose target, resolved from the link's directory, is
+/// absolute, leaves the extraction directory, or passes through another
+/// symlink, from anywhere in the archive or already in `dest` (which
+/// lexical resolution cannot follow).
+fn check_symlink(dest: &Path, link: &Path, target: &Path, links: &HashSet<PathBuf>) -> Result<()> {
+    let escape = || anyhow!("Symlink escapes extraction directory: {} -> {}", link.display(), target.display());
+    if target.is_absolute() {
+        return Err(escape());
+    }
+    let mut resolved = link.parent().map(Path::to_path_buf).unwrap_or_default();
+    for c in target.components() {
+        match c {
+            Component::Normal(n) => {
+                resolved.push(n);
+                let existing = fs::symlink_metadata(dest.join(&resolved)).is_ok_and(|m| m.file_type().is_symlink());
+                if existing || links.contains(&resolved) {
+                    return Err(escape());
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return Err(escape());
+                }
+            }
+            _ => return Err(escape()),
+        }
+    }
+    Ok(())
+}
+
+/// Reject entries that would be written through a symlink, whether it comes
+/// from the archive or already exists in the destination.
+fn check_ancestors(dest: &Path, path: &Path, links: &HashSet<PathBuf>) -> Result<()> {
+    for ancestor in path.ancestors().skip(1).filter(|a| !a.as_os_str().is_empty()) {
+        let existing = fs::symlink_metadata(dest.join(ancestor)).is_ok_and(|m| m.file_type().is_symlink());
+        if existing || links.contains(ancestor) {
+            return Err(anyhow!("Entry would be written through a symlink: {}", path.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Extract an archive into `dest`, restoring permissions and modification
+/// times. Every entry is checked before anything is written: path traversal,
+/// escaping symlinks or an entry that would overwrite an existing file make
+/// the whole extraction fail.
+pub fn unpack(archive: &Path, dest: &Path, format: Option<ArchiveFormat>) -> Result<String> {
+    let format = ArchiveFormat::resolve(archive, format)?;
+
+    // Links are checked against every symlink in the archive, not just the
+    // ones before them, since a later link can change what an earlier
+    // target resolves to.
+    let mut links = HashSet::new();
+    for_each_entry(archive, format, |entry, _| {
+        if matches!(entry.kind, Kind::Symlink(_)) {
+            links.insert(safe_path(&entry.path)?);
+        }
+        Ok(())
+    })?;
+
+    let mut seen = HashSet::new();
+    for_each_entry(archive, format, |entry, _| {
+        let path = safe_path(&entry.path)?;
+        check_ancestors(dest, &path, &links)?;
+        match &entry.kind {
+            Kind::Symlink(target) => check_symlink(dest, &path, target, &links)?,
+            Kind::Hardlink(target) => {
+                if !seen.contains(&safe_path(target)?) {
+                    return Err(anyhow!("Hard link to unknown entry: {}", target.display()));
+                }
+            }
+            Kind::Dir | Kind::File => {}
+        }
+        match fs::symlink_metadata(dest.join(&path)) {
+            Ok(m) if m.is_dir() && matches!(entry.kind, Kind::Dir) => {}
+            Ok(_) => return Err(anyhow!("Destination file exists: {}", dest.join(&path).display())),
+            Err(_) => {}
+        }
+        if !seen.insert(path.clone()) && !matches!(entry.kind, Kind::Dir) {
+            return Err(anyhow!("Duplicate entry in archive: {}", path.display()));
+        }
+        Ok(())
+    })?;
+
+    fs::create_dir_all(dest)?;
+    let mut count = 0;
+    let mut dirs = Vec::new();
+    for_each_entry(archive, format, |entry, reader| {
+        let path = dest.join(safe_path(&entry.path)?);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match &entry.kind {
+            Kind::Dir => {
+                fs::create_dir_all(&path)?;
+                // Applied last so read-only directories can still be filled.
+                dirs.push((path, entry.mode, entry.mtime));
+            }
+            Kind::File => {
+                let mut out = File::create_new(&path)?;
+                io::copy(reader, &mut out)?;
+                out.set_permissions(Permissions::from_mode(entry.mode))?;
+                drop(out);
+                touch_file(&path, entry.mtime, entry.mtime, true)?;
+            }
+            Kind::Symlink(target) => symlink(target, &path)?,
+            Kind::Hardlink(target) => fs::hard_link(dest.join(safe_path(target)?), &path)?,
+        }
+        count += 1;
+        Ok(())
+    })?;
+
+    for (path, mode, mtime) in dirs.iter().rev() {
+        fs::set_permissions(path, Permissions::from_mode(*mode))?;
+        touch_file(path, *mtime, *mtime, true)?;
+    }
+
+    let msg = format!(
+        "Unpacked {} entries successfully: {}",
+        count,
+        dest.to_str().unwrap_or_default()
+    );
+
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::fixture::ChildPath;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    fn setup_tree(root: &Path) {
+        fs::create_dir_all(root.join("project/bin")).unwrap();
+        fs::write(root.join("project/readme.txt"), "read me").unwrap();
+        fs::write(root.join("project/bin/run.sh"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(root.join("project/bin/run.sh"), Permissions::from_mode(0o750)).unwrap();
+        symlink("../readme.txt", root.join("project/bin/readme")).unwrap();
+        let old = Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        touch_file(&root.join("project/readme.txt"), old, old, true).unwrap();
+    }
+
+    #[test]
+    fn test_pack_unpack_round_trip() {
+        for format in [ArchiveFormat::Tar, ArchiveFormat::TarGz, ArchiveFormat::TarZst, ArchiveFormat::Zip] {
+            let binding = TempDir::new().unwrap();
+            let test_dir = binding.to_path_buf();
+            defer!(binding.close().unwrap());
+            setup_tree(&test_dir);
+
+            let archive = test_dir.join("out.archive");
+            let msg = format!("format: {:?}", format);
+            pack(&archive, &[test_dir.join("project")], Some(format)).expect(&msg);
+            let err = pack(&archive, &[test_dir.join("project")], Some(format)).unwrap_err();
+            assert!(err.to_string().contains("exists"), "{}", msg);
+
+            let dest = test_dir.join("extracted");
+            unpack(&archive, &dest, Some(format)).expect(&msg);
+            ChildPath::new(dest.join("project/readme.txt")).assert("read me");
+            ChildPath::new(dest.join("project/bin/readme")).assert("read me");
+            let meta = fs::metadata(dest.join("project/bin/run.sh")).unwrap();
+            assert_eq!(meta.mode() & 0o777, 0o750, "{}", msg);
+            let meta = fs::metadata(dest.join("project/readme.txt")).unwrap();
+            assert_eq!(meta.mtime(), 1_600_000_000, "{}", msg);
+
+            // a second extraction would overwrite files
+            let err = unpack(&archive, &dest, Some(format)).unwrap_err();
+            assert!(err.to_string().contains("Destination file exists"), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn test_unpack_rejects_unsafe_entries() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            /// Entry names, with a target for symlinks, in archive order.
+            entries: &'a [(&'a str, Option<&'a str>)],
+            result: Result<()>,
+        }
+
+        // tar::Builder refuses to write unsafe names, so craft raw headers.
+        fn write_raw_tar(archive: &Path, entries: &[(&str, Option<&str>)]) {
+            let mut builder = tar::Builder::new(File::create(archive).unwrap());
+            for (name, link) in entries {
+                let mut header = tar::Header::new_gnu();
+                header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+                header.set_mode(0o644);
+                match link {
+                    Some(target) => {
+                        header.set_entry_type(tar::EntryType::Symlink);
+                        header.set_size(0);
+                        header.as_gnu_mut().unwrap().linkname[..target.len()].copy_from_slice(target.as_bytes());
+                        header.set_cksum();
+                        builder.append(&header, io::empty()).unwrap();
+                    }
+                    None => {
+                        header.set_size(4);
+                        header.set_cksum();
+                        builder.append(&header, &b"evil"[..]).unwrap();
+                    }
+                }
+            }
+            builder.into_inner().unwrap();
+        }
+
+        let tests = &[
+            TestData {
+                entries: &[("../evil.txt", None)],
+                result: Err(anyhow!("Unsafe path")),
+            },
+            TestData {
+                entries: &[("/abs/evil.txt", None)],
+                result: Err(anyhow!("Unsafe path")),
+            },
+            TestData {
+                entries: &[("dir/link", Some("../../etc/passwd"))],
+                result: Err(anyhow!("Symlink escapes")),
+            },
+            TestData {
+                entries: &[("link", Some("/etc/passwd"))],
+                result: Err(anyhow!("Symlink escapes")),
+            },
+            // a later link changes what an earlier target resolves to
+            TestData {
+                entries: &[("sub/s", Some("../L/../outside_secret")), ("L", Some("."))],
+                result: Err(anyhow!("Symlink escapes")),
+            },
+            TestData {
+                entries: &[("dir/link", Some("../ok.txt"))],
+                result: Ok(()),
+            },
+            TestData {
+                entries: &[("dir/link", Some("."))],
+                result: Ok(()),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let binding = TempDir::new().unwrap();
+            let test_dir = binding.to_path_buf();
+            defer!(binding.close().unwrap());
+
+            let archive = test_dir.join("bad.tar");
+            write_raw_tar(&archive, d.entries);
+
+            let dest = test_dir.join("dest");
+            let actual = unpack(&archive, &dest, None);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+            match &d.result {
+                Ok(()) => assert!(actual.is_ok(), "{}", msg),
+                Err(e) => {
+                    assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg);
+                    assert!(!dest.exists(), "{}", msg);
+                }
+            }
+        }
+
+        // symlinks already in the destination count too
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let archive = test_dir.join("bad.tar");
+        write_raw_tar(&archive, &[("sub/s", Some("../L/../outside_secret"))]);
+        let dest = test_dir.join("dest");
+        fs::create_dir(&dest).unwrap();
+        symlink(".", dest.join("L")).unwrap();
+        let err = unpack(&archive, &dest, None).unwrap_err();
+        assert!(err.to_string().contains("Symlink escapes"), "{}", err);
+        assert!(!dest.join("sub").exists());
+    }
+
+    #[test]
+    fn test_archive_format_from_path() {
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.TGZ")), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.tar.zst")), Some(ArchiveFormat::TarZst));
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.tar")), Some(ArchiveFormat::Tar));
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.zip")), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.rar")), None);
+    }
+}
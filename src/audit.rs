@@ -0,0 +1,414 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::info::{group_name, user_name};
+use crate::output::{paint, to_json, Color, OutputFormat};
+use crate::walk::{walk_tree, Filter};
+
+/// How serious a finding is. Ordered from least to most severe.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Low,
+    Medium,
+    High,
+}
+
+/// Options for `audit_paths`.
+#[derive(Debug, Default)]
+pub struct AuditOptions {
+    /// The least severe finding that makes the audit fail.
+    pub fail_on: Severity,
+    pub color: bool,
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct Finding {
+    path: String,
+    severity: Severity,
+    /// Short name of the check, e.g. `world-writable`.
+    check: &'static str,
+    detail: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Counts {
+    high: usize,
+    medium: usize,
+    low: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    findings: Vec<Finding>,
+    counts: Counts,
+    scanned: usize,
+    failed: bool,
+}
+
+/// File names that usually hold credentials. A `*` stands for any suffix.
+const SECRET_NAMES: &[&str] = &[
+    "id_rsa",
+    "id_dsa",
+    "id_ecdsa",
+    "id_ed25519",
+    ".env",
+    ".env.*",
+    ".netrc",
+    ".pgpass",
+    ".htpasswd",
+    ".git-credentials",
+    "credentials",
+    "credentials.json",
+    "*.pem",
+    "*.key",
+    "*.p12",
+    "*.pfx",
+    "*.keystore",
+];
+
+/// Templates that are meant to be committed, not secrets themselves.
+const SECRET_EXAMPLES: &[&str] = &[".example", ".sample", ".template", ".dist"];
+
+impl Report {
+    fn add(&mut self, path: &Path, severity: Severity, check: &'static str, detail: String, fail_on: Severity) {
+        match severity {
+            Severity::High => self.counts.high += 1,
+            Severity::Medium => self.counts.medium += 1,
+            Severity::Low => self.counts.low += 1,
+        }
+        self.failed |= severity >= fail_on;
+        self.findings.push(Finding {
+            path: path.to_str().unwrap_or_default().to_owned(),
+            severity,
+            check,
+            detail,
+        });
+    }
+}
+
+/// Check each of `paths`, and every directory and file below it that
+/// `filter` allows, for risky permissions and ownership and for names that
+/// suggest secrets. Entries that cannot be read are reported as
+/// `unreadable` findings rather than ending the scan. Returns the rendered
+/// report and whether any finding reached `opts.fail_on`.
+pub fn audit_paths(paths: &[PathBuf], filter: &Filter, opts: &AuditOptions) -> Result<(String, bool)> {
+    let mut report = Report {
+        findings: Vec::new(),
+        counts: Counts::default(),
+        scanned: 0,
+        failed: false,
+    };
+    let mut entries = Vec::new();
+    for root in paths {
+        let meta = fs::metadata(root).map_err(|e| anyhow!("{}: {}", root.display(), e))?;
+        if !meta.is_dir() {
+            if filter.allows_file(root) {
+                entries.push(root.clone());
+            }
+            continue;
+        }
+        for entry in walk_tree(root, filter, false) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let path = e.path().unwrap_or(root).to_owned();
+                    let detail = e.io_error().map_or_else(|| e.to_string(), |io| io.to_string());
+                    report.add(&path, Severity::Low, "unreadable", detail, opts.fail_on);
+                    continue;
+                }
+            };
+            let kind = entry.file_type();
+            if entry.depth() == 0 || kind.is_dir() || (kind.is_file() && filter.allows_file(entry.path())) {
+                entries.push(entry.into_path());
+            }
+        }
+    }
+
+    for path in &entries {
+        report.scanned += 1;
+        match fs::symlink_metadata(path) {
+            Ok(meta) => {
+                for (severity, check, detail) in check_entry(path, &meta) {
+                    report.add(path, severity, check, detail, opts.fail_on);
+                }
+            }
+            Err(e) => report.add(path, Severity::Low, "unreadable", e.to_string(), opts.fail_on),
+        }
+    }
+
+    let failed = report.failed;
+    let out = match opts.format {
+        OutputFormat::Json => to_json(&report)?,
+        OutputFormat::Text => render_text(&report, opts.color),
+    };
+    Ok((out, failed))
+}
+
+fn check_entry(path: &Path, meta: &fs::Metadata) -> Vec<(Severity, &'static str, String)> {
+    let mut found = Vec::new();
+    // A symlink's own mode means nothing; its target is checked on its own.
+    if meta.file_type().is_symlink() {
+        return found;
+    }
+    let mode = meta.mode() & 0o7777;
+    let octal = format!("mode {:04o}", mode);
+    let sticky = mode & 0o1000 != 0;
+
+    if meta.is_dir() {
+        if mode & 0o002 != 0 && !sticky {
+            found.push((Severity::High, "world-writable", format!("directory without sticky bit, {}", octal)));
+        } else if mode & 0o020 != 0 && !sticky {
+            found.push((Severity::Medium, "group-writable", format!("directory without sticky bit, {}", octal)));
+        }
+    } else {
+        if mode & 0o002 != 0 {
+            found.push((Severity::High, "world-writable", format!("file, {}", octal)));
+        }
+        if meta.is_file() && mode & 0o4000 != 0 {
+            let owner = user_name(meta.uid()).unwrap_or_else(|| meta.uid().to_string());
+            found.push((Severity::High, "setuid", format!("runs as {}, {}", owner, octal)));
+        }
+        if meta.is_file() && mode & 0o2000 != 0 {
+            let group = group_name(meta.gid()).unwrap_or_else(|| meta.gid().to_string());
+            found.push((Severity::Medium, "setgid", format!("runs as group {}, {}", group, octal)));
+        }
+        if meta.is_file() && is_secret_name(path) {
+            if mode & 0o044 != 0 {
+                found.push((Severity::High, "secret", format!("readable by others, {}", octal)));
+            } else {
+                found.push((Severity::Low, "secret", "name suggests credentials".to_owned()));
+            }
+        }
+    }
+
+    if user_name(meta.uid()).is_none() {
+        found.push((Severity::Medium, "unknown-owner", format!("uid {} has no user", meta.uid())));
+    }
+    if group_name(meta.gid()).is_none() {
+        found.push((Severity::Low, "unknown-group", format!("gid {} has no group", meta.gid())));
+    }
+    found
+}
+
+fn is_secret_name(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    if SECRET_EXAMPLES.iter().any(|s| name.ends_with(s)) {
+        return false;
+    }
+    SECRET_NAMES.iter().any(|pattern| match pattern.split_once('*') {
+        Some((prefix, suffix)) => name.len() > prefix.len() + suffix.len() && name.starts_with(prefix) && name.ends_with(suffix),
+        None => name == *pattern,
+    })
+}
+
+fn render_text(report: &Report, color: bool) -> String {
+    let mut lines: Vec<String> = report
+        .findings
+        .iter()
+        .map(|f| {
+            let (label, c) = match f.severity {
+                Severity::High => ("HIGH  ", Color::Red),
+                Severity::Medium => ("MEDIUM", Color::Yellow),
+                Severity::Low => ("LOW   ", Color::Cyan),
+            };
+            format!("{} {}: {} ({})", paint(label, c, color), f.path, f.check, f.detail)
+        })
+        .collect();
+    let c = &report.counts;
+    lines.push(format!(
+        "Audited {} paths: {} high, {} medium, {} low",
+        report.scanned, c.high, c.medium, c.low
+    ));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::os::unix::fs::{chown, PermissionsExt};
+
+    #[test]
+    fn test_is_secret_name() {
+        let tests = &[
+            ("id_rsa", true),
+            ("id_rsa.pub", false),
+            (".env", true),
+            (".env.production", true),
+            (".env.example", false),
+            ("server.pem", true),
+            (".pem", false),
+            ("keys.txt", false),
+            ("environment", false),
+        ];
+
+        for (i, (name, expected)) in tests.iter().enumerate() {
+            assert_eq!(is_secret_name(Path::new(name)), *expected, "test[{}]: {}", i, name);
+        }
+    }
+
+    #[test]
+    fn test_audit_paths() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            file: &'a str,
+            mode: u32,
+            fail_on: Severity,
+            result: Result<(&'a str, bool)>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let tests = &[
+            // failures
+            TestData {
+                file: "missing",
+                mode: 0,
+                fail_on: Severity::Low,
+                result: Err(anyhow!("No such file")),
+            },
+            // successes
+            TestData {
+                file: "plain.txt",
+                mode: 0o644,
+                fail_on: Severity::Low,
+                result: Ok(("Audited 1 paths: 0 high, 0 medium, 0 low", false)),
+            },
+            TestData {
+                file: "shared.txt",
+                mode: 0o666,
+                fail_on: Severity::High,
+                result: Ok(("HIGH   shared.txt: world-writable (file, mode 0666)", true)),
+            },
+            TestData {
+                file: "tool",
+                mode: 0o4755,
+                fail_on: Severity::High,
+                result: Ok(("tool: setuid (runs as", true)),
+            },
+            TestData {
+                file: "mailer",
+                mode: 0o2755,
+                fail_on: Severity::High,
+                result: Ok(("MEDIUM mailer: setgid (runs as group", false)),
+            },
+            TestData {
+                file: "id_ed25519",
+                mode: 0o600,
+                fail_on: Severity::Medium,
+                result: Ok(("LOW    id_ed25519: secret (name suggests credentials)", false)),
+            },
+            TestData {
+                file: ".env",
+                mode: 0o644,
+                fail_on: Severity::Medium,
+                result: Ok((".env: secret (readable by others, mode 0644)", true)),
+            },
+            TestData {
+                file: "uploads/",
+                mode: 0o775,
+                fail_on: Severity::Medium,
+                result: Ok(("uploads: group-writable (directory without sticky bit, mode 0775)", true)),
+            },
+            TestData {
+                file: "tmp/",
+                mode: 0o1777,
+                fail_on: Severity::Low,
+                result: Ok(("0 high, 0 medium, 0 low", false)),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let path = test_dir.join(d.file.trim_end_matches('/'));
+            if d.file.ends_with('/') {
+                fs::create_dir(&path).unwrap();
+            } else if d.mode != 0 {
+                fs::write(&path, "x").unwrap();
+            }
+            if d.mode != 0 {
+                fs::set_permissions(&path, fs::Permissions::from_mode(d.mode)).unwrap();
+            }
+            let opts = AuditOptions {
+                fail_on: d.fail_on,
+                ..Default::default()
+            };
+            let actual = audit_paths(std::slice::from_ref(&path), &Filter::default(), &opts)
+                .map(|(out, failed)| (out.replace(&format!("{}/", test_dir.display()), ""), failed));
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok((expected, failed)) => {
+                    let (out, actual_failed) = actual.unwrap();
+                    assert!(out.contains(expected), "{}", msg);
+                    assert_eq!(actual_failed, *failed, "{}", msg);
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_audit_unknown_owner() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let file = test_dir.join("orphan");
+        fs::write(&file, "x").unwrap();
+        // Only root can give files away.
+        if chown(&file, Some(54321), Some(54321)).is_err() {
+            return;
+        }
+        let opts = AuditOptions {
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+        let (out, failed) = audit_paths(&[file], &Filter::default(), &opts).unwrap();
+        assert!(out.contains("\"check\": \"unknown-owner\""), "{}", out);
+        assert!(out.contains("\"detail\": \"gid 54321 has no group\""), "{}", out);
+        assert!(failed);
+    }
+
+    #[test]
+    fn test_audit_unreadable_dir() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let locked = test_dir.join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(locked.join("hidden"), "").unwrap();
+        fs::write(test_dir.join("shared.txt"), "").unwrap();
+        fs::set_permissions(test_dir.join("shared.txt"), fs::Permissions::from_mode(0o666)).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        defer!(fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap());
+        if fs::read_dir(&locked).is_ok() {
+            // Running as root, which can read anything.
+            return;
+        }
+
+        let opts = AuditOptions {
+            fail_on: Severity::High,
+            ..Default::default()
+        };
+        let (out, failed) = audit_paths(std::slice::from_ref(&test_dir), &Filter::default(), &opts).unwrap();
+        let out = out.replace(&format!("{}/", test_dir.display()), "");
+        assert!(out.contains("LOW    locked: unreadable (Permission denied"), "{}", out);
+        assert!(out.contains("HIGH   shared.txt: world-writable"), "{}", out);
+        assert!(failed);
+    }
+}
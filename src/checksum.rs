@@ -0,0 +1,86 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+/// Format a digest as lowercase hex.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SHA-256 of everything `reader` yields, as lowercase hex.
+pub fn sha256_reader<R: Read>(reader: &mut R) -> Result<String> {
+    let mut hasher = HashingWriter::new(io::sink());
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher.hex_digest())
+}
+
+/// SHA-256 of a file's contents, as lowercase hex.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    sha256_reader(&mut File::open(path)?)
+}
+
+/// A writer that hashes and counts everything passing through it.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hasher: Sha256::new(),
+            written: 0,
+        }
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn hex_digest(self) -> String {
+        to_hex(&self.hasher.finalize())
+    }
+
+    pub fn into_parts(self) -> (W, u64, String) {
+        (self.inner, self.written, to_hex(&self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256() {
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(sha256_reader(&mut io::empty()).unwrap(), empty);
+
+        let mut w = HashingWriter::new(Vec::new());
+        w.write_all(b"abc").unwrap();
+        let (buf, n, digest) = w.into_parts();
+        assert_eq!((buf.as_slice(), n), (&b"abc"[..], 3));
+        assert_eq!(
+            digest,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -1,17 +1,122 @@
 use anyhow::{anyhow, Ok, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::{copy, remove_file, File},
-    io::Write,
+    fs::{copy, create_dir_all, remove_file, File, Permissions},
+    io::{self, Read, Write},
+    os::unix::fs::PermissionsExt,
+    os::unix::io::AsRawFd,
     path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
 };
 
+use crate::compress::{open_decoded, Compression, Encoder};
+use crate::crypt::{encryptor, EncryptKey};
+use crate::touch::touch_file;
+
+/// Where the initial contents of a newly created file come from.
+#[derive(Debug, Clone)]
+pub enum Source<'a> {
+    Empty,
+    Text(&'a str),
+    Stdin,
+    File(&'a Path),
+}
+
+impl Source<'_> {
+    /// Read the whole source into memory.
+    pub fn read_all(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        match self {
+            Source::Empty => {}
+            Source::Text(t) => buf.extend_from_slice(t.as_bytes()),
+            Source::Stdin => {
+                io::copy(&mut io::stdin().lock(), &mut buf)?;
+            }
+            Source::File(src) => buf = std::fs::read(src)?,
+        }
+        Ok(buf)
+    }
+}
+
+/// What to do when the destination of a write already exists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    #[default]
+    Fail,
+    Overwrite,
+    Skip,
+}
+
+/// Options controlling how `create_file_from` lays out a new file.
+#[derive(Debug, Default)]
+pub struct CreateOptions {
+    pub conflict: ConflictPolicy,
+    pub parents: bool,
+    pub mode: Option<u32>,
+    pub size: Option<u64>,
+    pub sparse: bool,
+    /// Counters to report a copied source into, and a way to stop.
+    pub progress: Option<Arc<Progress>>,
+}
+
 pub fn create_file(file_path: &Path, text: Option<&str>) -> Result<String> {
-    let mut file = File::create_new(file_path)?;
+    let source = text.map_or(Source::Empty, Source::Text);
+    create_file_from(file_path, source, &CreateOptions::default())
+}
+
+pub fn create_file_from(file_path: &Path, source: Source, opts: &CreateOptions) -> Result<String> {
+    // Open the source first so that a missing one leaves nothing behind.
+    let mut input = match source {
+        Source::File(src) => Some(File::open(src)?),
+        _ => None,
+    };
+    if file_path.symlink_metadata().is_ok() {
+        match opts.conflict {
+            ConflictPolicy::Fail => return Err(io::Error::from_raw_os_error(libc::EEXIST).into()),
+            ConflictPolicy::Overwrite => {}
+            ConflictPolicy::Skip => {
+                return Ok(format!(
+                    "Skipped existing file: {}",
+                    file_path.to_str().unwrap_or_default()
+                ))
+            }
+        }
+    }
 
-    if let Some(t) = text {
-        file.write_all(t.as_bytes())?;
+    if opts.parents {
+        if let Some(parent) = file_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            create_dir_all(parent)?;
+        }
     }
 
+    // The file is filled under a temporary name, so it only appears at
+    // `file_path` complete and with its final mode.
+    let overwrite = opts.conflict == ConflictPolicy::Overwrite;
+    let idle = Progress::default();
+    let progress = opts.progress.as_deref().unwrap_or(&idle);
+    write_atomic_with(file_path, overwrite, |file| {
+        if let Some(mode) = opts.mode {
+            file.set_permissions(Permissions::from_mode(mode))?;
+        }
+        match (source, &mut input) {
+            (Source::Text(t), _) => file.write_all(t.as_bytes())?,
+            (Source::Stdin, _) => {
+                io::copy(&mut io::stdin().lock(), file)?;
+            }
+            (_, Some(input)) => {
+                progress.total.store(input.metadata()?.len(), Ordering::Relaxed);
+                io::copy(&mut Tracked { inner: input, progress }, file)?;
+            }
+            _ => {}
+        }
+        fill_to_size(file, opts)
+    })?;
+
     let msg = format!(
         "Created file successfully: {}",
         file_path.to_str().unwrap_or_default()
@@ -20,12 +125,189 @@ pub fn create_file(file_path: &Path, text: Option<&str>) -> Result<String> {
     Ok(msg)
 }
 
+fn fill_to_size(file: &File, opts: &CreateOptions) -> Result<()> {
+    if let Some(size) = opts.size {
+        if opts.sparse {
+            file.set_len(size)?;
+        } else {
+            preallocate(file, size)?;
+        }
+    }
+    Ok(())
+}
+
+fn preallocate(file: &File, size: u64) -> Result<()> {
+    if size == 0 {
+        return Ok(());
+    }
+    let len = libc::off_t::try_from(size).map_err(|_| anyhow!("Size too large: {}", size))?;
+    // SAFETY: the descriptor is owned by `file` and stays open for the call.
+    let rc = unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Parse a size such as `512`, `10K`, `10M` or `1GiB` into bytes.
+/// Suffixes are binary multiples.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: u64 = num.parse().map_err(|_| anyhow!("Invalid size: {}", s))?;
+    let shift = match unit.to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(anyhow!("Invalid size unit: {}", unit)),
+    };
+    num.checked_mul(1 << shift)
+        .ok_or_else(|| anyhow!("Size too large: {}", s))
+}
+
+/// Format a byte count the way `ls -h` does: `512`, `1.5K`, `12M`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+/// Parse an octal permission mode such as `0640` or `755`.
+pub fn parse_mode(s: &str) -> Result<u32> {
+    let mode = u32::from_str_radix(s.trim_start_matches("0o"), 8)
+        .map_err(|_| anyhow!("Invalid octal mode: {}", s))?;
+    if mode > 0o7777 {
+        return Err(anyhow!("Invalid octal mode: {}", s));
+    }
+    Ok(mode)
+}
+
+/// Replace the contents of `path` atomically: the data is written and synced
+/// to a temporary file in the same directory, which is then renamed over the
+/// destination. An existing destination keeps its permissions; without
+/// `overwrite` the rename fails if the destination appeared in the meantime.
+pub fn write_atomic(path: &Path, contents: &[u8], overwrite: bool) -> Result<()> {
+    write_atomic_with(path, overwrite, |file| Ok(file.write_all(contents)?))
+}
+
+/// Like `write_atomic`, but `fill` streams the contents into the temporary
+/// file. If `fill` fails the temporary file is removed and `path` is untouched.
+/// Permissions `fill` sets on the file win over those kept from `path`.
+pub fn write_atomic_with(path: &Path, overwrite: bool, fill: impl FnOnce(&mut File) -> Result<()>) -> Result<()> {
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    // Like a regular create, a new file gets 0666 filtered through the umask.
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".filey-")
+        .suffix(".tmp")
+        .permissions(Permissions::from_mode(0o666))
+        .tempfile_in(dir)?;
+    if let Result::Ok(meta) = std::fs::metadata(path) {
+        tmp.as_file().set_permissions(meta.permissions())?;
+    }
+    fill(tmp.as_file_mut())?;
+    tmp.as_file().sync_all()?;
+
+    if overwrite {
+        tmp.persist(path).map_err(|e| e.error)?;
+    } else {
+        tmp.persist_noclobber(path).map_err(|e| e.error)?;
+    }
+    Ok(())
+}
+
+/// Options for `copy_file_with` and `cat_files_with`.
+#[derive(Debug, Default)]
+pub struct TransferOptions {
+    /// Decode compressed sources, detected by their contents.
+    pub decompress: bool,
+    /// Compress the destination.
+    pub compress: Option<Compression>,
+    /// Encrypt the destination to these age recipients, after compressing.
+    pub encrypt_to: Vec<String>,
+    /// Copy a symlink source as a link to the same target instead of
+    /// copying the file it points to.
+    pub no_dereference: bool,
+    /// Counters to report into while streaming, and a way to stop.
+    pub progress: Option<Arc<Progress>>,
+}
+
+/// How far a transfer has got, shared with whoever watches it.
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// Source bytes read so far.
+    pub bytes: AtomicU64,
+    /// Total size of the sources, once known.
+    pub total: AtomicU64,
+    /// Set to make the transfer fail at its next read.
+    pub cancelled: AtomicBool,
+}
+
+/// A reader that counts what passes through it into a `Progress`, and fails
+/// once the transfer is cancelled.
+struct Tracked<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<R: Read> Read for Tracked<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.progress.cancelled.load(Ordering::Relaxed) {
+            return Err(io::Error::other("Cancelled"));
+        }
+        let n = self.inner.read(buf)?;
+        self.progress.bytes.fetch_add(n as u64, Ordering::Relaxed);
+        io::Result::Ok(n)
+    }
+}
+
 pub fn copy_file(source: &Path, dst: &Path) -> Result<String> {
+    copy_file_with(source, dst, &TransferOptions::default())
+}
+
+pub fn copy_file_with(source: &Path, dst: &Path, opts: &TransferOptions) -> Result<String> {
     if dst.exists() {
         return Err(anyhow!("Destination file exists"));
     }
 
-    copy(source, dst)?;
+    let transform = opts.decompress || opts.compress.is_some() || !opts.encrypt_to.is_empty();
+    // A plain copy has no reads to count, so tracked copies are streamed.
+    let stream = transform || opts.progress.is_some();
+    if opts.no_dereference && std::fs::symlink_metadata(source)?.file_type().is_symlink() {
+        if transform {
+            return Err(anyhow!("A symlink copied as a link cannot be compressed or encrypted"));
+        }
+        std::os::unix::fs::symlink(std::fs::read_link(source)?, dst)?;
+    } else if stream {
+        let meta = std::fs::metadata(source)?;
+        if !meta.is_file() {
+            return Err(anyhow!("the source path is neither a regular file nor a symlink to a regular file"));
+        }
+        stream_into(&[source], dst, opts)?;
+        if !transform {
+            // Keep the permissions a plain copy would have.
+            std::fs::set_permissions(dst, meta.permissions())?;
+        }
+    } else {
+        copy(source, dst)?;
+    }
 
     let msg = format!(
         "Copied file successfully: {} {}",
@@ -36,13 +318,34 @@ pub fn copy_file(source: &Path, dst: &Path) -> Result<String> {
     Ok(msg)
 }
 
+/// Copy `source` to `dst` through a temporary file, replacing `dst` if
+/// `overwrite` is set, then give the copy the source's permissions and
+/// access and modification times.
+pub fn copy_preserving(source: &Path, dst: &Path, overwrite: bool) -> Result<()> {
+    let meta = std::fs::metadata(source)?;
+    let mut input = File::open(source)?;
+    write_atomic_with(dst, overwrite, |file| {
+        io::copy(&mut input, file)?;
+        Ok(())
+    })?;
+    std::fs::set_permissions(dst, meta.permissions())?;
+    touch_file(dst, Some(meta.accessed()?), Some(meta.modified()?), true)?;
+    Ok(())
+}
+
 pub fn cat_files(file1: &Path, file2: &Path, dst: &Path) -> Result<String> {
-    let mut buf1 = std::fs::read(file1)?;
-    let mut buf2 = std::fs::read(file2)?;
-    let mut file = File::create_new(dst).map_err(|_| anyhow!("Destination file exists"))?;
-    buf1.append(&mut buf2);
-    file.write_all(&buf1)?;
-    
+    cat_files_with(file1, file2, dst, &TransferOptions::default())
+}
+
+pub fn cat_files_with(file1: &Path, file2: &Path, dst: &Path, opts: &TransferOptions) -> Result<String> {
+    // Surface missing sources before complaining about the destination.
+    File::open(file1)?;
+    File::open(file2)?;
+    if dst.exists() {
+        return Err(anyhow!("Destination file exists"));
+    }
+    stream_into(&[file1, file2], dst, opts)?;
+
     let msg = format!(
         "Concatenated files successfully: {} {} {}",
         file1.to_str().unwrap_or_default(),
@@ -53,6 +356,48 @@ pub fn cat_files(file1: &Path, file2: &Path, dst: &Path) -> Result<String> {
     Ok(msg)
 }
 
+/// Stream `sources` one after another into a new file at `dst`.
+fn stream_into(sources: &[&Path], dst: &Path, opts: &TransferOptions) -> Result<()> {
+    let encryptor = match opts.encrypt_to.as_slice() {
+        [] => None,
+        recipients => Some(encryptor(&EncryptKey::Recipients(recipients.to_vec()))?),
+    };
+    let idle = Progress::default();
+    let progress = opts.progress.as_deref().unwrap_or(&idle);
+    if !opts.decompress {
+        let total = sources.iter().map(|s| Ok(std::fs::metadata(s)?.len())).sum::<Result<u64>>()?;
+        progress.total.store(total, Ordering::Relaxed);
+    }
+    let copy_sources = |out: &mut dyn Write| -> Result<()> {
+        for src in sources {
+            let inner: Box<dyn io::Read> = if opts.decompress {
+                open_decoded(src)?
+            } else {
+                Box::new(File::open(src)?)
+            };
+            io::copy(&mut Tracked { inner, progress }, out)?;
+        }
+        Ok(())
+    };
+
+    write_atomic_with(dst, false, |file| {
+        let buffered = io::BufWriter::new(file);
+        match encryptor {
+            Some(e) => {
+                let mut out = Encoder::new(e.wrap_output(buffered)?, opts.compress)?;
+                copy_sources(&mut out)?;
+                out.finish()?.finish()?.flush()?;
+            }
+            None => {
+                let mut out = Encoder::new(buffered, opts.compress)?;
+                copy_sources(&mut out)?;
+                out.finish()?.flush()?;
+            }
+        }
+        Ok(())
+    })
+}
+
 pub fn delete_file(filename: &Path) -> Result<String> {
     remove_file(filename)?;
 
@@ -140,6 +485,162 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_create_file_from() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            path: &'a Path,
+            source: Source<'a>,
+            opts: CreateOptions,
+            contents: &'a str,
+            len: u64,
+            result: Result<()>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        // setup
+        let src = test_dir.join("src.txt");
+        let contents = "Nam lacinia pulvinar tortor nec facilisis.";
+        std::fs::write(&src, contents).unwrap();
+
+        let tests = &[
+            // failures
+            TestData {
+                path: &test_dir.join("missing/dst.txt"),
+                source: Source::Empty,
+                opts: CreateOptions::default(),
+                contents: "",
+                len: 0,
+                result: Err(anyhow!("No such file")),
+            },
+            TestData {
+                path: &test_dir.join("dst.txt"),
+                source: Source::File(&test_dir.join("nonexistent.txt")),
+                opts: CreateOptions::default(),
+                contents: "",
+                len: 0,
+                result: Err(anyhow!("No such file")),
+            },
+            // successes
+            TestData {
+                path: &test_dir.join("a/b/parents.txt"),
+                source: Source::File(&src),
+                opts: CreateOptions {
+                    parents: true,
+                    ..Default::default()
+                },
+                contents,
+                len: contents.len() as u64,
+                result: Ok(()),
+            },
+            TestData {
+                path: &test_dir.join("mode.txt"),
+                source: Source::Text(contents),
+                opts: CreateOptions {
+                    mode: Some(0o640),
+                    ..Default::default()
+                },
+                contents,
+                len: contents.len() as u64,
+                result: Ok(()),
+            },
+            TestData {
+                path: &test_dir.join("prealloc.bin"),
+                source: Source::Empty,
+                opts: CreateOptions {
+                    size: Some(8192),
+                    ..Default::default()
+                },
+                contents: "",
+                len: 8192,
+                result: Ok(()),
+            },
+            TestData {
+                path: &test_dir.join("sparse.bin"),
+                source: Source::Empty,
+                opts: CreateOptions {
+                    size: Some(1 << 20),
+                    sparse: true,
+                    ..Default::default()
+                },
+                contents: "",
+                len: 1 << 20,
+                result: Ok(()),
+            },
+        ];
+
+        // Run the tests
+        for (i, d) in tests.iter().enumerate() {
+            let msg = format!("test[{}]: {:?}", i, d);
+            let actual_result = create_file_from(d.path, d.source.clone(), &d.opts);
+            let msg = format!("{}, result: {:?}", msg, actual_result);
+
+            if actual_result.is_ok() {
+                let meta = std::fs::metadata(d.path).unwrap();
+                assert_eq!(meta.len(), d.len, "{}", msg);
+                if d.len == d.contents.len() as u64 {
+                    ChildPath::new(d.path).assert(d.contents);
+                }
+                if let Some(mode) = d.opts.mode {
+                    assert_eq!(meta.permissions().mode() & 0o7777, mode, "{}", msg);
+                }
+                continue;
+            }
+
+            // a failed create leaves nothing behind
+            assert!(!d.path.exists(), "{}", msg);
+            verify_result(actual_result, &d.result, msg);
+        }
+        assert_eq!(std::fs::read_dir(&test_dir).unwrap().count(), 5);
+    }
+
+    #[test]
+    fn test_parse_size_and_mode() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10K").unwrap(), 10 << 10);
+        assert_eq!(parse_size("10M").unwrap(), 10 << 20);
+        assert_eq!(parse_size("2GiB").unwrap(), 2 << 30);
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("M").is_err());
+
+        assert_eq!(format_size(512), "512");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(10 << 20), "10M");
+        assert_eq!(format_size(3 << 40), "3.0T");
+
+        assert_eq!(parse_mode("0640").unwrap(), 0o640);
+        assert_eq!(parse_mode("755").unwrap(), 0o755);
+        assert!(parse_mode("0999").is_err());
+        assert!(parse_mode("17777").is_err());
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let dst = test_dir.join("atomic.txt");
+        write_atomic(&dst, b"first", false).unwrap();
+        ChildPath::new(&dst).assert("first");
+
+        let err = write_atomic(&dst, b"second", false).unwrap_err();
+        assert!(err.to_string().contains("File exists"), "{}", err);
+        ChildPath::new(&dst).assert("first");
+
+        std::fs::set_permissions(&dst, Permissions::from_mode(0o600)).unwrap();
+        write_atomic(&dst, b"second", true).unwrap();
+        ChildPath::new(&dst).assert("second");
+        let mode = std::fs::metadata(&dst).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // no temporary files are left behind
+        assert_eq!(std::fs::read_dir(&test_dir).unwrap().count(), 1);
+    }
+
     #[test]
     fn test_copy_file() {
         #[derive(Debug)]
@@ -335,6 +836,123 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_transfer_compression() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let plain = test_dir.join("plain.log");
+        std::fs::write(&plain, "first line\n").unwrap();
+        let zstd_opts = TransferOptions {
+            compress: Some("zstd".parse().unwrap()),
+            ..Default::default()
+        };
+        let gzip_opts = TransferOptions {
+            compress: Some("gzip:9".parse().unwrap()),
+            ..Default::default()
+        };
+
+        // compressed copies with misleading names are still detected by content
+        let zst = test_dir.join("a.log");
+        let gz = test_dir.join("b.txt");
+        copy_file_with(&plain, &zst, &zstd_opts).unwrap();
+        cat_files_with(&plain, &plain, &gz, &gzip_opts).unwrap();
+        assert!(copy_file_with(&test_dir, &test_dir.join("dir.zst"), &zstd_opts)
+            .unwrap_err()
+            .to_string()
+            .contains("neither a regular file"));
+
+        let dst = test_dir.join("merged.log");
+        let opts = TransferOptions {
+            decompress: true,
+            ..Default::default()
+        };
+        cat_files_with(&zst, &gz, &dst, &opts).unwrap();
+        ChildPath::new(&dst).assert("first line\nfirst line\nfirst line\n");
+
+        // without --decompress the raw bytes are concatenated
+        let raw = test_dir.join("raw.bin");
+        cat_files_with(&zst, &plain, &raw, &TransferOptions::default()).unwrap();
+        let raw = std::fs::read(&raw).unwrap();
+        assert!(raw.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+    }
+
+    #[test]
+    fn test_copy_symlink() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        std::fs::write(test_dir.join("real.txt"), "real").unwrap();
+        let link = test_dir.join("link");
+        std::os::unix::fs::symlink("real.txt", &link).unwrap();
+        let keep = TransferOptions {
+            no_dereference: true,
+            ..Default::default()
+        };
+
+        // followed by default
+        let followed = test_dir.join("followed");
+        copy_file_with(&link, &followed, &TransferOptions::default()).unwrap();
+        assert!(!followed.symlink_metadata().unwrap().file_type().is_symlink());
+        ChildPath::new(&followed).assert("real");
+
+        let kept = test_dir.join("kept");
+        copy_file_with(&link, &kept, &keep).unwrap();
+        assert_eq!(std::fs::read_link(&kept).unwrap(), Path::new("real.txt"));
+
+        // regular files are copied as usual
+        copy_file_with(&test_dir.join("real.txt"), &test_dir.join("plain"), &keep).unwrap();
+        ChildPath::new(test_dir.join("plain")).assert("real");
+
+        let err = copy_file_with(
+            &link,
+            &test_dir.join("kept.zst"),
+            &TransferOptions {
+                compress: Some("zstd".parse().unwrap()),
+                ..keep
+            },
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("cannot be compressed or encrypted"), "{}", err);
+    }
+
+    #[test]
+    fn test_copy_progress() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let src = test_dir.join("src.bin");
+        std::fs::write(&src, vec![7; 100_000]).unwrap();
+        let progress = Arc::new(Progress::default());
+        let opts = TransferOptions {
+            progress: Some(progress.clone()),
+            ..Default::default()
+        };
+
+        copy_file_with(&src, &test_dir.join("copy.bin"), &opts).unwrap();
+        assert_eq!(progress.bytes.load(Ordering::Relaxed), 100_000);
+        assert_eq!(progress.total.load(Ordering::Relaxed), 100_000);
+        assert_eq!(std::fs::read(test_dir.join("copy.bin")).unwrap().len(), 100_000);
+
+        // a cancelled transfer leaves nothing behind
+        progress.cancelled.store(true, Ordering::Relaxed);
+        let err = copy_file_with(&src, &test_dir.join("cancelled.bin"), &opts).unwrap_err();
+        assert!(err.to_string().contains("Cancelled"), "{}", err);
+        assert_eq!(std::fs::read_dir(&test_dir).unwrap().count(), 2);
+
+        // and so does a cancelled create --from
+        let opts = CreateOptions {
+            progress: Some(progress.clone()),
+            ..Default::default()
+        };
+        let err = create_file_from(&test_dir.join("created.bin"), Source::File(&src), &opts).unwrap_err();
+        assert!(err.to_string().contains("Cancelled"), "{}", err);
+        assert_eq!(std::fs::read_dir(&test_dir).unwrap().count(), 2);
+    }
+
     #[test]
     fn test_delete_file() {
         #[derive(Debug)]
@@ -0,0 +1,255 @@
+use anyhow::{anyhow, Result};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::detect::{detect_bytes, Category, FileType, GZIP, XZ, ZSTD};
+
+/// Compression codecs filey can read and write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Codec {
+    /// Identify a codec from the first bytes of a stream.
+    pub fn from_magic(header: &[u8]) -> Option<Self> {
+        Self::from_type(&detect_bytes(header))
+    }
+
+    /// The codec that decodes content of the detected `kind`, if any.
+    pub fn from_type(kind: &FileType) -> Option<Self> {
+        [(GZIP, Codec::Gzip), (ZSTD, Codec::Zstd), (XZ, Codec::Xz)]
+            .into_iter()
+            .find(|(t, _)| t == kind)
+            .map(|(_, c)| c)
+    }
+
+    fn levels(self) -> (u32, u32, u32) {
+        // (min, max, default)
+        match self {
+            Codec::Gzip => (0, 9, 6),
+            Codec::Zstd => (1, 22, 3),
+            Codec::Xz => (0, 9, 6),
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+            Codec::Xz => "xz",
+        })
+    }
+}
+
+/// A codec and level, parsed from `gzip`, `zstd:19`, `xz:9` and so on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    pub codec: Codec,
+    pub level: u32,
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, level) = match s.split_once(':') {
+            Some((n, l)) => (n, Some(l)),
+            None => (s, None),
+        };
+        let codec = match name {
+            "gzip" | "gz" => Codec::Gzip,
+            "zstd" | "zst" => Codec::Zstd,
+            "xz" => Codec::Xz,
+            _ => return Err(anyhow!("Unknown compression {}, expected gzip, zstd or xz", name)),
+        };
+        let (min, max, default) = codec.levels();
+        let level = match level {
+            None => default,
+            Some(l) => l
+                .parse()
+                .ok()
+                .filter(|l| (min..=max).contains(l))
+                .ok_or_else(|| anyhow!("Invalid {} level {}, expected {}-{}", codec, l, min, max))?,
+        };
+        Ok(Compression { codec, level })
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.codec, self.level)
+    }
+}
+
+/// Open `path` for reading, transparently decoding it if its contents start
+/// with a known compression header. Other files are read as they are, except
+/// compressed formats filey cannot decode, which are an error.
+pub fn open_decoded(path: &Path) -> Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let kind = detect_bytes(reader.fill_buf()?);
+    let codec = Codec::from_type(&kind);
+    if codec.is_none() && kind.category == Category::Compressed {
+        return Err(anyhow!("Cannot decompress {}: {} is not supported", path.display(), kind.description));
+    }
+    Ok(match codec {
+        None => Box::new(reader),
+        Some(Codec::Gzip) => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+        Some(Codec::Zstd) => Box::new(zstd::Decoder::with_buffer(reader)?),
+        Some(Codec::Xz) => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)),
+    })
+}
+
+/// A writer that optionally compresses what passes through it. `finish`
+/// must be called to flush the compressed stream's trailer.
+pub enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+    Xz(xz2::write::XzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(inner: W, compression: Option<Compression>) -> Result<Self> {
+        let Some(c) = compression else {
+            return Ok(Encoder::Plain(inner));
+        };
+        Ok(match c.codec {
+            Codec::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(inner, flate2::Compression::new(c.level))),
+            Codec::Zstd => Encoder::Zstd(zstd::Encoder::new(inner, c.level as i32)?),
+            Codec::Xz => Encoder::Xz(xz2::write::XzEncoder::new(inner, c.level)),
+        })
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Plain(mut w) => {
+                w.flush()?;
+                Ok(w)
+            }
+            Encoder::Gzip(e) => e.finish(),
+            Encoder::Zstd(e) => e.finish(),
+            Encoder::Xz(e) => e.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(w) => w.write(buf),
+            Encoder::Gzip(e) => e.write(buf),
+            Encoder::Zstd(e) => e.write(buf),
+            Encoder::Xz(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Gzip(e) => e.flush(),
+            Encoder::Zstd(e) => e.flush(),
+            Encoder::Xz(e) => e.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_parse_compression() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            input: &'a str,
+            result: Result<Compression>,
+        }
+
+        let tests = &[
+            // failures
+            TestData {
+                input: "lz4",
+                result: Err(anyhow!("Unknown compression")),
+            },
+            TestData {
+                input: "gzip:10",
+                result: Err(anyhow!("Invalid gzip level")),
+            },
+            TestData {
+                input: "zstd:0",
+                result: Err(anyhow!("Invalid zstd level")),
+            },
+            // successes
+            TestData {
+                input: "gzip",
+                result: Ok(Compression { codec: Codec::Gzip, level: 6 }),
+            },
+            TestData {
+                input: "zstd:19",
+                result: Ok(Compression { codec: Codec::Zstd, level: 19 }),
+            },
+            TestData {
+                input: "xz:0",
+                result: Ok(Compression { codec: Codec::Xz, level: 0 }),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let actual = d.input.parse::<Compression>();
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+            match &d.result {
+                Ok(expected) => assert_eq!(actual.unwrap(), *expected, "{}", msg),
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let contents = "Pellentesque habitant morbi tristique senectus et netus.\n".repeat(100);
+        for spec in ["gzip", "zstd:5", "xz:1"] {
+            let c: Compression = spec.parse().unwrap();
+            let path = test_dir.join(format!("out.{}", spec));
+            let mut enc = Encoder::new(File::create(&path).unwrap(), Some(c)).unwrap();
+            enc.write_all(contents.as_bytes()).unwrap();
+            enc.finish().unwrap();
+
+            let raw = std::fs::read(&path).unwrap();
+            assert_eq!(Codec::from_magic(&raw), Some(c.codec), "{}", spec);
+            assert!(raw.len() < contents.len(), "{}", spec);
+
+            let mut decoded = String::new();
+            open_decoded(&path).unwrap().read_to_string(&mut decoded).unwrap();
+            assert_eq!(decoded, contents, "{}", spec);
+        }
+
+        // plain files pass through untouched
+        let plain = test_dir.join("plain.gz");
+        std::fs::write(&plain, "not actually gzip").unwrap();
+        let mut decoded = String::new();
+        open_decoded(&plain).unwrap().read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "not actually gzip");
+
+        // recognized but unsupported formats are not passed through as is
+        let bzip2 = test_dir.join("data.bz2");
+        std::fs::write(&bzip2, b"BZh91AY&SY").unwrap();
+        let err = open_decoded(&bzip2).err().unwrap();
+        assert!(err.to_string().contains("bzip2 compressed data is not supported"), "{}", err);
+    }
+}
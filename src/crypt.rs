@@ -0,0 +1,273 @@
+use age::secrecy::SecretString;
+use anyhow::{anyhow, Result};
+use std::{
+    env,
+    fs::{File, Permissions},
+    io::{self, BufReader, BufWriter, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::cmd::write_atomic_with;
+
+/// What `encrypt_file` encrypts to.
+#[derive(Debug)]
+pub enum EncryptKey {
+    Passphrase(String),
+    /// `age1...` public keys, or paths to files listing one per line.
+    Recipients(Vec<String>),
+}
+
+/// What `decrypt_file` decrypts with.
+#[derive(Debug)]
+pub enum DecryptKey {
+    Passphrase(String),
+    /// Paths to age identity files.
+    Identities(Vec<PathBuf>),
+}
+
+/// Read a passphrase from the first line of `file`, falling back to the
+/// `FILEY_PASSPHRASE` environment variable.
+pub fn read_passphrase(file: Option<&Path>) -> Result<String> {
+    let passphrase = match file {
+        Some(f) => std::fs::read_to_string(f)?.lines().next().unwrap_or_default().to_owned(),
+        None => env::var("FILEY_PASSPHRASE")
+            .map_err(|_| anyhow!("No passphrase given, use --passphrase-file or set FILEY_PASSPHRASE"))?,
+    };
+    if passphrase.is_empty() {
+        return Err(anyhow!("Passphrase is empty"));
+    }
+    Ok(passphrase)
+}
+
+fn parse_recipients(specs: &[String]) -> Result<Vec<age::x25519::Recipient>> {
+    let mut recipients = Vec::new();
+    for spec in specs {
+        if spec.starts_with("age1") {
+            recipients.push(age::x25519::Recipient::from_str(spec).map_err(|e| anyhow!("Invalid recipient {}: {}", spec, e))?);
+            continue;
+        }
+        let contents = std::fs::read_to_string(spec).map_err(|e| anyhow!("Recipients file {}: {}", spec, e))?;
+        for line in contents.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            recipients.push(age::x25519::Recipient::from_str(line).map_err(|e| anyhow!("Invalid recipient in {}: {}", spec, e))?);
+        }
+    }
+    if recipients.is_empty() {
+        return Err(anyhow!("No recipients given"));
+    }
+    Ok(recipients)
+}
+
+/// Build an age encryptor for `key`.
+pub fn encryptor(key: &EncryptKey) -> Result<age::Encryptor> {
+    match key {
+        EncryptKey::Passphrase(p) => Ok(age::Encryptor::with_user_passphrase(SecretString::from(p.clone()))),
+        EncryptKey::Recipients(specs) => {
+            let recipients = parse_recipients(specs)?;
+            Ok(age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?)
+        }
+    }
+}
+
+/// Encrypt `source` into a new age file at `dst`. The plaintext is streamed
+/// straight into the encryptor, so no plaintext copy is ever written.
+pub fn encrypt_file(source: &Path, dst: &Path, key: &EncryptKey) -> Result<String> {
+    if dst.exists() {
+        return Err(anyhow!("Destination file exists"));
+    }
+    let encryptor = encryptor(key)?;
+    let mut input = File::open(source)?;
+
+    write_atomic_with(dst, false, |file| {
+        let mut writer = encryptor.wrap_output(BufWriter::new(file))?;
+        io::copy(&mut input, &mut writer)?;
+        writer.finish()?.flush()?;
+        Ok(())
+    })?;
+
+    let msg = format!(
+        "Encrypted file successfully: {} {}",
+        source.to_str().unwrap_or_default(),
+        dst.to_str().unwrap_or_default()
+    );
+
+    Ok(msg)
+}
+
+/// Decrypt the age file `source` into a new file at `dst`. The output is
+/// readable only by its owner and is removed again if authentication of any
+/// chunk fails.
+pub fn decrypt_file(source: &Path, dst: &Path, key: &DecryptKey) -> Result<String> {
+    if dst.exists() {
+        return Err(anyhow!("Destination file exists"));
+    }
+    let decryptor = age::Decryptor::new_buffered(BufReader::new(File::open(source)?))
+        .map_err(|e| anyhow!("{}: {}", source.display(), e))?;
+
+    let identities: Vec<Box<dyn age::Identity>> = match key {
+        DecryptKey::Passphrase(p) => {
+            if !decryptor.is_scrypt() {
+                return Err(anyhow!("File is not passphrase encrypted, use --identity"));
+            }
+            vec![Box::new(age::scrypt::Identity::new(SecretString::from(p.clone())))]
+        }
+        DecryptKey::Identities(files) => {
+            let mut identities = Vec::new();
+            for f in files {
+                let file = age::IdentityFile::from_file(f.to_string_lossy().into_owned())
+                    .map_err(|e| anyhow!("Identity file {}: {}", f.display(), e))?;
+                identities.extend(file.into_identities()?);
+            }
+            identities
+        }
+    };
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|i| i.as_ref()))
+        .map_err(|e| anyhow!("Decryption failed: {}", e))?;
+
+    write_atomic_with(dst, false, |file| {
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        let mut writer = BufWriter::new(file);
+        io::copy(&mut reader, &mut writer).map_err(|e| anyhow!("Decryption failed: {}", e))?;
+        writer.flush()?;
+        Ok(())
+    })?;
+
+    let msg = format!(
+        "Decrypted file successfully: {} {}",
+        source.to_str().unwrap_or_default(),
+        dst.to_str().unwrap_or_default()
+    );
+
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+    use assert_fs::fixture::ChildPath;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            encrypt: EncryptKey,
+            decrypt: DecryptKey,
+            result: Result<&'a str>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        // setup keys and plaintext
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let identity_file = test_dir.join("key.txt");
+        std::fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+        let other_file = test_dir.join("other.txt");
+        let other = age::x25519::Identity::generate();
+        std::fs::write(&other_file, other.to_string().expose_secret()).unwrap();
+        let recipients_file = test_dir.join("recipients.txt");
+        std::fs::write(&recipients_file, format!("# team\n{}\n", recipient)).unwrap();
+
+        let plaintext = "Donec sollicitudin molestie malesuada.\n".repeat(5000);
+        let src = test_dir.join("secret.txt");
+        std::fs::write(&src, &plaintext).unwrap();
+
+        let tests = &[
+            // failures
+            TestData {
+                encrypt: EncryptKey::Recipients(vec!["age1notakey".to_owned()]),
+                decrypt: DecryptKey::Identities(vec![identity_file.clone()]),
+                result: Err(anyhow!("Invalid recipient")),
+            },
+            TestData {
+                encrypt: EncryptKey::Recipients(vec![recipient.clone()]),
+                decrypt: DecryptKey::Identities(vec![other_file.clone()]),
+                result: Err(anyhow!("Decryption failed")),
+            },
+            TestData {
+                encrypt: EncryptKey::Recipients(vec![recipient.clone()]),
+                decrypt: DecryptKey::Passphrase("hunter2".to_owned()),
+                result: Err(anyhow!("not passphrase encrypted")),
+            },
+            TestData {
+                encrypt: EncryptKey::Passphrase("hunter2".to_owned()),
+                decrypt: DecryptKey::Passphrase("hunter3".to_owned()),
+                result: Err(anyhow!("Decryption failed")),
+            },
+            // successes
+            TestData {
+                encrypt: EncryptKey::Recipients(vec![recipient.clone()]),
+                decrypt: DecryptKey::Identities(vec![identity_file.clone()]),
+                result: Ok(&plaintext),
+            },
+            TestData {
+                encrypt: EncryptKey::Recipients(vec![recipients_file.to_str().unwrap().to_owned()]),
+                decrypt: DecryptKey::Identities(vec![other_file.clone(), identity_file.clone()]),
+                result: Ok(&plaintext),
+            },
+            TestData {
+                encrypt: EncryptKey::Passphrase("hunter2".to_owned()),
+                decrypt: DecryptKey::Passphrase("hunter2".to_owned()),
+                result: Ok(&plaintext),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let encrypted = test_dir.join(format!("{}.age", i));
+            let decrypted = test_dir.join(format!("{}.out", i));
+            let actual = encrypt_file(&src, &encrypted, &d.encrypt)
+                .and_then(|_| decrypt_file(&encrypted, &decrypted, &d.decrypt));
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok(expected) => {
+                    assert!(actual.is_ok(), "{}", msg);
+                    let ciphertext = std::fs::read(&encrypted).unwrap();
+                    assert!(ciphertext.starts_with(b"age-encryption.org/v1"), "{}", msg);
+                    ChildPath::new(&decrypted).assert(*expected);
+                    let mode = std::fs::metadata(&decrypted).unwrap().permissions().mode();
+                    assert_eq!(mode & 0o777, 0o600, "{}", msg);
+                }
+                Err(e) => {
+                    assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg);
+                    assert!(!decrypted.exists(), "{}", msg);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decrypt_tampered() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = test_dir.join("key.txt");
+        std::fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+        let src = test_dir.join("data.bin");
+        std::fs::write(&src, vec![42u8; 200_000]).unwrap();
+
+        let encrypted = test_dir.join("data.age");
+        encrypt_file(&src, &encrypted, &EncryptKey::Recipients(vec![identity.to_public().to_string()])).unwrap();
+        let mut ciphertext = std::fs::read(&encrypted).unwrap();
+        let last = ciphertext.len() - 10;
+        ciphertext[last] ^= 0xff;
+        std::fs::write(&encrypted, ciphertext).unwrap();
+
+        let dst = test_dir.join("data.out");
+        let err = decrypt_file(&encrypted, &dst, &DecryptKey::Identities(vec![identity_file])).unwrap_err();
+        assert!(err.to_string().contains("Decryption failed"), "{}", err);
+        assert!(!dst.exists());
+        // no partial plaintext is left behind
+        assert_eq!(std::fs::read_dir(&test_dir).unwrap().count(), 3);
+    }
+}
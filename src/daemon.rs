@@ -0,0 +1,671 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env, fmt, fs,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::cmd::{
+    cat_files_with, copy_file_with, create_file_from, delete_file, format_size, parse_mode, ConflictPolicy,
+    CreateOptions, Progress, Source, TransferOptions,
+};
+
+/// How many finished jobs the daemon remembers.
+const MAX_FINISHED: usize = 1000;
+
+/// How often a waiting client hears about a running job.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const UNKNOWN_JOB: i64 = -32000;
+
+/// Where the daemon listens unless told otherwise: `$FILEY_SOCKET`, or
+/// `filey.sock` in the user's runtime dir.
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = env::var_os("FILEY_SOCKET") {
+        return PathBuf::from(path);
+    }
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("filey.sock"),
+        // SAFETY: getuid(2) has no failure modes.
+        None => env::temp_dir().join(format!("filey-{}.sock", unsafe { libc::getuid() })),
+    }
+}
+
+/// An operation from `cmd` that the daemon runs as a job. On the wire it is
+/// a JSON-RPC method and its params; relative paths are resolved against
+/// the `cwd` param, or the daemon's own directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "lowercase")]
+pub enum Operation {
+    Create {
+        path: PathBuf,
+        text: Option<String>,
+        /// Copy the contents of this file.
+        from: Option<PathBuf>,
+        #[serde(default)]
+        parents: bool,
+        /// Octal permission mode, e.g. `"0640"`.
+        mode: Option<String>,
+        size: Option<u64>,
+        #[serde(default)]
+        sparse: bool,
+        #[serde(default)]
+        on_conflict: ConflictPolicy,
+    },
+    Copy {
+        src: PathBuf,
+        dst: PathBuf,
+        /// `CODEC[:LEVEL]`, as for `filey copy --compress`.
+        compress: Option<String>,
+        #[serde(default)]
+        encrypt_to: Vec<String>,
+        #[serde(default)]
+        no_dereference: bool,
+    },
+    Cat {
+        src1: PathBuf,
+        src2: PathBuf,
+        dst: PathBuf,
+        #[serde(default)]
+        decompress: bool,
+        compress: Option<String>,
+    },
+    Delete {
+        path: PathBuf,
+    },
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Create { .. } => "create",
+            Operation::Copy { .. } => "copy",
+            Operation::Cat { .. } => "cat",
+            Operation::Delete { .. } => "delete",
+        }
+    }
+
+    fn resolve(&mut self, cwd: &Path) {
+        let paths = match self {
+            Operation::Create { path, from, .. } => vec![Some(path), from.as_mut()],
+            Operation::Copy { src, dst, .. } => vec![Some(src), Some(dst)],
+            Operation::Cat { src1, src2, dst, .. } => vec![Some(src1), Some(src2), Some(dst)],
+            Operation::Delete { path } => vec![Some(path)],
+        };
+        for path in paths.into_iter().flatten() {
+            *path = cwd.join(&*path);
+        }
+    }
+
+    fn run(&self, progress: &Arc<Progress>) -> Result<String> {
+        let transfer = |decompress: bool, compress: &Option<String>, encrypt_to: &[String], no_dereference: bool| -> Result<TransferOptions> {
+            Ok(TransferOptions {
+                decompress,
+                compress: compress.as_deref().map(str::parse).transpose()?,
+                encrypt_to: encrypt_to.to_vec(),
+                no_dereference,
+                progress: Some(progress.clone()),
+            })
+        };
+        match self {
+            Operation::Create {
+                path,
+                text,
+                from,
+                parents,
+                mode,
+                size,
+                sparse,
+                on_conflict,
+            } => {
+                let source = match (text, from) {
+                    (Some(t), _) => Source::Text(t),
+                    (None, Some(f)) => Source::File(f),
+                    (None, None) => Source::Empty,
+                };
+                let opts = CreateOptions {
+                    conflict: *on_conflict,
+                    parents: *parents,
+                    mode: mode.as_deref().map(parse_mode).transpose()?,
+                    size: *size,
+                    sparse: *sparse,
+                    progress: Some(progress.clone()),
+                };
+                create_file_from(path, source, &opts)
+            }
+            Operation::Copy {
+                src,
+                dst,
+                compress,
+                encrypt_to,
+                no_dereference,
+            } => copy_file_with(src, dst, &transfer(false, compress, encrypt_to, *no_dereference)?),
+            Operation::Cat {
+                src1,
+                src2,
+                dst,
+                decompress,
+                compress,
+            } => cat_files_with(src1, src2, dst, &transfer(*decompress, compress, &[], false)?),
+            Operation::Delete { path } => delete_file(path),
+        }
+    }
+}
+
+/// Where a job is in its life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    fn finished(self) -> bool {
+        matches!(self, JobState::Done | JobState::Failed | JobState::Cancelled)
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        })
+    }
+}
+
+/// What a client is told about a job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub job: u64,
+    pub method: String,
+    pub state: JobState,
+    /// Bytes transferred so far, for copies and concatenations.
+    pub bytes: u64,
+    pub total: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Job {
+    op: Operation,
+    state: JobState,
+    result: Option<Result<String, String>>,
+    progress: Arc<Progress>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next: u64,
+    all: BTreeMap<u64, Job>,
+    queue: VecDeque<u64>,
+}
+
+impl Jobs {
+    fn status(&self, id: u64) -> Result<JobStatus> {
+        let job = self.all.get(&id).ok_or_else(|| anyhow!("Unknown job {}", id))?;
+        let (message, error) = match &job.result {
+            Some(Ok(msg)) => (Some(msg.clone()), None),
+            Some(Err(e)) => (None, Some(e.clone())),
+            None => (None, None),
+        };
+        Ok(JobStatus {
+            job: id,
+            method: job.op.name().to_owned(),
+            state: job.state,
+            bytes: job.progress.bytes.load(Ordering::Relaxed),
+            total: job.progress.total.load(Ordering::Relaxed),
+            message,
+            error,
+        })
+    }
+
+    /// Forget the oldest finished jobs beyond `MAX_FINISHED`.
+    fn prune(&mut self) {
+        let finished: Vec<u64> = self.all.iter().filter(|(_, j)| j.state.finished()).map(|(id, _)| *id).collect();
+        for id in &finished[..finished.len().saturating_sub(MAX_FINISHED)] {
+            self.all.remove(id);
+        }
+    }
+}
+
+/// A queue of jobs run by a fixed number of worker threads.
+#[derive(Default)]
+pub struct Daemon {
+    jobs: Mutex<Jobs>,
+    /// Signalled whenever a job is queued or changes state.
+    changed: Condvar,
+}
+
+impl Daemon {
+    /// Start `workers` threads, the most jobs that run at once.
+    pub fn start_workers(self: &Arc<Self>, workers: usize) {
+        for _ in 0..workers {
+            let daemon = self.clone();
+            thread::spawn(move || daemon.work());
+        }
+    }
+
+    fn work(&self) {
+        loop {
+            let (id, op, progress) = {
+                let jobs = self.jobs.lock().unwrap();
+                let mut jobs = self.changed.wait_while(jobs, |j| j.queue.is_empty()).unwrap();
+                let id = jobs.queue.pop_front().unwrap();
+                let job = jobs.all.get_mut(&id).unwrap();
+                job.state = JobState::Running;
+                (id, job.op.clone(), job.progress.clone())
+            };
+            self.changed.notify_all();
+
+            let result = catch_panic(|| op.run(&progress));
+
+            let mut jobs = self.jobs.lock().unwrap();
+            if let Some(job) = jobs.all.get_mut(&id) {
+                job.state = match (&result, progress.cancelled.load(Ordering::Relaxed)) {
+                    (Ok(_), _) => JobState::Done,
+                    (Err(_), true) => JobState::Cancelled,
+                    (Err(_), false) => JobState::Failed,
+                };
+                job.result = Some(result.map_err(|e| e.to_string()));
+            }
+            jobs.prune();
+            drop(jobs);
+            self.changed.notify_all();
+        }
+    }
+
+    /// Queue `op` and return its job id.
+    pub fn submit(&self, op: Operation) -> u64 {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.next += 1;
+        let id = jobs.next;
+        jobs.all.insert(
+            id,
+            Job {
+                op,
+                state: JobState::Queued,
+                result: None,
+                progress: Arc::default(),
+            },
+        );
+        jobs.queue.push_back(id);
+        drop(jobs);
+        self.changed.notify_all();
+        id
+    }
+
+    pub fn status(&self, id: u64) -> Result<JobStatus> {
+        self.jobs.lock().unwrap().status(id)
+    }
+
+    /// Every job the daemon remembers, oldest first.
+    pub fn list(&self) -> Vec<JobStatus> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.all.keys().filter_map(|id| jobs.status(*id).ok()).collect()
+    }
+
+    /// Cancel a job. A queued job never starts; a running transfer or
+    /// `create` from a file stops at its next read, and other running
+    /// operations finish as usual.
+    pub fn cancel(&self, id: u64) -> Result<JobStatus> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.all.get_mut(&id).ok_or_else(|| anyhow!("Unknown job {}", id))?;
+        match job.state {
+            JobState::Queued => {
+                job.state = JobState::Cancelled;
+                job.result = Some(Err("Cancelled".to_owned()));
+                jobs.queue.retain(|j| *j != id);
+            }
+            JobState::Running => job.progress.cancelled.store(true, Ordering::Relaxed),
+            _ => return Err(anyhow!("Job {} has already finished", id)),
+        }
+        let status = jobs.status(id);
+        drop(jobs);
+        self.changed.notify_all();
+        status
+    }
+
+    /// Wait for job `id` to finish, calling `on_progress` each time it
+    /// changes state or moves forward.
+    pub fn wait(&self, id: u64, mut on_progress: impl FnMut(&JobStatus) -> Result<()>) -> Result<JobStatus> {
+        let mut last = None;
+        loop {
+            let status = self.status(id)?;
+            if status.state.finished() {
+                return Ok(status);
+            }
+            if last != Some((status.state, status.bytes)) {
+                last = Some((status.state, status.bytes));
+                on_progress(&status)?;
+            }
+            let jobs = self.jobs.lock().unwrap();
+            let _ = self.changed.wait_timeout(jobs, PROGRESS_INTERVAL).unwrap();
+        }
+    }
+}
+
+/// Run `f`, turning a panic into an error, so that a panicking operation
+/// fails its job rather than taking the worker down with it and leaving the
+/// job running forever.
+fn catch_panic(f: impl FnOnce() -> Result<String>) -> Result<String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let reason = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_owned());
+        Err(anyhow!("Job panicked: {}", reason))
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    /// Absent for notifications, which get no response.
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Listen on `socket` and serve the JSON-RPC API until the process is
+/// stopped, running at most `workers` jobs at once. Messages are JSON
+/// objects, one per line. The socket is only accessible to its owner. Once
+/// it accepts connections, a line saying so is written to `out`.
+pub fn serve(socket: &Path, workers: usize, out: &mut dyn Write) -> Result<String> {
+    if let Ok(meta) = fs::symlink_metadata(socket) {
+        if !meta.file_type().is_socket() {
+            return Err(anyhow!("{} exists and is not a socket", socket.display()));
+        }
+        if UnixStream::connect(socket).is_ok() {
+            return Err(anyhow!("A daemon is already listening on {}", socket.display()));
+        }
+        // Left behind by a daemon that was killed.
+        fs::remove_file(socket)?;
+    }
+    // Bind inside a private directory and only move the socket into place
+    // once it is owner-only, so no one can connect while it is still open
+    // to the umask.
+    let parent = socket.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let private = tempfile::Builder::new().prefix(".filey-").tempdir_in(parent)?;
+    let bound = private.path().join("sock");
+    let listener = UnixListener::bind(&bound).map_err(|e| anyhow!("{}: {}", socket.display(), e))?;
+    fs::set_permissions(&bound, fs::Permissions::from_mode(0o600))?;
+    fs::rename(&bound, socket)?;
+
+    let daemon = Arc::new(Daemon::default());
+    daemon.start_workers(workers.max(1));
+    writeln!(out, "Listening on {}", socket.display())?;
+    out.flush()?;
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let daemon = daemon.clone();
+        thread::spawn(move || {
+            // A client that goes away mid-conversation is not an error.
+            let _ = handle(&daemon, stream);
+        });
+    }
+    Ok(String::new())
+}
+
+fn handle(daemon: &Daemon, stream: UnixStream) -> Result<()> {
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, result) = match serde_json::from_str::<Request>(&line) {
+            Ok(req) => (req.id.clone(), dispatch(daemon, req, &mut out)),
+            Err(e) => (Some(Value::Null), Err(RpcError::new(PARSE_ERROR, e))),
+        };
+        let Some(id) = id else { continue };
+        let response = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(e) => json!({"jsonrpc": "2.0", "id": id, "error": {"code": e.code, "message": e.message}}),
+        };
+        writeln!(out, "{}", response)?;
+    }
+    Ok(())
+}
+
+fn dispatch(daemon: &Daemon, req: Request, out: &mut UnixStream) -> Result<Value, RpcError> {
+    let mut params = match req.params {
+        Value::Object(map) => map,
+        Value::Null => Default::default(),
+        _ => return Err(RpcError::new(INVALID_PARAMS, "params must be an object")),
+    };
+    let job = || params.get("job").and_then(Value::as_u64).ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing job"));
+    let unknown = |e: anyhow::Error| RpcError::new(UNKNOWN_JOB, e);
+    let to_value = |s: JobStatus| serde_json::to_value(s).map_err(|e| RpcError::new(INVALID_PARAMS, e));
+    let wait = |id: u64, out: &mut UnixStream| {
+        daemon
+            .wait(id, |status| {
+                let note = json!({"jsonrpc": "2.0", "method": "progress", "params": status});
+                Ok(writeln!(out, "{}", note)?)
+            })
+            .map_err(unknown)
+    };
+
+    match req.method.as_str() {
+        "create" | "copy" | "cat" | "delete" => {
+            let cwd = match params.remove("cwd") {
+                Some(Value::String(dir)) => PathBuf::from(dir),
+                None => env::current_dir().map_err(|e| RpcError::new(INVALID_PARAMS, e))?,
+                Some(_) => return Err(RpcError::new(INVALID_PARAMS, "cwd must be a string")),
+            };
+            let wait_for_it = params.remove("wait").and_then(|w| w.as_bool()).unwrap_or(false);
+            let mut op: Operation = serde_json::from_value(json!({"method": req.method, "params": params}))
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+            op.resolve(&cwd);
+            let id = daemon.submit(op);
+            if wait_for_it {
+                to_value(wait(id, out)?)
+            } else {
+                Ok(json!({"job": id}))
+            }
+        }
+        "status" => to_value(daemon.status(job()?).map_err(unknown)?),
+        "jobs" => serde_json::to_value(daemon.list()).map_err(|e| RpcError::new(INVALID_PARAMS, e)),
+        "cancel" => to_value(daemon.cancel(job()?).map_err(unknown)?),
+        "wait" => to_value(wait(job()?, out)?),
+        other => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", other))),
+    }
+}
+
+/// Run `op` on the daemon listening at `socket` and wait for it, showing
+/// its progress when stderr is a terminal. Relative paths are resolved
+/// against the current directory.
+pub fn run_remote(socket: &Path, op: &Operation) -> Result<String> {
+    let stream = UnixStream::connect(socket).map_err(|e| anyhow!("Cannot connect to {}: {}", socket.display(), e))?;
+    let mut request = serde_json::to_value(op)?;
+    request["jsonrpc"] = json!("2.0");
+    request["id"] = json!(1);
+    request["params"]["cwd"] = json!(env::current_dir()?);
+    request["params"]["wait"] = json!(true);
+    writeln!(&stream, "{}", request)?;
+
+    let tty = io::stderr().is_terminal();
+    for line in BufReader::new(&stream).lines() {
+        let msg: Value = serde_json::from_str(&line?)?;
+        if msg.get("method").is_some() {
+            if let (true, Ok(status)) = (tty, serde_json::from_value::<JobStatus>(msg["params"].clone())) {
+                eprint!("\r\x1b[K{}", progress_line(&status));
+            }
+            continue;
+        }
+        if tty {
+            eprint!("\r\x1b[K");
+        }
+        if let Some(err) = msg.get("error") {
+            return Err(anyhow!("{}", err["message"].as_str().unwrap_or("Daemon error")));
+        }
+        let status: JobStatus = serde_json::from_value(msg["result"].clone())?;
+        return match status.state {
+            JobState::Done => Ok(status.message.unwrap_or_default()),
+            state => Err(anyhow!(status.error.unwrap_or_else(|| format!("Job {}", state)))),
+        };
+    }
+    Err(anyhow!("The daemon closed the connection"))
+}
+
+fn progress_line(status: &JobStatus) -> String {
+    let mut line = format!("job {}: {} {}", status.job, status.method, status.state);
+    if let Some(percent) = (status.bytes * 100).checked_div(status.total) {
+        line.push_str(&format!(
+            " {}% ({} of {})",
+            percent,
+            format_size(status.bytes),
+            format_size(status.total)
+        ));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    fn call(stream: &UnixStream, request: Value) -> Value {
+        writeln!(&*stream, "{}", request).unwrap();
+        let mut reader = BufReader::new(stream);
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let msg: Value = serde_json::from_str(&line).unwrap();
+            if msg.get("method").is_none() {
+                return msg;
+            }
+        }
+    }
+
+    #[test]
+    fn test_cancel_queued() {
+        // without workers nothing leaves the queue
+        let daemon = Daemon::default();
+        let id = daemon.submit(Operation::Delete {
+            path: PathBuf::from("/nonexistent"),
+        });
+        assert_eq!(daemon.status(id).unwrap().state, JobState::Queued);
+
+        let status = daemon.cancel(id).unwrap();
+        assert_eq!(status.state, JobState::Cancelled);
+        assert_eq!(status.error.as_deref(), Some("Cancelled"));
+        assert!(daemon.jobs.lock().unwrap().queue.is_empty());
+
+        let err = daemon.cancel(id).unwrap_err();
+        assert!(err.to_string().contains("already finished"), "{}", err);
+        let err = daemon.status(99).unwrap_err();
+        assert!(err.to_string().contains("Unknown job 99"), "{}", err);
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        assert_eq!(catch_panic(|| Ok("fine".to_owned())).unwrap(), "fine");
+        let err = catch_panic(|| panic!("index {} out of range", 3)).unwrap_err();
+        assert_eq!(err.to_string(), "Job panicked: index 3 out of range");
+        let err = catch_panic(|| panic!("boom")).unwrap_err();
+        assert_eq!(err.to_string(), "Job panicked: boom");
+    }
+
+    #[test]
+    fn test_serve() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let socket = test_dir.join("filey.sock");
+        let path = socket.clone();
+        thread::spawn(move || serve(&path, 2, &mut io::sink()));
+        while UnixStream::connect(&socket).is_err() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mode = fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let err = serve(&socket, 1, &mut io::sink()).unwrap_err();
+        assert!(err.to_string().contains("already listening"), "{}", err);
+
+        fs::write(test_dir.join("a.txt"), "hello").unwrap();
+        let cwd = test_dir.to_str().unwrap();
+        let stream = UnixStream::connect(&socket).unwrap();
+
+        let res = call(&stream, json!({"jsonrpc": "2.0", "id": 1, "method": "copy", "params": {"src": "a.txt", "dst": "b.txt", "cwd": cwd, "wait": true}}));
+        assert_eq!(res["id"], 1);
+        assert_eq!(res["result"]["state"], "done", "{}", res);
+        assert_eq!(res["result"]["bytes"], 5, "{}", res);
+        assert_eq!(fs::read_to_string(test_dir.join("b.txt")).unwrap(), "hello");
+
+        let res = call(&stream, json!({"jsonrpc": "2.0", "id": 2, "method": "delete", "params": {"path": "missing", "cwd": cwd}}));
+        let job = res["result"]["job"].as_u64().unwrap();
+        let res = call(&stream, json!({"jsonrpc": "2.0", "id": 3, "method": "wait", "params": {"job": job}}));
+        assert_eq!(res["result"]["state"], "failed", "{}", res);
+        assert!(res["result"]["error"].as_str().unwrap().contains("No such file"), "{}", res);
+
+        let res = call(&stream, json!({"jsonrpc": "2.0", "id": 4, "method": "jobs"}));
+        assert_eq!(res["result"].as_array().unwrap().len(), 2, "{}", res);
+
+        // errors
+        let res = call(&stream, json!({"jsonrpc": "2.0", "id": 5, "method": "copy", "params": {"src": "a.txt"}}));
+        assert_eq!(res["error"]["code"], INVALID_PARAMS, "{}", res);
+        let res = call(&stream, json!({"jsonrpc": "2.0", "id": 6, "method": "format", "params": {}}));
+        assert_eq!(res["error"]["code"], METHOD_NOT_FOUND, "{}", res);
+        let res = call(&stream, json!({"jsonrpc": "2.0", "id": 7, "method": "cancel", "params": {"job": 42}}));
+        assert_eq!(res["error"]["code"], UNKNOWN_JOB, "{}", res);
+
+        // the client
+        let op = Operation::Cat {
+            src1: test_dir.join("a.txt"),
+            src2: test_dir.join("b.txt"),
+            dst: test_dir.join("c.txt"),
+            decompress: false,
+            compress: None,
+        };
+        let msg = run_remote(&socket, &op).unwrap();
+        assert!(msg.starts_with("Concatenated files successfully"), "{}", msg);
+        assert_eq!(fs::read_to_string(test_dir.join("c.txt")).unwrap(), "hellohello");
+        let err = run_remote(&socket, &op).unwrap_err();
+        assert!(err.to_string().contains("Destination file exists"), "{}", err);
+    }
+}
@@ -0,0 +1,385 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::{self, Read},
+    os::unix::{fs::MetadataExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::checksum::{sha256_file, sha256_reader};
+use crate::output::{paint, to_json, Color, OutputFormat};
+
+/// How many leading bytes are hashed to split same-size candidates cheaply.
+const PARTIAL_HASH_BYTES: u64 = 4096;
+
+/// What to do with the duplicates in each set.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupeAction {
+    /// Only report duplicate sets.
+    #[default]
+    Report,
+    /// Replace duplicates with hard links to the kept file.
+    Hardlink,
+    /// Replace duplicates with copy-on-write clones of the kept file.
+    Reflink,
+    /// Delete all but the kept file.
+    Delete,
+}
+
+/// Which file of a set survives.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeepPolicy {
+    #[default]
+    Oldest,
+    Newest,
+    ShortestPath,
+}
+
+/// Options for `dedupe_files`.
+#[derive(Debug, Default)]
+pub struct DedupeOptions {
+    pub action: DedupeAction,
+    pub keep: KeepPolicy,
+    /// Report what would be done without changing anything.
+    pub dry_run: bool,
+    pub color: bool,
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct DuplicateSet {
+    size: u64,
+    sha256: String,
+    keep: String,
+    duplicates: Vec<String>,
+    wasted: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    action: DedupeAction,
+    dry_run: bool,
+    sets: Vec<DuplicateSet>,
+    total_wasted: u64,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    path: PathBuf,
+    mtime: SystemTime,
+}
+
+/// Find files with identical contents among `files` and optionally collapse
+/// each set onto one kept file. Files are grouped by size, then by a hash of
+/// their first bytes, and only then hashed in full. Empty files and paths
+/// that are already hard links of one another are ignored.
+pub fn dedupe_files(files: &[PathBuf], opts: &DedupeOptions) -> Result<String> {
+    let mut by_size: BTreeMap<u64, Vec<Candidate>> = BTreeMap::new();
+    let mut inodes = HashSet::new();
+    for path in files {
+        let meta = fs::metadata(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        if meta.len() == 0 || !inodes.insert((meta.dev(), meta.ino())) {
+            continue;
+        }
+        by_size.entry(meta.len()).or_default().push(Candidate {
+            path: path.clone(),
+            mtime: meta.modified()?,
+        });
+    }
+
+    let mut report = Report {
+        action: opts.action,
+        dry_run: opts.dry_run,
+        sets: Vec::new(),
+        total_wasted: 0,
+    };
+
+    // Largest files first: they are where the space goes.
+    for (size, same_size) in by_size.into_iter().rev().filter(|(_, c)| c.len() > 1) {
+        let by_partial = group_by(same_size, |c| {
+            sha256_reader(&mut File::open(&c.path)?.take(PARTIAL_HASH_BYTES))
+        })?;
+        for (partial, candidates) in by_partial.into_iter().filter(|(_, c)| c.len() > 1) {
+            // Files no longer than the partial hash were already hashed in full.
+            let by_full = if size <= PARTIAL_HASH_BYTES {
+                BTreeMap::from([(partial, candidates)])
+            } else {
+                group_by(candidates, |c| sha256_file(&c.path))?
+            };
+            for (sha256, mut set) in by_full.into_iter().filter(|(_, c)| c.len() > 1) {
+                sort_for_keep(&mut set, opts.keep);
+                let keep = &set[0].path;
+                for dup in &set[1..] {
+                    if !opts.dry_run {
+                        apply(opts.action, keep, &dup.path)?;
+                    }
+                }
+                let wasted = size * (set.len() as u64 - 1);
+                report.total_wasted += wasted;
+                report.sets.push(DuplicateSet {
+                    size,
+                    sha256,
+                    keep: keep.to_str().unwrap_or_default().to_owned(),
+                    duplicates: set[1..].iter().map(|c| c.path.to_str().unwrap_or_default().to_owned()).collect(),
+                    wasted,
+                });
+            }
+        }
+    }
+
+    match opts.format {
+        OutputFormat::Json => to_json(&report),
+        OutputFormat::Text => Ok(render_text(&report, opts.color)),
+    }
+}
+
+fn group_by(
+    candidates: Vec<Candidate>,
+    key: impl Fn(&Candidate) -> Result<String>,
+) -> Result<BTreeMap<String, Vec<Candidate>>> {
+    let mut groups: BTreeMap<String, Vec<Candidate>> = BTreeMap::new();
+    for c in candidates {
+        let k = key(&c).map_err(|e| anyhow!("{}: {}", c.path.display(), e))?;
+        groups.entry(k).or_default().push(c);
+    }
+    Ok(groups)
+}
+
+/// Put the file to keep first; ties fall back to the path so runs are stable.
+fn sort_for_keep(set: &mut [Candidate], keep: KeepPolicy) {
+    match keep {
+        KeepPolicy::Oldest => set.sort_by(|a, b| a.mtime.cmp(&b.mtime).then_with(|| a.path.cmp(&b.path))),
+        KeepPolicy::Newest => set.sort_by(|a, b| b.mtime.cmp(&a.mtime).then_with(|| a.path.cmp(&b.path))),
+        KeepPolicy::ShortestPath => set.sort_by(|a, b| {
+            let len = |p: &Path| p.as_os_str().len();
+            len(&a.path).cmp(&len(&b.path)).then_with(|| a.path.cmp(&b.path))
+        }),
+    }
+}
+
+/// Replace or remove `dup`. Links and clones are made under a temporary name
+/// and renamed over `dup`, so it is never missing.
+fn apply(action: DedupeAction, keep: &Path, dup: &Path) -> Result<()> {
+    let dir = match dup.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    match action {
+        DedupeAction::Report => {}
+        DedupeAction::Delete => fs::remove_file(dup)?,
+        DedupeAction::Hardlink => {
+            let tmp = tempfile::Builder::new()
+                .prefix(".filey-")
+                .suffix(".tmp")
+                .make_in(dir, |p| fs::hard_link(keep, p))?;
+            tmp.persist(dup).map_err(|e| e.error)?;
+        }
+        DedupeAction::Reflink => {
+            let tmp = tempfile::Builder::new().prefix(".filey-").suffix(".tmp").tempfile_in(dir)?;
+            let src = File::open(keep)?;
+            // SAFETY: both descriptors are open for the duration of the call.
+            let rc = unsafe { libc::ioctl(tmp.as_file().as_raw_fd(), libc::FICLONE, src.as_raw_fd()) };
+            if rc != 0 {
+                let err = io::Error::last_os_error();
+                return Err(anyhow!("Cannot reflink {} to {}: {}", keep.display(), dup.display(), err));
+            }
+            tmp.as_file().set_permissions(fs::metadata(dup)?.permissions())?;
+            tmp.persist(dup).map_err(|e| e.error)?;
+        }
+    }
+    Ok(())
+}
+
+fn render_text(report: &Report, color: bool) -> String {
+    let mut lines = Vec::new();
+    let verb = match report.action {
+        DedupeAction::Report => "duplicate",
+        DedupeAction::Hardlink => "hardlink",
+        DedupeAction::Reflink => "reflink",
+        DedupeAction::Delete => "delete",
+    };
+    for set in &report.sets {
+        lines.push(paint(
+            &format!(
+                "{} copies of {} bytes, {} bytes wasted (sha256 {})",
+                set.duplicates.len() + 1,
+                set.size,
+                set.wasted,
+                &set.sha256[..12]
+            ),
+            Color::Cyan,
+            color,
+        ));
+        lines.push(format!("  keep {}", set.keep));
+        lines.extend(set.duplicates.iter().map(|d| paint(&format!("  {} {}", verb, d), Color::Yellow, color)));
+    }
+
+    let files: usize = report.sets.iter().map(|s| s.duplicates.len()).sum();
+    let summary = match (report.action, report.dry_run) {
+        (DedupeAction::Report, _) => format!(
+            "Found {} duplicate sets, {} bytes wasted",
+            report.sets.len(),
+            report.total_wasted
+        ),
+        (_, true) => format!("Would {} {} files, reclaiming {} bytes", verb, files, report.total_wasted),
+        (_, false) => format!(
+            "Deduplicated {} files successfully, reclaimed {} bytes",
+            files, report.total_wasted
+        ),
+    };
+    lines.push(summary);
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::time::Duration;
+
+    fn nlink(path: &Path) -> u64 {
+        fs::metadata(path).unwrap().nlink()
+    }
+
+    fn setup(dir: &Path) -> Vec<PathBuf> {
+        let big = "x".repeat(10_000);
+        let mut big_other = big.clone();
+        big_other.push('y');
+        big_other.remove(0);
+        let files = [
+            ("a/one.txt", "same text", 300),
+            ("b/one-copy.txt", "same text", 100),
+            ("c.txt", "same text", 200),
+            ("diff.txt", "other txt", 100),
+            ("big1.bin", big.as_str(), 100),
+            ("deeper/big2.bin", big.as_str(), 200),
+            // same size and same first block, different tail
+            ("big3.bin", big_other.as_str(), 100),
+            ("empty1", "", 100),
+            ("empty2", "", 100),
+        ];
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        files
+            .iter()
+            .map(|(f, contents, age)| {
+                let p = dir.join(f);
+                fs::create_dir_all(p.parent().unwrap()).unwrap();
+                fs::write(&p, contents).unwrap();
+                File::options()
+                    .write(true)
+                    .open(&p)
+                    .unwrap()
+                    .set_modified(epoch - Duration::from_secs(*age))
+                    .unwrap();
+                p
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dedupe_files() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            opts: DedupeOptions,
+            result: Result<&'a str>,
+        }
+
+        let tests = &[
+            // successes
+            TestData {
+                opts: DedupeOptions::default(),
+                result: Ok("Found 2 duplicate sets, 10018 bytes wasted"),
+            },
+            TestData {
+                opts: DedupeOptions::default(),
+                result: Ok("keep a/one.txt\n  duplicate c.txt\n  duplicate b/one-copy.txt"),
+            },
+            TestData {
+                opts: DedupeOptions {
+                    keep: KeepPolicy::Newest,
+                    ..Default::default()
+                },
+                result: Ok("keep b/one-copy.txt\n  duplicate c.txt\n  duplicate a/one.txt"),
+            },
+            TestData {
+                opts: DedupeOptions {
+                    keep: KeepPolicy::ShortestPath,
+                    ..Default::default()
+                },
+                result: Ok("keep c.txt\n  duplicate a/one.txt\n  duplicate b/one-copy.txt"),
+            },
+            TestData {
+                opts: DedupeOptions {
+                    format: OutputFormat::Json,
+                    ..Default::default()
+                },
+                result: Ok("\"total_wasted\": 10018"),
+            },
+            TestData {
+                opts: DedupeOptions {
+                    action: DedupeAction::Delete,
+                    dry_run: true,
+                    ..Default::default()
+                },
+                result: Ok("Would delete 3 files, reclaiming 10018 bytes"),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let binding = TempDir::new().unwrap();
+            let test_dir = binding.to_path_buf();
+            defer!(binding.close().unwrap());
+            let files = setup(&test_dir);
+
+            let actual = dedupe_files(&files, &d.opts).map(|out| out.replace(&format!("{}/", test_dir.display()), ""));
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+            match &d.result {
+                Ok(expected) => assert!(actual.unwrap().contains(expected), "{}", msg),
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+            // nothing was changed
+            assert!(files.iter().all(|f| f.exists()), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn test_dedupe_actions() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        let files = setup(&test_dir);
+
+        let opts = DedupeOptions {
+            action: DedupeAction::Hardlink,
+            ..Default::default()
+        };
+        let out = dedupe_files(&files, &opts).unwrap();
+        assert!(out.contains("Deduplicated 3 files successfully, reclaimed 10018 bytes"), "{}", out);
+        assert_eq!(nlink(&test_dir.join("a/one.txt")), 3);
+        assert_eq!(fs::read_to_string(test_dir.join("c.txt")).unwrap(), "same text");
+        assert_eq!(nlink(&test_dir.join("big3.bin")), 1);
+
+        // already linked files are not reported again
+        let out = dedupe_files(&files, &DedupeOptions::default()).unwrap();
+        assert!(out.contains("Found 0 duplicate sets"), "{}", out);
+
+        let opts = DedupeOptions {
+            action: DedupeAction::Delete,
+            ..Default::default()
+        };
+        fs::write(test_dir.join("diff-copy.txt"), "other txt").unwrap();
+        let pair = [test_dir.join("diff.txt"), test_dir.join("diff-copy.txt")];
+        dedupe_files(&pair, &opts).unwrap();
+        assert!(pair[0].exists());
+        assert!(!pair[1].exists());
+
+        let err = dedupe_files(&[test_dir.join("missing")], &opts).unwrap_err();
+        assert!(err.to_string().contains("No such file"), "{}", err);
+    }
+}
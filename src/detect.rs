@@ -0,0 +1,426 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::output::{paint, to_json, Color, OutputFormat};
+
+/// How many leading bytes are inspected to identify a file.
+pub const SNIFF_BYTES: u64 = 8192;
+
+/// Broad kinds of content, usable as `--type` filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Image,
+    Audio,
+    Video,
+    Archive,
+    Compressed,
+    Executable,
+    Document,
+    Text,
+    Binary,
+    Empty,
+}
+
+/// What a file's contents look like, regardless of its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FileType {
+    pub description: &'static str,
+    pub mime: &'static str,
+    pub category: Category,
+    /// Extensions files of this type usually have. Empty means any will do.
+    #[serde(skip)]
+    pub extensions: &'static [&'static str],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<&'static str>,
+}
+
+struct Signature {
+    /// Every (offset, bytes) pair must match.
+    magic: &'static [(usize, &'static [u8])],
+    kind: FileType,
+}
+
+const fn binary(
+    description: &'static str,
+    mime: &'static str,
+    category: Category,
+    extensions: &'static [&'static str],
+) -> FileType {
+    FileType {
+        description,
+        mime,
+        category,
+        extensions,
+        encoding: None,
+    }
+}
+
+const fn text(description: &'static str, encoding: &'static str) -> FileType {
+    FileType {
+        description,
+        mime: "text/plain",
+        category: Category::Text,
+        extensions: &[],
+        encoding: Some(encoding),
+    }
+}
+
+const GIF: FileType = binary("GIF image", "image/gif", Category::Image, &["gif"]);
+const TIFF: FileType = binary("TIFF image", "image/tiff", Category::Image, &["tif", "tiff"]);
+const ZIP: FileType = binary(
+    "Zip archive",
+    "application/zip",
+    Category::Archive,
+    &["zip", "jar", "war", "apk", "whl", "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub"],
+);
+
+pub const GZIP: FileType = binary("gzip compressed data", "application/gzip", Category::Compressed, &["gz", "tgz"]);
+pub const ZSTD: FileType = binary(
+    "Zstandard compressed data",
+    "application/zstd",
+    Category::Compressed,
+    &["zst", "tzst"],
+);
+pub const XZ: FileType = binary("XZ compressed data", "application/x-xz", Category::Compressed, &["xz", "txz"]);
+
+const EMPTY: FileType = FileType {
+    description: "empty",
+    mime: "inode/x-empty",
+    category: Category::Empty,
+    extensions: &[],
+    encoding: None,
+};
+const DATA: FileType = FileType {
+    description: "data",
+    mime: "application/octet-stream",
+    category: Category::Binary,
+    extensions: &[],
+    encoding: Some("binary"),
+};
+
+/// Checked in order; the first match wins.
+const SIGNATURES: &[Signature] = &[
+    Signature {
+        magic: &[(0, b"\x89PNG\r\n\x1a\n")],
+        kind: binary("PNG image", "image/png", Category::Image, &["png"]),
+    },
+    Signature {
+        magic: &[(0, &[0xff, 0xd8, 0xff])],
+        kind: binary("JPEG image", "image/jpeg", Category::Image, &["jpg", "jpeg", "jpe"]),
+    },
+    Signature {
+        magic: &[(0, b"GIF87a")],
+        kind: GIF,
+    },
+    Signature {
+        magic: &[(0, b"GIF89a")],
+        kind: GIF,
+    },
+    Signature {
+        magic: &[(0, b"RIFF"), (8, b"WEBP")],
+        kind: binary("WebP image", "image/webp", Category::Image, &["webp"]),
+    },
+    Signature {
+        magic: &[(0, b"II*\0")],
+        kind: TIFF,
+    },
+    Signature {
+        magic: &[(0, b"MM\0*")],
+        kind: TIFF,
+    },
+    Signature {
+        magic: &[(0, &[0, 0, 1, 0])],
+        kind: binary("Windows icon", "image/vnd.microsoft.icon", Category::Image, &["ico"]),
+    },
+    Signature {
+        magic: &[(0, b"%PDF-")],
+        kind: binary("PDF document", "application/pdf", Category::Document, &["pdf"]),
+    },
+    Signature {
+        magic: &[(0, b"SQLite format 3\0")],
+        kind: binary(
+            "SQLite database",
+            "application/vnd.sqlite3",
+            Category::Document,
+            &["sqlite", "sqlite3", "db"],
+        ),
+    },
+    Signature {
+        magic: &[(0, b"PK\x03\x04")],
+        kind: ZIP,
+    },
+    Signature {
+        magic: &[(0, b"PK\x05\x06")],
+        kind: ZIP,
+    },
+    Signature {
+        magic: &[(257, b"ustar")],
+        kind: binary("tar archive", "application/x-tar", Category::Archive, &["tar"]),
+    },
+    Signature {
+        magic: &[(0, b"7z\xbc\xaf\x27\x1c")],
+        kind: binary("7-zip archive", "application/x-7z-compressed", Category::Archive, &["7z"]),
+    },
+    Signature {
+        magic: &[(0, b"Rar!\x1a\x07")],
+        kind: binary("RAR archive", "application/vnd.rar", Category::Archive, &["rar"]),
+    },
+    Signature {
+        magic: &[(0, &[0x1f, 0x8b])],
+        kind: GZIP,
+    },
+    Signature {
+        magic: &[(0, &[0x28, 0xb5, 0x2f, 0xfd])],
+        kind: ZSTD,
+    },
+    Signature {
+        magic: &[(0, &[0xfd, b'7', b'z', b'X', b'Z', 0x00])],
+        kind: XZ,
+    },
+    Signature {
+        magic: &[(0, b"BZh")],
+        kind: binary("bzip2 compressed data", "application/x-bzip2", Category::Compressed, &["bz2", "tbz2"]),
+    },
+    Signature {
+        magic: &[(0, b"age-encryption.org/v1\n")],
+        kind: binary("age encrypted data", "application/x-age-encrypted", Category::Binary, &["age"]),
+    },
+    Signature {
+        magic: &[(0, b"\x7fELF")],
+        kind: binary("ELF executable", "application/x-elf", Category::Executable, &["so", "o", "ko", "elf"]),
+    },
+    Signature {
+        magic: &[(0, b"\0asm")],
+        kind: binary("WebAssembly module", "application/wasm", Category::Executable, &["wasm"]),
+    },
+    Signature {
+        magic: &[(0, b"MZ")],
+        kind: binary(
+            "Windows executable",
+            "application/vnd.microsoft.portable-executable",
+            Category::Executable,
+            &["exe", "dll", "sys"],
+        ),
+    },
+    Signature {
+        magic: &[(0, b"ID3")],
+        kind: binary("MP3 audio", "audio/mpeg", Category::Audio, &["mp3"]),
+    },
+    Signature {
+        magic: &[(0, b"fLaC")],
+        kind: binary("FLAC audio", "audio/flac", Category::Audio, &["flac"]),
+    },
+    Signature {
+        magic: &[(0, b"RIFF"), (8, b"WAVE")],
+        kind: binary("WAVE audio", "audio/wav", Category::Audio, &["wav"]),
+    },
+    Signature {
+        magic: &[(0, b"OggS")],
+        kind: binary("Ogg media", "audio/ogg", Category::Audio, &["ogg", "oga", "ogv", "opus"]),
+    },
+    Signature {
+        magic: &[(4, b"ftyp")],
+        kind: binary("MPEG-4 media", "video/mp4", Category::Video, &["mp4", "m4a", "m4v", "mov"]),
+    },
+    Signature {
+        magic: &[(0, &[0x1a, 0x45, 0xdf, 0xa3])],
+        kind: binary("Matroska media", "video/x-matroska", Category::Video, &["mkv", "webm"]),
+    },
+];
+
+/// Identify content from its first bytes, ideally `SNIFF_BYTES` of them.
+pub fn detect_bytes(head: &[u8]) -> FileType {
+    if head.is_empty() {
+        return EMPTY;
+    }
+    let matches = |s: &Signature| {
+        s.magic
+            .iter()
+            .all(|(offset, bytes)| head.get(*offset..offset + bytes.len()) == Some(bytes))
+    };
+    if let Some(s) = SIGNATURES.iter().find(|s| matches(s)) {
+        return s.kind;
+    }
+    if head.starts_with(&[0xef, 0xbb, 0xbf]) && !head.contains(&0) {
+        return text("UTF-8 text with BOM", "utf-8");
+    }
+    if head.starts_with(&[0xff, 0xfe]) {
+        return text("UTF-16LE text", "utf-16le");
+    }
+    if head.starts_with(&[0xfe, 0xff]) {
+        return text("UTF-16BE text", "utf-16be");
+    }
+    // A multi-byte character may be cut off at the end of the sample.
+    let valid = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() as u64 == SNIFF_BYTES,
+    };
+    if valid && !head.contains(&0) {
+        return if head.is_ascii() {
+            text("ASCII text", "us-ascii")
+        } else {
+            text("UTF-8 text", "utf-8")
+        };
+    }
+    DATA
+}
+
+/// Identify a file from its first bytes.
+pub fn detect_file(path: &Path) -> Result<FileType> {
+    let mut head = Vec::new();
+    File::open(path)?.take(SNIFF_BYTES).read_to_end(&mut head)?;
+    Ok(detect_bytes(&head))
+}
+
+/// A warning when `path`'s extension belongs to some other type than `kind`.
+/// Extensions no known type claims are fine for text and unrecognized data.
+pub fn extension_mismatch(path: &Path, kind: &FileType) -> Option<String> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    if kind.extensions.contains(&ext.as_str()) {
+        return None;
+    }
+    let claimed = SIGNATURES.iter().any(|s| s.kind.extensions.contains(&ext.as_str()));
+    if kind.extensions.is_empty() && !claimed {
+        return None;
+    }
+    Some(format!("extension .{} does not match {}", ext, kind.description))
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    path: String,
+    #[serde(flatten)]
+    kind: FileType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warning: Option<String>,
+}
+
+/// Report the detected type of each file, flagging misleading extensions.
+pub fn type_files(files: &[PathBuf], color: bool, format: OutputFormat) -> Result<String> {
+    let entries = files
+        .iter()
+        .map(|path| {
+            let kind = detect_file(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+            Ok(Entry {
+                path: path.to_str().unwrap_or_default().to_owned(),
+                warning: extension_mismatch(path, &kind),
+                kind,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if format == OutputFormat::Json {
+        return to_json(&entries);
+    }
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|e| {
+            let mime = match e.kind.encoding {
+                Some(enc) if e.kind.category == Category::Text => format!("{}; charset={}", e.kind.mime, enc),
+                _ => e.kind.mime.to_owned(),
+            };
+            let mut line = format!("{}: {} ({})", e.path, e.kind.description, mime);
+            if let Some(w) = &e.warning {
+                line.push_str(&paint(&format!(" warning: {}", w), Color::Yellow, color));
+            }
+            line
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_detect_bytes() {
+        let mut tar = vec![0u8; 512];
+        tar[..8].copy_from_slice(b"file.txt");
+        tar[257..262].copy_from_slice(b"ustar");
+        let mut long_utf8 = "é".repeat(SNIFF_BYTES as usize / 2).into_bytes();
+        long_utf8.truncate(SNIFF_BYTES as usize - 1);
+        long_utf8.insert(0, b'a');
+
+        let tests: &[(&[u8], &str, Category)] = &[
+            (b"", "inode/x-empty", Category::Empty),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "image/png", Category::Image),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", "image/jpeg", Category::Image),
+            (b"RIFF\0\0\0\0WEBPVP8 ", "image/webp", Category::Image),
+            (b"RIFF\0\0\0\0WAVEfmt ", "audio/wav", Category::Audio),
+            (b"%PDF-1.7\n", "application/pdf", Category::Document),
+            (b"PK\x03\x04\x14\0", "application/zip", Category::Archive),
+            (&tar, "application/x-tar", Category::Archive),
+            (b"\x1f\x8b\x08\0", "application/gzip", Category::Compressed),
+            (b"BZh91AY&SY", "application/x-bzip2", Category::Compressed),
+            (b"\x7fELF\x02\x01\x01", "application/x-elf", Category::Executable),
+            (b"\0\0\0\x18ftypmp42", "video/mp4", Category::Video),
+            (b"plain ascii\n", "text/plain", Category::Text),
+            (&long_utf8, "text/plain", Category::Text),
+            (b"\xff\xfeh\0i\0", "text/plain", Category::Text),
+            (b"\x00\x01\x02\x03", "application/octet-stream", Category::Binary),
+            (b"caf\xe9", "application/octet-stream", Category::Binary),
+        ];
+        for (i, (bytes, mime, category)) in tests.iter().enumerate() {
+            let actual = detect_bytes(bytes);
+            assert_eq!((actual.mime, actual.category), (*mime, *category), "test[{}]: {:?}", i, actual);
+        }
+        assert_eq!(detect_bytes(b"caf\xc3\xa9").encoding, Some("utf-8"));
+        assert_eq!(detect_bytes(b"cafe").encoding, Some("us-ascii"));
+    }
+
+    #[test]
+    fn test_extension_mismatch() {
+        let png = detect_bytes(b"\x89PNG\r\n\x1a\n");
+        let text = detect_bytes(b"hello");
+        let tests = [
+            ("photo.png", png, None),
+            ("photo.PNG", png, None),
+            ("photo", png, None),
+            ("photo.jpg", png, Some("extension .jpg does not match PNG image")),
+            ("notes.txt", text, None),
+            ("notes.whatever", text, None),
+            ("fake.pdf", text, Some("extension .pdf does not match ASCII text")),
+        ];
+        for (i, (name, kind, expected)) in tests.iter().enumerate() {
+            let actual = extension_mismatch(Path::new(name), kind);
+            assert_eq!(actual.as_deref(), *expected, "test[{}]: {}", i, name);
+        }
+    }
+
+    #[test]
+    fn test_type_files() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let real = test_dir.join("real.gz");
+        let fake = test_dir.join("fake.png");
+        std::fs::write(&real, [0x1f, 0x8b, 8, 0]).unwrap();
+        std::fs::write(&fake, "just text").unwrap();
+
+        let out = type_files(&[real.clone(), fake.clone()], false, OutputFormat::Text).unwrap();
+        assert!(out.contains("real.gz: gzip compressed data (application/gzip)\n"), "{}", out);
+        assert!(
+            out.contains("fake.png: ASCII text (text/plain; charset=us-ascii) warning: extension .png does not match"),
+            "{}",
+            out
+        );
+
+        let out = type_files(std::slice::from_ref(&fake), false, OutputFormat::Json).unwrap();
+        assert!(out.contains("\"category\": \"text\""), "{}", out);
+        assert!(out.contains("\"warning\""), "{}", out);
+
+        let err = type_files(&[test_dir.join("missing")], false, OutputFormat::Text).unwrap_err();
+        assert!(err.to_string().contains("No such file"), "{}", err);
+    }
+}
@@ -0,0 +1,479 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use similar::TextDiff;
+use std::{
+    collections::BTreeMap,
+    fs::{self, Metadata},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use walkdir::WalkDir;
+
+use crate::checksum::sha256_file;
+use crate::output::{paint, to_json, Color, OutputFormat};
+use crate::replace::paint_diff_line;
+
+/// Options for `diff_paths`.
+#[derive(Debug)]
+pub struct DiffOptions {
+    /// Lines of context around each hunk of a text diff.
+    pub context: usize,
+    /// Compare directory entries by content hash instead of size and mtime.
+    pub checksum: bool,
+    pub color: bool,
+    pub format: OutputFormat,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            context: 3,
+            checksum: false,
+            color: false,
+            format: OutputFormat::Text,
+        }
+    }
+}
+
+/// A run of differing bytes in a binary comparison.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct Range {
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Change {
+    path: String,
+    reason: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Report {
+    Text {
+        a: String,
+        b: String,
+        identical: bool,
+        diff: String,
+    },
+    Binary {
+        a: String,
+        b: String,
+        identical: bool,
+        size_a: u64,
+        size_b: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        first_difference: Option<u64>,
+        differing_bytes: u64,
+        ranges: Vec<Range>,
+    },
+    Directory {
+        a: String,
+        b: String,
+        identical: bool,
+        compare: &'static str,
+        added: Vec<String>,
+        removed: Vec<String>,
+        changed: Vec<Change>,
+    },
+}
+
+impl Report {
+    fn identical(&self) -> bool {
+        match self {
+            Report::Text { identical, .. } | Report::Binary { identical, .. } | Report::Directory { identical, .. } => {
+                *identical
+            }
+        }
+    }
+}
+
+/// Compare two files or two directories. Returns the rendered report and
+/// whether any difference was found, so the caller can exit like `diff(1)`.
+pub fn diff_paths(a: &Path, b: &Path, opts: &DiffOptions) -> Result<(String, bool)> {
+    let meta_a = fs::metadata(a).map_err(|e| anyhow!("{}: {}", a.display(), e))?;
+    let meta_b = fs::metadata(b).map_err(|e| anyhow!("{}: {}", b.display(), e))?;
+
+    let report = match (meta_a.is_dir(), meta_b.is_dir()) {
+        (true, true) => diff_dirs(a, b, opts.checksum)?,
+        (false, false) => diff_files(a, b, opts.context)?,
+        _ => return Err(anyhow!("Cannot compare a file with a directory")),
+    };
+
+    let out = match opts.format {
+        OutputFormat::Json => to_json(&report)?,
+        OutputFormat::Text => render_text(&report, opts.color),
+    };
+    Ok((out, !report.identical()))
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes.contains(&0) || std::str::from_utf8(bytes).is_err()
+}
+
+fn diff_files(a: &Path, b: &Path, context: usize) -> Result<Report> {
+    let bytes_a = fs::read(a).map_err(|e| anyhow!("{}: {}", a.display(), e))?;
+    let bytes_b = fs::read(b).map_err(|e| anyhow!("{}: {}", b.display(), e))?;
+    let (name_a, name_b) = (a.to_str().unwrap_or_default(), b.to_str().unwrap_or_default());
+    let identical = bytes_a == bytes_b;
+
+    if is_binary(&bytes_a) || is_binary(&bytes_b) {
+        let ranges = differing_ranges(&bytes_a, &bytes_b);
+        return Ok(Report::Binary {
+            a: name_a.to_owned(),
+            b: name_b.to_owned(),
+            identical,
+            size_a: bytes_a.len() as u64,
+            size_b: bytes_b.len() as u64,
+            first_difference: ranges.first().map(|r| r.offset),
+            differing_bytes: ranges.iter().map(|r| r.length).sum(),
+            ranges,
+        });
+    }
+
+    // Both were checked to be UTF-8 above.
+    let (text_a, text_b) = (String::from_utf8(bytes_a)?, String::from_utf8(bytes_b)?);
+    let diff = if identical {
+        String::new()
+    } else {
+        TextDiff::from_lines(&text_a, &text_b)
+            .unified_diff()
+            .context_radius(context)
+            .header(name_a, name_b)
+            .to_string()
+    };
+    Ok(Report::Text {
+        a: name_a.to_owned(),
+        b: name_b.to_owned(),
+        identical,
+        diff,
+    })
+}
+
+/// Runs of positions where `a` and `b` differ. Bytes past the end of the
+/// shorter input all count as different.
+fn differing_ranges(a: &[u8], b: &[u8]) -> Vec<Range> {
+    let mut ranges: Vec<Range> = Vec::new();
+    let common = a.len().min(b.len());
+    let mut push = |offset: u64, length: u64| match ranges.last_mut() {
+        Some(last) if last.offset + last.length == offset => last.length += length,
+        _ => ranges.push(Range { offset, length }),
+    };
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y {
+            push(i as u64, 1);
+        }
+    }
+    let longest = a.len().max(b.len());
+    if longest > common {
+        push(common as u64, (longest - common) as u64);
+    }
+    ranges
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    meta: Metadata,
+}
+
+fn kind(meta: &Metadata) -> &'static str {
+    let t = meta.file_type();
+    if t.is_dir() {
+        "directory"
+    } else if t.is_symlink() {
+        "symlink"
+    } else if t.is_file() {
+        "file"
+    } else {
+        "special"
+    }
+}
+
+/// Every entry below `root`, keyed by its path relative to `root`.
+/// Symlinks are listed, not followed.
+fn list_tree(root: &Path) -> Result<BTreeMap<PathBuf, Entry>> {
+    let mut entries = BTreeMap::new();
+    for entry in WalkDir::new(root).min_depth(1) {
+        let entry = entry?;
+        let rel = entry.path().strip_prefix(root)?.to_path_buf();
+        let meta = entry.metadata()?;
+        entries.insert(rel, Entry { path: entry.into_path(), meta });
+    }
+    Ok(entries)
+}
+
+fn changed_reason(a: &Entry, b: &Entry, checksum: bool) -> Result<Option<&'static str>> {
+    let (kind_a, kind_b) = (kind(&a.meta), kind(&b.meta));
+    if kind_a != kind_b {
+        return Ok(Some("type"));
+    }
+    Ok(match kind_a {
+        "symlink" => (fs::read_link(&a.path)? != fs::read_link(&b.path)?).then_some("target"),
+        "file" if a.meta.len() != b.meta.len() => Some("size"),
+        "file" if checksum => (sha256_file(&a.path)? != sha256_file(&b.path)?).then_some("content"),
+        "file" => {
+            let mtime = |m: &Metadata| m.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            (mtime(&a.meta) != mtime(&b.meta)).then_some("mtime")
+        }
+        _ => None,
+    })
+}
+
+fn diff_dirs(a: &Path, b: &Path, checksum: bool) -> Result<Report> {
+    let tree_a = list_tree(a)?;
+    let tree_b = list_tree(b)?;
+    let display = |p: &Path, e: &Entry| {
+        let mut s = p.to_str().unwrap_or_default().to_owned();
+        if e.meta.is_dir() {
+            s.push('/');
+        }
+        s
+    };
+
+    // Only the top of an added or removed subtree is reported.
+    let only_in = |this: &BTreeMap<PathBuf, Entry>, other: &BTreeMap<PathBuf, Entry>| {
+        let mut roots: Vec<&Path> = Vec::new();
+        let mut out = Vec::new();
+        for (p, e) in this {
+            if other.contains_key(p) || roots.iter().any(|r| p.starts_with(r)) {
+                continue;
+            }
+            if e.meta.is_dir() {
+                roots.push(p);
+            }
+            out.push(display(p, e));
+        }
+        out
+    };
+    let added = only_in(&tree_b, &tree_a);
+    let removed = only_in(&tree_a, &tree_b);
+
+    let mut changed = Vec::new();
+    for (p, ea) in &tree_a {
+        let Some(eb) = tree_b.get(p) else { continue };
+        if let Some(reason) = changed_reason(ea, eb, checksum)? {
+            changed.push(Change {
+                path: display(p, eb),
+                reason,
+            });
+        }
+    }
+
+    Ok(Report::Directory {
+        a: a.to_str().unwrap_or_default().to_owned(),
+        b: b.to_str().unwrap_or_default().to_owned(),
+        identical: added.is_empty() && removed.is_empty() && changed.is_empty(),
+        compare: if checksum { "checksum" } else { "metadata" },
+        added,
+        removed,
+        changed,
+    })
+}
+
+/// How many binary ranges the text report lists before summarizing.
+const MAX_TEXT_RANGES: usize = 10;
+
+fn render_text(report: &Report, color: bool) -> String {
+    let mut lines = Vec::new();
+    match report {
+        Report::Text { diff, .. } => {
+            lines.extend(diff.lines().map(|l| paint_diff_line(l, color)));
+        }
+        Report::Binary {
+            a,
+            b,
+            identical: false,
+            size_a,
+            size_b,
+            first_difference,
+            differing_bytes,
+            ranges,
+        } => {
+            lines.push(format!("Binary files {} and {} differ", a, b));
+            lines.push(format!("sizes: {} and {} bytes", size_a, size_b));
+            if let Some(first) = first_difference {
+                lines.push(format!("first difference at byte {}", first));
+            }
+            lines.push(format!("{} bytes differ in {} ranges", differing_bytes, ranges.len()));
+            for r in ranges.iter().take(MAX_TEXT_RANGES) {
+                lines.push(paint(&format!("  {}: {} bytes", r.offset, r.length), Color::Yellow, color));
+            }
+            if ranges.len() > MAX_TEXT_RANGES {
+                lines.push(format!("  ... {} more ranges", ranges.len() - MAX_TEXT_RANGES));
+            }
+        }
+        Report::Binary { .. } => {}
+        Report::Directory {
+            added,
+            removed,
+            changed,
+            identical,
+            ..
+        } => {
+            lines.extend(removed.iter().map(|p| paint(&format!("- {}", p), Color::Red, color)));
+            lines.extend(added.iter().map(|p| paint(&format!("+ {}", p), Color::Green, color)));
+            lines.extend(
+                changed
+                    .iter()
+                    .map(|c| paint(&format!("~ {} ({})", c.path, c.reason), Color::Yellow, color)),
+            );
+            if !identical {
+                lines.push(format!(
+                    "{} added, {} removed, {} changed",
+                    added.len(),
+                    removed.len(),
+                    changed.len()
+                ));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_diff_files() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            a: &'a [u8],
+            b: &'a [u8],
+            opts: DiffOptions,
+            differ: bool,
+            result: Result<&'a str>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let tests = &[
+            // successes
+            TestData {
+                a: b"one\ntwo\nthree\n",
+                b: b"one\ntwo\nthree\n",
+                opts: DiffOptions::default(),
+                differ: false,
+                result: Ok(""),
+            },
+            TestData {
+                a: b"one\ntwo\nthree\n",
+                b: b"one\n2\nthree\n",
+                opts: DiffOptions::default(),
+                differ: true,
+                result: Ok("@@ -1,3 +1,3 @@\n one\n-two\n+2\n three"),
+            },
+            TestData {
+                a: b"one\ntwo\nthree\nfour\n",
+                b: b"one\ntwo\nthree\n4\n",
+                opts: DiffOptions {
+                    context: 0,
+                    ..Default::default()
+                },
+                differ: true,
+                result: Ok("@@ -4 +4 @@\n-four\n+4"),
+            },
+            TestData {
+                a: b"\x00\x01\x02\x03\x04\x05",
+                b: b"\x00\xff\xff\x03\x04\x05\x06\x07",
+                opts: DiffOptions::default(),
+                differ: true,
+                result: Ok("first difference at byte 1\n4 bytes differ in 2 ranges\n  1: 2 bytes\n  6: 2 bytes"),
+            },
+            TestData {
+                a: b"\x00\x01",
+                b: b"\x00\x02",
+                opts: DiffOptions {
+                    format: OutputFormat::Json,
+                    ..Default::default()
+                },
+                differ: true,
+                result: Ok("\"kind\": \"binary\""),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let a = test_dir.join(format!("{}.a", i));
+            let b = test_dir.join(format!("{}.b", i));
+            std::fs::write(&a, d.a).unwrap();
+            std::fs::write(&b, d.b).unwrap();
+            let actual = diff_paths(&a, &b, &d.opts);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok(expected) => {
+                    let (out, differ) = actual.unwrap();
+                    assert_eq!(differ, d.differ, "{}", msg);
+                    assert!(out.contains(expected), "{}", msg);
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+
+        // errors
+        let err = diff_paths(&test_dir, &test_dir.join("0.a"), &DiffOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("Cannot compare"), "{}", err);
+        let err = diff_paths(&test_dir.join("missing"), &test_dir.join("0.a"), &DiffOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("No such file"), "{}", err);
+    }
+
+    #[test]
+    fn test_diff_dirs() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let (a, b) = (test_dir.join("a"), test_dir.join("b"));
+        for (root, files) in [
+            (&a, &["same.txt", "gone/x.txt", "gone/y.txt", "size.txt", "content.txt"][..]),
+            (&b, &["same.txt", "new.txt", "size.txt", "content.txt"][..]),
+        ] {
+            for f in files {
+                let p = root.join(f);
+                std::fs::create_dir_all(p.parent().unwrap()).unwrap();
+                std::fs::write(&p, "same").unwrap();
+            }
+        }
+        std::fs::write(b.join("size.txt"), "longer").unwrap();
+        std::fs::write(b.join("content.txt"), "diff").unwrap();
+        // line up mtimes so only content tells the files apart
+        let mtime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        for root in [&a, &b] {
+            for f in ["same.txt", "content.txt"] {
+                fs::File::options().write(true).open(root.join(f)).unwrap().set_modified(mtime).unwrap();
+            }
+        }
+
+        let (out, differ) = diff_paths(&a, &b, &DiffOptions::default()).unwrap();
+        assert!(differ);
+        assert_eq!(out, "- gone/\n+ new.txt\n~ size.txt (size)\n1 added, 1 removed, 1 changed");
+
+        let opts = DiffOptions {
+            checksum: true,
+            ..Default::default()
+        };
+        let (out, _) = diff_paths(&a, &b, &opts).unwrap();
+        assert!(out.contains("~ content.txt (content)\n~ size.txt (size)"), "{}", out);
+
+        let (out, differ) = diff_paths(&a, &a, &opts).unwrap();
+        assert!(!differ);
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn test_differing_ranges() {
+        assert_eq!(differing_ranges(b"abc", b"abc"), vec![]);
+        assert_eq!(
+            differing_ranges(b"abcdef", b"aXXdeY"),
+            vec![Range { offset: 1, length: 2 }, Range { offset: 5, length: 1 }]
+        );
+        assert_eq!(differing_ranges(b"ab", b"abcd"), vec![Range { offset: 2, length: 2 }]);
+        assert_eq!(differing_ranges(b"aXcd", b"aY"), vec![Range { offset: 1, length: 3 }]);
+    }
+}
@@ -0,0 +1,481 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, Metadata},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::cmd::format_size;
+use crate::info::user_name;
+use crate::output::{csv_row, to_json};
+
+/// How `du` renders its report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DuFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+/// Which breakdown `du` reports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuView {
+    /// Every directory down to a depth.
+    #[default]
+    Directories,
+    /// The N largest files and the N largest directories.
+    Top(usize),
+    ByExtension,
+    ByOwner,
+}
+
+/// Options for `disk_usage`.
+#[derive(Debug, Default)]
+pub struct DuOptions {
+    pub view: DuView,
+    /// Deepest directory level listed in the directories view; `None` lists all.
+    pub max_depth: Option<usize>,
+    /// Rank and sort by apparent size instead of allocated size.
+    pub apparent: bool,
+    /// Print sizes like `1.5K` in text output.
+    pub human: bool,
+    pub format: DuFormat,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    path: PathBuf,
+    depth: usize,
+    files: u64,
+    apparent: u64,
+    allocated: u64,
+    children: Vec<Node>,
+}
+
+#[derive(Debug)]
+struct FileStat {
+    path: PathBuf,
+    uid: u32,
+    apparent: u64,
+    allocated: u64,
+}
+
+/// State shared by the walker threads.
+#[derive(Default)]
+struct Scan {
+    /// Inodes with several links that were already counted.
+    seen: Mutex<HashSet<(u64, u64)>>,
+    files: Mutex<Vec<FileStat>>,
+    errors: Mutex<Vec<String>>,
+}
+
+impl Scan {
+    /// Whether an entry's size should be counted: hard-linked inodes count
+    /// only for the first path the walk reaches.
+    fn first_sighting(&self, meta: &Metadata) -> bool {
+        meta.nlink() <= 1 || self.seen.lock().unwrap().insert((meta.dev(), meta.ino()))
+    }
+
+    fn error(&self, path: &Path, e: impl std::fmt::Display) {
+        self.errors.lock().unwrap().push(format!("{}: {}", path.display(), e));
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Row {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+    name: String,
+    files: u64,
+    apparent: u64,
+    allocated: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    view: &'static str,
+    total: Row,
+    rows: Vec<Row>,
+    errors: Vec<String>,
+}
+
+/// Measure the apparent and allocated size of `paths`. Directories are
+/// walked in parallel; symlinks are not followed, and a file with several
+/// hard links is counted once, under whichever of its paths is seen first.
+/// Unreadable entries are reported and skipped.
+pub fn disk_usage(paths: &[PathBuf], opts: &DuOptions) -> Result<String> {
+    let scan = Scan::default();
+    let roots = paths
+        .iter()
+        .map(|p| {
+            let meta = fs::symlink_metadata(p).map_err(|e| anyhow!("{}: {}", p.display(), e))?;
+            Ok(measure(p, &meta, 0, &scan))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let total = Row {
+        kind: None,
+        name: "total".to_owned(),
+        files: roots.iter().map(|r| r.files).sum(),
+        apparent: roots.iter().map(|r| r.apparent).sum(),
+        allocated: roots.iter().map(|r| r.allocated).sum(),
+    };
+    let files = scan.files.into_inner().unwrap();
+    let size = |apparent: u64, allocated: u64| if opts.apparent { apparent } else { allocated };
+
+    let (view, label, mut rows) = match opts.view {
+        DuView::Directories => {
+            let mut rows = Vec::new();
+            for root in &roots {
+                directory_rows(root, opts.max_depth, &mut rows);
+            }
+            ("directories", "path", rows)
+        }
+        DuView::Top(n) => {
+            let mut largest: Vec<&FileStat> = files.iter().collect();
+            largest.sort_by_key(|f| std::cmp::Reverse(size(f.apparent, f.allocated)));
+            let mut dirs = Vec::new();
+            for root in &roots {
+                directory_rows(root, None, &mut dirs);
+            }
+            dirs.sort_by_key(|d| std::cmp::Reverse(size(d.apparent, d.allocated)));
+            let rows = largest
+                .into_iter()
+                .take(n)
+                .map(|f| Row {
+                    kind: Some("file"),
+                    name: f.path.to_str().unwrap_or_default().to_owned(),
+                    files: 1,
+                    apparent: f.apparent,
+                    allocated: f.allocated,
+                })
+                .chain(dirs.into_iter().take(n).map(|d| Row {
+                    kind: Some("directory"),
+                    ..d
+                }))
+                .collect();
+            // Already ranked within each kind.
+            return render(opts, "top", "path", total, rows, scan.errors.into_inner().unwrap());
+        }
+        DuView::ByExtension => {
+            let key = |f: &FileStat| match f.path.extension() {
+                Some(ext) => ext.to_string_lossy().to_lowercase(),
+                None => "(none)".to_owned(),
+            };
+            ("extensions", "extension", group(&files, key))
+        }
+        DuView::ByOwner => {
+            let key = |f: &FileStat| user_name(f.uid).unwrap_or_else(|| f.uid.to_string());
+            ("owners", "owner", group(&files, key))
+        }
+    };
+    if opts.view != DuView::Directories {
+        rows.sort_by(|a, b| size(b.apparent, b.allocated).cmp(&size(a.apparent, a.allocated)).then_with(|| a.name.cmp(&b.name)));
+    }
+    render(opts, view, label, total, rows, scan.errors.into_inner().unwrap())
+}
+
+/// Size up one path, descending into directories in parallel.
+fn measure(path: &Path, meta: &Metadata, depth: usize, scan: &Scan) -> Node {
+    let mut node = Node {
+        path: path.to_path_buf(),
+        depth,
+        ..Default::default()
+    };
+    if scan.first_sighting(meta) {
+        node.apparent = meta.len();
+        node.allocated = meta.blocks() * 512;
+    }
+    if !meta.is_dir() {
+        node.files = 1;
+        scan.files.lock().unwrap().push(FileStat {
+            path: path.to_path_buf(),
+            uid: meta.uid(),
+            apparent: node.apparent,
+            allocated: node.allocated,
+        });
+        return node;
+    }
+
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            scan.error(path, e);
+            return node;
+        }
+    };
+    let mut subdirs = Vec::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => {
+                scan.error(path, e);
+                continue;
+            }
+        };
+        let child = entry.path();
+        match entry.metadata() {
+            Ok(m) if m.is_dir() => subdirs.push((child, m)),
+            Ok(m) => {
+                let leaf = measure(&child, &m, depth + 1, scan);
+                node.files += leaf.files;
+                node.apparent += leaf.apparent;
+                node.allocated += leaf.allocated;
+            }
+            Err(e) => scan.error(&child, e),
+        }
+    }
+
+    node.children = subdirs
+        .par_iter()
+        .map(|(child, m)| measure(child, m, depth + 1, scan))
+        .collect();
+    node.children.sort_by(|a, b| a.path.cmp(&b.path));
+    for child in &node.children {
+        node.files += child.files;
+        node.apparent += child.apparent;
+        node.allocated += child.allocated;
+    }
+    node
+}
+
+/// Directory rows in `du` order: each directory after its subdirectories.
+fn directory_rows(node: &Node, max_depth: Option<usize>, rows: &mut Vec<Row>) {
+    for child in &node.children {
+        directory_rows(child, max_depth, rows);
+    }
+    if max_depth.is_none_or(|d| node.depth <= d) {
+        rows.push(Row {
+            kind: None,
+            name: node.path.to_str().unwrap_or_default().to_owned(),
+            files: node.files,
+            apparent: node.apparent,
+            allocated: node.allocated,
+        });
+    }
+}
+
+fn group(files: &[FileStat], key: impl Fn(&FileStat) -> String) -> Vec<Row> {
+    let mut groups: BTreeMap<String, Row> = BTreeMap::new();
+    for f in files {
+        let k = key(f);
+        let row = groups.entry(k.clone()).or_insert(Row {
+            kind: None,
+            name: k,
+            files: 0,
+            apparent: 0,
+            allocated: 0,
+        });
+        row.files += 1;
+        row.apparent += f.apparent;
+        row.allocated += f.allocated;
+    }
+    groups.into_values().collect()
+}
+
+fn render(
+    opts: &DuOptions,
+    view: &'static str,
+    label: &str,
+    total: Row,
+    rows: Vec<Row>,
+    errors: Vec<String>,
+) -> Result<String> {
+    match opts.format {
+        DuFormat::Json => to_json(&Report {
+            view,
+            total,
+            rows,
+            errors,
+        }),
+        DuFormat::Csv => {
+            let mut header = vec!["kind", label, "files", "apparent", "allocated"];
+            let with_kind = rows.iter().any(|r| r.kind.is_some());
+            if !with_kind {
+                header.remove(0);
+            }
+            let mut lines = vec![csv_row(&header)];
+            for r in &rows {
+                let mut fields = vec![r.name.clone(), r.files.to_string(), r.apparent.to_string(), r.allocated.to_string()];
+                if with_kind {
+                    fields.insert(0, r.kind.unwrap_or_default().to_owned());
+                }
+                lines.push(csv_row(&fields));
+            }
+            Ok(lines.join("\n"))
+        }
+        DuFormat::Text => {
+            let size = |n: u64| if opts.human { format_size(n) } else { n.to_string() };
+            let cells: Vec<[String; 3]> = rows
+                .iter()
+                .chain(std::iter::once(&total))
+                .map(|r| [size(r.allocated), size(r.apparent), r.files.to_string()])
+                .collect();
+            let width = |i: usize, title: &str| cells.iter().map(|c| c[i].len()).max().unwrap_or(0).max(title.len());
+            let (wa, wp, wf) = (width(0, "allocated"), width(1, "apparent"), width(2, "files"));
+
+            let mut lines = vec![format!(
+                "{:>wa$}  {:>wp$}  {:>wf$}  {}",
+                "allocated", "apparent", "files", label
+            )];
+            for (r, c) in rows.iter().chain(std::iter::once(&total)).zip(&cells) {
+                let name = match r.kind {
+                    Some(kind) => format!("{} ({})", r.name, kind),
+                    None => r.name.clone(),
+                };
+                lines.push(format!("{:>wa$}  {:>wp$}  {:>wf$}  {}", c[0], c[1], c[2], name));
+            }
+            lines.extend(errors.iter().map(|e| format!("warning: {}", e)));
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    fn setup(dir: &Path) {
+        for (f, size) in [
+            ("a/big.bin", 10_000),
+            ("a/b/small.txt", 100),
+            ("a/b/notes.txt", 300),
+            ("c/README", 50),
+        ] {
+            let p = dir.join(f);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(&p, "x".repeat(size)).unwrap();
+        }
+        // a second name for the same inode must not be counted twice
+        fs::hard_link(dir.join("a/big.bin"), dir.join("c/big-link.bin")).unwrap();
+    }
+
+    fn dirs_size(dir: &Path) -> u64 {
+        ["", "a", "a/b", "c"].iter().map(|d| fs::metadata(dir.join(d)).unwrap().len()).sum()
+    }
+
+    #[test]
+    fn test_disk_usage() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        setup(&test_dir);
+
+        let opts = DuOptions {
+            format: DuFormat::Json,
+            ..Default::default()
+        };
+        let out = disk_usage(std::slice::from_ref(&test_dir), &opts).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(report["total"]["files"], 5);
+        assert_eq!(report["total"]["apparent"], 10_450 + dirs_size(&test_dir));
+        assert!(report["total"]["allocated"].as_u64().unwrap() >= 10_000);
+        // subdirectories come before their parents, the root last
+        let names: Vec<&str> = report["rows"].as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap()).collect();
+        assert_eq!(names.len(), 4);
+        assert!(names[0].ends_with("a/b"), "{:?}", names);
+        assert_eq!(names[3], test_dir.to_str().unwrap());
+
+        let opts = DuOptions {
+            max_depth: Some(0),
+            human: true,
+            ..Default::default()
+        };
+        let out = disk_usage(std::slice::from_ref(&test_dir), &opts).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", out);
+        assert!(lines[0].ends_with("allocated  apparent  files  path"), "{}", out);
+        assert!(lines[2].ends_with("5  total"), "{}", out);
+
+        let err = disk_usage(&[test_dir.join("missing")], &opts).unwrap_err();
+        assert!(err.to_string().contains("No such file"), "{}", err);
+    }
+
+    #[test]
+    fn test_disk_usage_views() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            opts: DuOptions,
+            result: &'a str,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        setup(&test_dir);
+
+        let tests = &[
+            TestData {
+                opts: DuOptions {
+                    view: DuView::ByExtension,
+                    apparent: true,
+                    format: DuFormat::Csv,
+                    ..Default::default()
+                },
+                result: "extension,files,apparent,allocated\nbin,2,10000,",
+            },
+            TestData {
+                opts: DuOptions {
+                    view: DuView::ByExtension,
+                    apparent: true,
+                    format: DuFormat::Csv,
+                    ..Default::default()
+                },
+                result: "\ntxt,2,400,",
+            },
+            TestData {
+                opts: DuOptions {
+                    view: DuView::ByExtension,
+                    apparent: true,
+                    format: DuFormat::Csv,
+                    ..Default::default()
+                },
+                result: "\n(none),1,50,",
+            },
+            TestData {
+                opts: DuOptions {
+                    view: DuView::Top(1),
+                    apparent: true,
+                    format: DuFormat::Csv,
+                    ..Default::default()
+                },
+                result: "kind,path,files,apparent,allocated\nfile,",
+            },
+            TestData {
+                opts: DuOptions {
+                    view: DuView::Top(2),
+                    apparent: true,
+                    ..Default::default()
+                },
+                result: "notes.txt (file)",
+            },
+            TestData {
+                opts: DuOptions {
+                    view: DuView::ByOwner,
+                    format: DuFormat::Json,
+                    ..Default::default()
+                },
+                result: "\"view\": \"owners\"",
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let actual = disk_usage(std::slice::from_ref(&test_dir), &d.opts);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+            assert!(actual.unwrap().contains(d.result), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn test_csv_row() {
+        assert_eq!(csv_row(&["plain", "with,comma", "say \"hi\""]), "plain,\"with,comma\",\"say \"\"hi\"\"\"");
+    }
+}
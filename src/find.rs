@@ -0,0 +1,826 @@
+use anyhow::{anyhow, Result};
+use glob::{MatchOptions, Pattern};
+use regex::{bytes, Regex};
+use std::{
+    ffi::OsString,
+    fs::{self, Metadata},
+    io::Write,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+use crate::cmd::{delete_file, parse_mode, parse_size};
+use crate::info::{group_name, kind_name, user_name};
+use crate::touch::parse_unit;
+use crate::walk::{walk_tree, Filter};
+
+/// Upper bound on the bytes of arguments given to one batched `--exec`
+/// command, well below ARG_MAX on any system, like xargs(1)'s default.
+const MAX_BATCH_BYTES: usize = 128 * 1024;
+
+/// How a number is compared: `+N` is more than N, `-N` less than N, and a
+/// plain `N` exactly N. `A..B` is inclusive at both ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound<T> {
+    Above(T),
+    Below(T),
+    Exactly(T),
+    Between(T, T),
+}
+
+impl<T: PartialOrd> Bound<T> {
+    fn contains(&self, v: T) -> bool {
+        match self {
+            Bound::Above(n) => v > *n,
+            Bound::Below(n) => v < *n,
+            Bound::Exactly(n) => v == *n,
+            Bound::Between(lo, hi) => v >= *lo && v <= *hi,
+        }
+    }
+}
+
+/// An age bound counted in whole units, e.g. days for `7d`. Like find(1),
+/// an entry's age is rounded down to the unit before comparing, so `7d`
+/// matches anything from 7 to just under 8 days old.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Age {
+    pub bound: Bound<u64>,
+    /// The unit's length in seconds.
+    pub unit: u64,
+}
+
+/// How `--perm` compares mode bits: exactly, all of them set (`-MODE`) or
+/// any of them set (`/MODE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermMatch {
+    Exactly(u32),
+    All(u32),
+    Any(u32),
+}
+
+/// A single test on a directory entry.
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    /// Glob against the file name.
+    Name(Pattern),
+    /// Glob against the file name, ignoring case.
+    IName(Pattern),
+    /// Regular expression searched for in the whole path.
+    Regex(Regex),
+    Size(Bound<u64>),
+    /// Age since the last modification.
+    Mtime(Age),
+    /// Age since the last access.
+    Atime(Age),
+    /// One of the names `kind_name` returns, e.g. `file` or `symlink`.
+    Type(&'static str),
+    Perm(PermMatch),
+    /// Owning user, by name or uid.
+    User(String),
+    /// Owning group, by name or gid.
+    Group(String),
+    /// An empty regular file or a directory without entries.
+    Empty,
+    /// Regular expression searched for in the contents of regular files.
+    Contains(bytes::Regex),
+}
+
+/// A boolean combination of predicates.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    True,
+    Test(Predicate),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// What `find` does with each match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum FindAction {
+    /// Print one path per line.
+    #[default]
+    Print,
+    /// Print paths terminated by NUL bytes, for `xargs -0`.
+    Print0,
+    /// Delete matching files, and directories that are empty by then.
+    Delete,
+    /// Run a command; `{}` in its arguments is replaced by the path, or the
+    /// path is appended when there is no `{}`. With `batch` the command runs
+    /// with as many matches at once as fit, like `find -exec … +`, and `{}`
+    /// must be an argument of its own.
+    Exec { argv: Vec<String>, batch: bool },
+}
+
+/// A parsed `find` command line: where to look, what to match and what to do.
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub paths: Vec<PathBuf>,
+    pub expr: Expr,
+    /// `--include`/`--exclude` rules, shared with the other bulk commands.
+    pub filter: Filter,
+    pub action: FindAction,
+}
+
+impl Predicate {
+    fn matches(&self, path: &Path, meta: &Metadata) -> bool {
+        let name = path.file_name().map(Path::new).unwrap_or(path);
+        match self {
+            Predicate::Name(p) => p.matches_path(name),
+            Predicate::IName(p) => {
+                let opts = MatchOptions {
+                    case_sensitive: false,
+                    ..Default::default()
+                };
+                p.matches_path_with(name, opts)
+            }
+            Predicate::Regex(re) => re.is_match(&path.to_string_lossy()),
+            Predicate::Size(b) => b.contains(meta.len()),
+            Predicate::Mtime(a) => a.bound.contains(age(meta.modified()) / a.unit),
+            Predicate::Atime(a) => a.bound.contains(age(meta.accessed()) / a.unit),
+            Predicate::Type(kind) => kind_name(meta.mode()) == *kind,
+            Predicate::Perm(p) => {
+                let mode = meta.permissions().mode() & 0o7777;
+                match p {
+                    PermMatch::Exactly(m) => mode == *m,
+                    PermMatch::All(m) => mode & m == *m,
+                    PermMatch::Any(m) => *m == 0 || mode & m != 0,
+                }
+            }
+            Predicate::User(u) => meta.uid().to_string() == *u || user_name(meta.uid()).as_ref() == Some(u),
+            Predicate::Group(g) => meta.gid().to_string() == *g || group_name(meta.gid()).as_ref() == Some(g),
+            Predicate::Empty => {
+                if meta.is_dir() {
+                    fs::read_dir(path).is_ok_and(|mut d| d.next().is_none())
+                } else {
+                    meta.is_file() && meta.len() == 0
+                }
+            }
+            Predicate::Contains(re) => meta.is_file() && fs::read(path).is_ok_and(|data| re.is_match(&data)),
+        }
+    }
+}
+
+/// Seconds elapsed since `t`; times in the future have age 0.
+fn age(t: std::io::Result<SystemTime>) -> u64 {
+    t.ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+impl Expr {
+    /// Whether the entry at `path`, described by its (not followed)
+    /// metadata, satisfies the expression.
+    pub fn matches(&self, path: &Path, meta: &Metadata) -> bool {
+        match self {
+            Expr::True => true,
+            Expr::Test(p) => p.matches(path, meta),
+            Expr::Not(e) => !e.matches(path, meta),
+            Expr::And(a, b) => a.matches(path, meta) && b.matches(path, meta),
+            Expr::Or(a, b) => a.matches(path, meta) || b.matches(path, meta),
+        }
+    }
+}
+
+/// Parse `[PATH]... [EXPRESSION]` the way find(1) reads its arguments. The
+/// paths are the leading arguments up to the first one that starts the
+/// expression; without any, the current directory is searched. Adjacent
+/// tests are joined with `--and`, which binds tighter than `--or`.
+/// `--print0`, `--delete` and `--exec CMD… ;` may appear anywhere in the
+/// expression and apply to every match, as may `--include` and `--exclude`
+/// globs, which work as for the other bulk commands.
+pub fn parse_query(args: &[String]) -> Result<Query> {
+    let starts_expr = |a: &String| a.starts_with('-') || a == "(" || a == ")" || a == "!";
+    let split = args.iter().position(starts_expr).unwrap_or(args.len());
+    let mut paths: Vec<PathBuf> = args[..split].iter().map(PathBuf::from).collect();
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let mut tokens = Vec::new();
+    let mut action = None;
+    let (mut include, mut exclude) = (Vec::new(), Vec::new());
+    let mut rest = args[split..].iter();
+    while let Some(arg) = rest.next() {
+        let next = match arg.as_str() {
+            "--include" | "--exclude" => {
+                let glob = rest.next().ok_or_else(|| anyhow!("{} needs a value", arg))?;
+                if arg == "--include" { &mut include } else { &mut exclude }.push(glob.clone());
+                continue;
+            }
+            "--print0" => FindAction::Print0,
+            "--delete" => FindAction::Delete,
+            "--exec" => {
+                let mut argv = Vec::new();
+                let batch = loop {
+                    match rest.next().map(String::as_str) {
+                        Some(";") => break false,
+                        Some("+") => break true,
+                        Some(a) => argv.push(a.to_owned()),
+                        None => return Err(anyhow!("--exec must be terminated by ';' or '+'")),
+                    }
+                };
+                if argv.is_empty() {
+                    return Err(anyhow!("--exec needs a command"));
+                }
+                if batch && argv.iter().any(|a| a != "{}" && a.contains("{}")) {
+                    return Err(anyhow!("--exec … + only accepts {{}} as an argument of its own"));
+                }
+                FindAction::Exec { argv, batch }
+            }
+            _ => {
+                tokens.push(arg.as_str());
+                continue;
+            }
+        };
+        if action.replace(next).is_some() {
+            return Err(anyhow!("Only one of --print0, --delete and --exec may be given"));
+        }
+    }
+
+    Ok(Query {
+        paths,
+        expr: parse_expr(&tokens)?,
+        filter: Filter::new(&include, &exclude)?,
+        action: action.unwrap_or_default(),
+    })
+}
+
+/// Parse a predicate expression such as
+/// `--name '*.rs' --or ( --size +1M --not --empty )`. Other commands take
+/// one through `Filter::with_expr`.
+pub fn parse_expr(tokens: &[&str]) -> Result<Expr> {
+    if tokens.is_empty() {
+        return Ok(Expr::True);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    match parser.peek() {
+        Some(")") => Err(anyhow!("Unmatched ')' in expression")),
+        Some(t) => Err(anyhow!("Unexpected {} in expression", t)),
+        None => Ok(expr),
+    }
+}
+
+/// Recursive descent over the expression tokens:
+///
+/// ```text
+/// or    := and ( "--or" and )*
+/// and   := unary ( "--and"? unary )*
+/// unary := ( "--not" | "!" ) unary | "(" or ")" | test
+/// ```
+struct Parser<'a> {
+    tokens: &'a [&'a str],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let t = self.peek();
+        self.pos += 1;
+        t
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while matches!(self.peek(), Some("--or" | "-o")) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        loop {
+            match self.peek() {
+                Some("--and" | "-a") => self.pos += 1,
+                None | Some(")" | "--or" | "-o") => return Ok(expr),
+                Some(_) => {}
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            None => Err(anyhow!("Expression ends unexpectedly")),
+            Some("--not" | "!") => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some("(") => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(")") => Ok(expr),
+                    _ => Err(anyhow!("Missing ')' in expression")),
+                }
+            }
+            Some(flag) => self.test(flag).map(Expr::Test),
+        }
+    }
+
+    fn test(&mut self, flag: &str) -> Result<Predicate> {
+        if flag == "--empty" {
+            return Ok(Predicate::Empty);
+        }
+        let value = self.next().ok_or_else(|| anyhow!("{} needs a value", flag))?;
+        let glob = |g: &str| Pattern::new(g).map_err(|e| anyhow!("Invalid glob {}: {}", g, e));
+        Ok(match flag {
+            "--name" => Predicate::Name(glob(value)?),
+            "--iname" => Predicate::IName(glob(value)?),
+            "--regex" => Predicate::Regex(Regex::new(value).map_err(|e| anyhow!("Invalid regex {}: {}", value, e))?),
+            "--contains" => Predicate::Contains(
+                bytes::Regex::new(value).map_err(|e| anyhow!("Invalid regex {}: {}", value, e))?,
+            ),
+            "--size" => Predicate::Size(parse_bound(value, parse_size)?),
+            "--mtime" => Predicate::Mtime(parse_age(value)?),
+            "--atime" => Predicate::Atime(parse_age(value)?),
+            "--type" => Predicate::Type(parse_type(value)?),
+            "--perm" => Predicate::Perm(match value.chars().next() {
+                Some('-') => PermMatch::All(parse_mode(&value[1..])?),
+                Some('/') => PermMatch::Any(parse_mode(&value[1..])?),
+                _ => PermMatch::Exactly(parse_mode(value)?),
+            }),
+            "--user" => Predicate::User(value.to_owned()),
+            "--group" => Predicate::Group(value.to_owned()),
+            _ => return Err(anyhow!("Unknown test {}", flag)),
+        })
+    }
+}
+
+/// Parse `+N`, `-N`, `N` or `A..B`, reading each number with `parse`.
+fn parse_bound(s: &str, parse: fn(&str) -> Result<u64>) -> Result<Bound<u64>> {
+    if let Some((lo, hi)) = s.split_once("..") {
+        let (lo, hi) = (parse(lo)?, parse(hi)?);
+        if lo > hi {
+            return Err(anyhow!("Invalid range {}: start is past the end", s));
+        }
+        return Ok(Bound::Between(lo, hi));
+    }
+    Ok(match s.chars().next() {
+        Some('+') => Bound::Above(parse(&s[1..])?),
+        Some('-') => Bound::Below(parse(&s[1..])?),
+        _ => Bound::Exactly(parse(s)?),
+    })
+}
+
+/// Parse an age bound such as `+30m`, `-2h`, `7d` or `1d..3d`, accepting
+/// the units `parse_date` takes for relative dates. A range whose ends use
+/// different units is counted in the smaller one.
+fn parse_age(s: &str) -> Result<Age> {
+    let invalid = || anyhow!("Invalid age: {}", s);
+    let count = |part: &str| -> Result<(u64, u64)> {
+        let split = part.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let (num, unit) = part.split_at(split);
+        let unit = parse_unit(unit.trim()).ok_or_else(invalid)?.num_seconds() as u64;
+        Ok((num.parse().map_err(|_| invalid())?, unit))
+    };
+
+    if let Some((lo, hi)) = s.split_once("..") {
+        let ((lo, lo_unit), (hi, hi_unit)) = (count(lo)?, count(hi)?);
+        let unit = lo_unit.min(hi_unit);
+        // Every unit is a whole multiple of the smaller ones.
+        let lo = lo.checked_mul(lo_unit / unit).ok_or_else(invalid)?;
+        let hi = hi.checked_mul(hi_unit / unit).ok_or_else(invalid)?;
+        if lo > hi {
+            return Err(anyhow!("Invalid range {}: start is past the end", s));
+        }
+        return Ok(Age {
+            bound: Bound::Between(lo, hi),
+            unit,
+        });
+    }
+    let (bound, rest): (fn(u64) -> Bound<u64>, &str) = match s.chars().next() {
+        Some('+') => (Bound::Above, &s[1..]),
+        Some('-') => (Bound::Below, &s[1..]),
+        _ => (Bound::Exactly, s),
+    };
+    let (n, unit) = count(rest)?;
+    Ok(Age { bound: bound(n), unit })
+}
+
+fn parse_type(s: &str) -> Result<&'static str> {
+    Ok(match s {
+        "f" | "file" => "file",
+        "d" | "dir" | "directory" => "directory",
+        "l" | "symlink" => "symlink",
+        "p" | "fifo" => "fifo",
+        "s" | "socket" => "socket",
+        "c" => "character device",
+        "b" => "block device",
+        _ => return Err(anyhow!("Invalid type {}: expected one of f, d, l, p, s, c, b", s)),
+    })
+}
+
+/// Walk `paths` without following symlinks and collect every entry,
+/// including the starting points themselves, that `filter` allows and that
+/// satisfies `expr`. Entries are sorted by name within each directory; with
+/// `contents_first` a directory comes after everything inside it. Entries
+/// that cannot be read are reported on stderr and skipped; their number is
+/// returned alongside the matches.
+pub fn find_paths(paths: &[PathBuf], expr: &Expr, filter: &Filter, contents_first: bool) -> Result<(Vec<PathBuf>, usize)> {
+    let mut found = Vec::new();
+    let mut errors = 0;
+    for path in paths {
+        let meta = fs::symlink_metadata(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        // Like find -P, a symlink given as a root is examined itself rather
+        // than followed, so a dangling one is still found.
+        if meta.is_symlink() {
+            if filter.allows_path(path) && expr.matches(path, &meta) {
+                found.push(path.clone());
+            }
+            continue;
+        }
+        for entry in walk_tree(path, filter, contents_first) {
+            let (entry, meta) = match entry.and_then(|e| e.metadata().map(|m| (e, m))) {
+                Ok(found) => found,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    errors += 1;
+                    continue;
+                }
+            };
+            // Directories are subject only to the exclude rules, which the
+            // walk has already applied.
+            let allowed = match meta.file_type() {
+                t if t.is_dir() => true,
+                t if t.is_file() => filter.allows_file(entry.path()),
+                _ => filter.allows_path(entry.path()),
+            };
+            if allowed && expr.matches(entry.path(), &meta) {
+                found.push(entry.into_path());
+            }
+        }
+    }
+    Ok((found, errors))
+}
+
+/// Run `query` and apply its action to every match. Returns the output and
+/// whether some entries could not be read or deleted.
+pub fn find(query: &Query) -> Result<(String, bool)> {
+    let delete = query.action == FindAction::Delete;
+    let (found, mut errors) = find_paths(&query.paths, &query.expr, &query.filter, delete)?;
+    let display = |p: &PathBuf| p.to_str().unwrap_or_default().to_owned();
+
+    let out = match &query.action {
+        FindAction::Print => found.iter().map(display).collect::<Vec<_>>().join("\n"),
+        FindAction::Print0 => {
+            // Written directly: the caller would add a trailing newline.
+            let mut out = std::io::stdout().lock();
+            for p in &found {
+                out.write_all(p.as_os_str().as_encoded_bytes())?;
+                out.write_all(b"\0")?;
+            }
+            out.flush()?;
+            String::new()
+        }
+        FindAction::Delete => {
+            let mut msgs = Vec::new();
+            for p in &found {
+                // A directory that still has contents is reported and left
+                // in place; the other matches are deleted all the same.
+                let res = match fs::symlink_metadata(p) {
+                    Ok(meta) if meta.is_dir() => fs::remove_dir(p)
+                        .map(|_| format!("Deleted directory successfully: {}", display(p)))
+                        .map_err(anyhow::Error::from),
+                    Ok(_) => delete_file(p),
+                    Err(e) => Err(e.into()),
+                };
+                match res {
+                    Ok(msg) => msgs.push(msg),
+                    Err(e) => {
+                        eprintln!("Error: {}: {}", p.display(), e);
+                        errors += 1;
+                    }
+                }
+            }
+            msgs.join("\n")
+        }
+        FindAction::Exec { argv, batch } => {
+            let runs = if *batch { batches(argv, &found) } else { found.chunks(1).collect() };
+            let mut failed = 0;
+            for paths in &runs {
+                if !exec(argv, paths)? {
+                    failed += 1;
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow!("{} failed for {} of {} runs", argv[0], failed, runs.len()));
+            }
+            String::new()
+        }
+    };
+    Ok((out, errors > 0))
+}
+
+/// Split `paths` into runs of `argv` whose arguments stay within
+/// `MAX_BATCH_BYTES`, counting each argument's pointer and terminator too.
+fn batches<'a>(argv: &[String], paths: &'a [PathBuf]) -> Vec<&'a [PathBuf]> {
+    let cost = |len: usize| len + 1 + std::mem::size_of::<usize>();
+    let base: usize = argv.iter().map(|a| cost(a.len())).sum();
+    let mut runs = Vec::new();
+    let (mut start, mut size) = (0, base);
+    for (i, p) in paths.iter().enumerate() {
+        let len = cost(p.as_os_str().len());
+        if i > start && size + len > MAX_BATCH_BYTES {
+            runs.push(&paths[start..i]);
+            (start, size) = (i, base);
+        }
+        size += len;
+    }
+    if start < paths.len() {
+        runs.push(&paths[start..]);
+    }
+    runs
+}
+
+/// Run `argv` with `{}` replaced by `paths`, inheriting stdio. Inside a
+/// longer argument, e.g. `{}.bak`, `{}` stands for the single path of a
+/// non-batched run. Returns whether the command exited successfully.
+fn exec(argv: &[String], paths: &[PathBuf]) -> Result<bool> {
+    let mut args = Vec::new();
+    let mut substituted = false;
+    for a in &argv[1..] {
+        if a == "{}" {
+            args.extend(paths.iter().map(|p| p.as_os_str().to_owned()));
+            substituted = true;
+        } else if a.contains("{}") {
+            let mut arg = OsString::new();
+            for (i, part) in a.split("{}").enumerate() {
+                if i > 0 {
+                    arg.push(paths[0].as_os_str());
+                }
+                arg.push(part);
+            }
+            args.push(arg);
+            substituted = true;
+        } else {
+            args.push(a.into());
+        }
+    }
+    if !substituted {
+        args.extend(paths.iter().map(|p| p.as_os_str().to_owned()));
+    }
+    let status = Command::new(&argv[0])
+        .args(&args)
+        .status()
+        .map_err(|e| anyhow!("Cannot run {}: {}", argv[0], e))?;
+    Ok(status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::os::unix::fs::symlink;
+    use std::time::Duration;
+
+    fn setup(dir: &Path) {
+        for (f, contents) in [
+            ("README.md", "# readme\nTODO: write docs\n"),
+            ("src/main.rs", "fn main() {}\n"),
+            ("src/lib.RS", "// TODO\n"),
+            ("src/empty.txt", ""),
+            ("big.bin", &"x".repeat(4096)),
+        ] {
+            let p = dir.join(f);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(&p, contents).unwrap();
+        }
+        fs::create_dir(dir.join("hollow")).unwrap();
+        symlink("src/main.rs", dir.join("link.rs")).unwrap();
+        fs::set_permissions(dir.join("big.bin"), fs::Permissions::from_mode(0o755)).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(10 * 86400);
+        fs::File::options()
+            .write(true)
+            .open(dir.join("big.bin"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_find_paths() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            args: &'a [&'a str],
+            result: Result<&'a [&'a str]>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        setup(&test_dir);
+
+        let tests = &[
+            // failures
+            TestData {
+                args: &["--name"],
+                result: Err(anyhow!("--name needs a value")),
+            },
+            TestData {
+                args: &["--bogus", "x"],
+                result: Err(anyhow!("Unknown test --bogus")),
+            },
+            TestData {
+                args: &["(", "--empty"],
+                result: Err(anyhow!("Missing ')'")),
+            },
+            TestData {
+                args: &["--empty", ")"],
+                result: Err(anyhow!("Unmatched ')'")),
+            },
+            TestData {
+                args: &["--size", "10M..1K"],
+                result: Err(anyhow!("start is past the end")),
+            },
+            TestData {
+                args: &["--type", "q"],
+                result: Err(anyhow!("Invalid type q")),
+            },
+            TestData {
+                args: &["--mtime", "+3fortnights"],
+                result: Err(anyhow!("Invalid age")),
+            },
+            TestData {
+                args: &["--exec", "echo"],
+                result: Err(anyhow!("terminated by ';' or '+'")),
+            },
+            TestData {
+                args: &["--delete", "--print0"],
+                result: Err(anyhow!("Only one of")),
+            },
+            TestData {
+                args: &["--exec", "mv", "{}", "{}.bak", "+"],
+                result: Err(anyhow!("as an argument of its own")),
+            },
+            TestData {
+                args: &["--mtime", "2d..1h"],
+                result: Err(anyhow!("start is past the end")),
+            },
+            // successes
+            TestData {
+                args: &["--name", "*.rs"],
+                result: Ok(&["link.rs", "src/main.rs"]),
+            },
+            TestData {
+                args: &["--iname", "*.rs", "--type", "f"],
+                result: Ok(&["src/lib.RS", "src/main.rs"]),
+            },
+            TestData {
+                args: &["--regex", "src/.*\\.txt$"],
+                result: Ok(&["src/empty.txt"]),
+            },
+            TestData {
+                args: &["--empty"],
+                result: Ok(&["hollow", "src/empty.txt"]),
+            },
+            TestData {
+                args: &["--type", "f", "--size", "+1K", "--or", "--type", "l"],
+                result: Ok(&["big.bin", "link.rs"]),
+            },
+            TestData {
+                args: &["--type", "f", "--size", "1..20"],
+                result: Ok(&["src/lib.RS", "src/main.rs"]),
+            },
+            TestData {
+                args: &["--type", "f", "--mtime", "+7d"],
+                result: Ok(&["big.bin"]),
+            },
+            TestData {
+                args: &["--type", "f", "--not", "(", "--mtime", "+7d", "--or", "--empty", ")", "--mtime", "-1h"],
+                result: Ok(&["README.md", "src/lib.RS", "src/main.rs"]),
+            },
+            // ages are rounded down to whole units
+            TestData {
+                args: &["--type", "f", "--mtime", "10d"],
+                result: Ok(&["big.bin"]),
+            },
+            TestData {
+                args: &["--type", "f", "--mtime", "1w..240h"],
+                result: Ok(&["big.bin"]),
+            },
+            TestData {
+                args: &["--type", "f", "--mtime", "0d"],
+                result: Ok(&["README.md", "src/empty.txt", "src/lib.RS", "src/main.rs"]),
+            },
+            TestData {
+                args: &["--type", "f", "--exclude", "src"],
+                result: Ok(&["README.md", "big.bin"]),
+            },
+            TestData {
+                args: &["--include", "*.md", "--type", "f"],
+                result: Ok(&["README.md"]),
+            },
+            TestData {
+                args: &["--perm", "/111", "--type", "f"],
+                result: Ok(&["big.bin"]),
+            },
+            TestData {
+                args: &["--contains", "TODO"],
+                result: Ok(&["README.md", "src/lib.RS"]),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let mut args = vec![test_dir.to_str().unwrap().to_owned()];
+            args.extend(d.args.iter().map(|s| s.to_string()));
+            let actual = parse_query(&args).and_then(|q| find_paths(&q.paths, &q.expr, &q.filter, false));
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok(expected) => {
+                    let (found, errors) = actual.unwrap();
+                    assert_eq!(errors, 0, "{}", msg);
+                    let actual: Vec<PathBuf> = found
+                        .iter()
+                        .map(|p| p.strip_prefix(&test_dir).unwrap().to_path_buf())
+                        .collect();
+                    let expected: Vec<PathBuf> = expected.iter().map(PathBuf::from).collect();
+                    assert_eq!(actual, expected, "{}", msg);
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_actions() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        setup(&test_dir);
+        let args = |a: &[&str]| {
+            let mut args = vec![test_dir.to_str().unwrap().to_owned()];
+            args.extend(a.iter().map(|s| s.to_string()));
+            parse_query(&args).unwrap()
+        };
+
+        let err = find(&args(&["--exec", "test", "-s", "{}", ";", "--name", "*.txt"])).unwrap_err();
+        assert!(err.to_string().contains("test failed for 1 of 1 runs"), "{}", err);
+        find(&args(&["--name", "*.rs", "--type", "f", "--exec", "ls", "{}", "+"])).unwrap();
+
+        find(&args(&["--name", "README.md", "--exec", "cp", "{}", "{}.bak", ";"])).unwrap();
+        assert!(test_dir.join("README.md.bak").exists());
+
+        // the directory is deleted after its contents, so it is empty by then
+        let (out, _) = find(&args(&["--regex", "/src(/|$)", "--delete"])).unwrap();
+        assert!(out.ends_with(&format!("Deleted directory successfully: {}", test_dir.join("src").display())), "{}", out);
+        assert!(!test_dir.join("src").exists());
+        assert!(test_dir.join("README.md").exists());
+
+        // a directory that is not empty is reported and the rest still go
+        for f in ["a.tmp", "b.tmp/inner.dat", "c.tmp"] {
+            let path = test_dir.join("tmp").join(f);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let (out, failed) = find(&args(&["--name", "*.tmp", "--delete"])).unwrap();
+        assert!(failed, "{}", out);
+        assert!(test_dir.join("tmp/b.tmp/inner.dat").exists());
+        assert!(!test_dir.join("tmp/a.tmp").exists());
+        assert!(!test_dir.join("tmp/c.tmp").exists());
+    }
+
+    #[test]
+    fn test_exec_batches() {
+        let argv = vec!["echo".to_owned()];
+        let paths: Vec<PathBuf> = (0..1000).map(|i| PathBuf::from(format!("{:0>200}", i))).collect();
+        let runs = batches(&argv, &paths);
+        assert!(runs.len() > 1);
+        assert_eq!(runs.iter().map(|r| r.len()).sum::<usize>(), paths.len());
+        for run in &runs {
+            let size: usize = run.iter().map(|p| p.as_os_str().len() + 1 + std::mem::size_of::<usize>()).sum();
+            assert!(size <= MAX_BATCH_BYTES);
+        }
+        assert!(batches(&argv, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_find_unreadable_dir() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        setup(&test_dir);
+
+        let locked = test_dir.join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(locked.join("hidden.rs"), "").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        defer!(fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap());
+        if fs::read_dir(&locked).is_ok() {
+            // Running as root, which can read anything.
+            return;
+        }
+
+        let query = parse_query(&[test_dir.to_str().unwrap().to_owned(), "--name".to_owned(), "*.rs".to_owned()]).unwrap();
+        let (out, failed) = find(&query).unwrap();
+        assert!(failed);
+        assert!(out.contains("src/main.rs"), "{}", out);
+    }
+}
@@ -0,0 +1,359 @@
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::detect::{detect_bytes, Category, SNIFF_BYTES};
+use crate::output::{paint, to_json, Color, OutputFormat};
+
+/// Options for `grep_files`.
+#[derive(Debug, Default)]
+pub struct GrepOptions {
+    pub ignore_case: bool,
+    /// Treat the pattern as a literal string rather than a regular expression.
+    pub fixed: bool,
+    /// Lines of context to show before each match.
+    pub before: usize,
+    /// Lines of context to show after each match.
+    pub after: usize,
+    /// Only report the number of matching lines per file.
+    pub count: bool,
+    /// Only report the names of files that match.
+    pub files_only: bool,
+    /// Search files that look binary as if they were text.
+    pub binary: bool,
+    /// Prefix lines with their file name.
+    pub with_filename: bool,
+    pub color: bool,
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct LineMatch {
+    line: usize,
+    text: String,
+    /// Byte ranges of each match within `text`.
+    ranges: Vec<(usize, usize)>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    before: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    after: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct FileMatches {
+    path: String,
+    /// Number of matching lines.
+    count: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    matches: Vec<LineMatch>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    files: Vec<FileMatches>,
+    files_matched: usize,
+    total_matches: usize,
+    binary_skipped: usize,
+}
+
+/// Search `files` for lines matching `pattern`, several files at a time.
+/// Files whose contents look binary are skipped unless `opts.binary` is
+/// set. A file that cannot be read is reported on stderr and the search
+/// goes on. Returns the rendered report, whether anything matched and
+/// whether some file could not be read.
+pub fn grep_files(pattern: &str, files: &[PathBuf], opts: &GrepOptions) -> Result<(String, bool, bool)> {
+    let pattern = if opts.fixed {
+        regex::escape(pattern)
+    } else {
+        pattern.to_owned()
+    };
+    let re = RegexBuilder::new(&pattern)
+        .case_insensitive(opts.ignore_case)
+        .build()
+        .map_err(|e| anyhow!("Invalid pattern: {}", e))?;
+
+    let results: Vec<Result<Option<FileMatches>>> = files.par_iter().map(|path| search_file(&re, path, opts)).collect();
+
+    let mut report = Report {
+        files: Vec::new(),
+        files_matched: 0,
+        total_matches: 0,
+        binary_skipped: 0,
+    };
+    let mut failed = false;
+    for result in results {
+        match result {
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                failed = true;
+            }
+            Ok(None) => report.binary_skipped += 1,
+            Ok(Some(file)) if file.count > 0 => {
+                report.files_matched += 1;
+                report.total_matches += file.count;
+                report.files.push(file);
+            }
+            Ok(Some(_)) => {}
+        }
+    }
+
+    let found = report.files_matched > 0;
+    let out = match opts.format {
+        OutputFormat::Json => to_json(&report)?,
+        OutputFormat::Text => render_text(&report, opts),
+    };
+    Ok((out, found, failed))
+}
+
+/// Search one file, or return `None` when it is skipped as binary.
+fn search_file(re: &Regex, path: &Path, opts: &GrepOptions) -> Result<Option<FileMatches>> {
+    let data = std::fs::read(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    let head = &data[..data.len().min(SNIFF_BYTES as usize)];
+    if !opts.binary && !matches!(detect_bytes(head).category, Category::Text | Category::Empty) {
+        return Ok(None);
+    }
+
+    let text = String::from_utf8_lossy(&data);
+    let lines: Vec<&str> = text.lines().collect();
+    let mut file = FileMatches {
+        path: path.to_str().unwrap_or_default().to_owned(),
+        count: 0,
+        matches: Vec::new(),
+    };
+    for (i, line) in lines.iter().enumerate() {
+        let ranges: Vec<(usize, usize)> = re.find_iter(line).map(|m| (m.start(), m.end())).collect();
+        if ranges.is_empty() {
+            continue;
+        }
+        file.count += 1;
+        if opts.count || opts.files_only {
+            continue;
+        }
+        let context = |from: usize, to: usize| lines[from..to].iter().map(|l| l.to_string()).collect();
+        file.matches.push(LineMatch {
+            line: i + 1,
+            text: line.to_string(),
+            ranges,
+            before: context(i.saturating_sub(opts.before), i),
+            after: context(i + 1, (i + 1 + opts.after).min(lines.len())),
+        });
+    }
+    Ok(Some(file))
+}
+
+/// Render matches like grep(1): `path:line:text` for matching lines,
+/// `path-line-text` for context, and `--` between separate groups.
+fn render_text(report: &Report, opts: &GrepOptions) -> String {
+    let mut out = Vec::new();
+    let context = opts.before > 0 || opts.after > 0;
+    for file in &report.files {
+        let path = paint(&file.path, Color::Cyan, opts.color);
+        if opts.files_only {
+            out.push(path);
+            continue;
+        }
+        if opts.count {
+            out.push(format!("{}:{}", path, file.count));
+            continue;
+        }
+
+        let prefix = |line: usize, sep: char| {
+            let n = paint(&line.to_string(), Color::Green, opts.color);
+            if opts.with_filename {
+                format!("{}{}{}{}", path, sep, n, sep)
+            } else {
+                format!("{}{}", n, sep)
+            }
+        };
+        // The last line printed for this file, so overlapping context is
+        // shown once.
+        let mut printed = 0;
+        for m in &file.matches {
+            let first = m.line - m.before.len();
+            if context && !out.is_empty() && (printed == 0 || first > printed + 1) {
+                out.push("--".to_owned());
+            }
+            for (j, text) in m.before.iter().enumerate() {
+                let n = first + j;
+                if n > printed {
+                    out.push(format!("{}{}", prefix(n, '-'), text));
+                    printed = n;
+                }
+            }
+            if m.line > printed {
+                out.push(format!("{}{}", prefix(m.line, ':'), highlight(&m.text, &m.ranges, opts.color)));
+                printed = m.line;
+            }
+            for (j, text) in m.after.iter().enumerate() {
+                let n = m.line + 1 + j;
+                // A later match inside this context prints itself.
+                if file.matches.iter().any(|o| o.line == n) {
+                    break;
+                }
+                out.push(format!("{}{}", prefix(n, '-'), text));
+                printed = n;
+            }
+        }
+    }
+    out.join("\n")
+}
+
+fn highlight(text: &str, ranges: &[(usize, usize)], color: bool) -> String {
+    if !color {
+        return text.to_owned();
+    }
+    let mut s = String::new();
+    let mut last = 0;
+    for (start, end) in ranges {
+        s.push_str(&text[last..*start]);
+        s.push_str(&paint(&text[*start..*end], Color::Red, true));
+        last = *end;
+    }
+    s.push_str(&text[last..]);
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_grep_files() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            pattern: &'a str,
+            opts: GrepOptions,
+            result: Result<(&'a str, bool)>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let lines: Vec<String> = (1..=12).map(|i| format!("line {}", i)).collect();
+        std::fs::write(test_dir.join("a.txt"), "alpha\nBeta\ngamma beta\n").unwrap();
+        std::fs::write(test_dir.join("b.txt"), lines.join("\n")).unwrap();
+        std::fs::write(test_dir.join("c.bin"), b"\x00\x01beta\x02").unwrap();
+        let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.bin"].iter().map(|f| test_dir.join(f)).collect();
+
+        let tests = &[
+            // failures
+            TestData {
+                pattern: "(",
+                opts: GrepOptions::default(),
+                result: Err(anyhow!("Invalid pattern")),
+            },
+            // successes
+            TestData {
+                pattern: "nothing",
+                opts: GrepOptions::default(),
+                result: Ok(("", false)),
+            },
+            TestData {
+                pattern: "beta",
+                opts: GrepOptions::default(),
+                result: Ok(("3:gamma beta", true)),
+            },
+            TestData {
+                pattern: "beta",
+                opts: GrepOptions {
+                    ignore_case: true,
+                    ..Default::default()
+                },
+                result: Ok(("2:Beta\n3:gamma beta", true)),
+            },
+            TestData {
+                pattern: "a.",
+                opts: GrepOptions {
+                    fixed: true,
+                    ..Default::default()
+                },
+                result: Ok(("", false)),
+            },
+            TestData {
+                pattern: "beta",
+                opts: GrepOptions {
+                    binary: true,
+                    count: true,
+                    ..Default::default()
+                },
+                result: Ok(("a.txt:1\n", true)),
+            },
+            TestData {
+                pattern: "beta",
+                opts: GrepOptions {
+                    binary: true,
+                    files_only: true,
+                    ..Default::default()
+                },
+                result: Ok(("c.bin", true)),
+            },
+            TestData {
+                pattern: "line (3|5|11)$",
+                opts: GrepOptions {
+                    before: 1,
+                    after: 1,
+                    ..Default::default()
+                },
+                result: Ok(("2-line 2\n3:line 3\n4-line 4\n5:line 5\n6-line 6\n--\n10-line 10\n11:line 11\n12-line 12", true)),
+            },
+            TestData {
+                pattern: "line 1$",
+                opts: GrepOptions {
+                    after: 2,
+                    with_filename: true,
+                    ..Default::default()
+                },
+                result: Ok(("b.txt:1:line 1\n", true)),
+            },
+            TestData {
+                pattern: "gamma",
+                opts: GrepOptions {
+                    before: 1,
+                    format: OutputFormat::Json,
+                    ..Default::default()
+                },
+                result: Ok(("\"binary_skipped\": 1", true)),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let actual = grep_files(d.pattern, &files, &d.opts);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok((expected, found)) => {
+                    let (out, actual_found, failed) = actual.unwrap();
+                    assert!(!failed, "{}", msg);
+                    assert!(out.contains(expected), "{}", msg);
+                    assert_eq!(actual_found, *found, "{}", msg);
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+    #[test]
+    fn test_grep_unreadable_file() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        std::fs::write(test_dir.join("a.txt"), "beta\n").unwrap();
+        std::fs::write(test_dir.join("c.txt"), "beta\n").unwrap();
+        let files: Vec<PathBuf> = ["a.txt", "gone.txt", "c.txt"].iter().map(|f| test_dir.join(f)).collect();
+        let opts = GrepOptions {
+            count: true,
+            with_filename: true,
+            ..Default::default()
+        };
+        let (out, found, failed) = grep_files("beta", &files, &opts).unwrap();
+        let out = out.replace(&format!("{}/", test_dir.display()), "");
+        assert_eq!(out, "a.txt:1\nc.txt:1");
+        assert!(found);
+        assert!(failed);
+    }
+}
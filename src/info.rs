@@ -0,0 +1,520 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    ffi::{CStr, CString, OsStr},
+    fs,
+    io,
+    mem::MaybeUninit,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+};
+
+use crate::detect::{detect_file, extension_mismatch};
+use crate::output::{to_json, OutputFormat};
+
+#[derive(Debug, Serialize)]
+struct Info {
+    path: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link_target: Option<String>,
+    size: u64,
+    /// 512-byte blocks actually allocated.
+    blocks: u64,
+    io_block: u32,
+    mode: String,
+    permissions: String,
+    uid: u32,
+    user: Option<String>,
+    gid: u32,
+    group: Option<String>,
+    inode: u64,
+    device: String,
+    links: u32,
+    accessed: Option<String>,
+    modified: Option<String>,
+    changed: Option<String>,
+    born: Option<String>,
+    xattrs: BTreeMap<String, String>,
+    /// Why the extended attributes could not be listed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    xattrs_error: Option<String>,
+    mime: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warning: Option<String>,
+}
+
+/// Describe `path` in detail: type, sizes, ownership, permissions, every
+/// timestamp the filesystem records (including birth time, via statx),
+/// extended attributes and a MIME type guessed from the contents.
+/// Symlinks are described themselves unless `dereference` is set.
+pub fn file_info(path: &Path, dereference: bool, format: OutputFormat) -> Result<String> {
+    let stx = statx(path, dereference).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    let mode = u32::from(stx.stx_mode);
+    let kind = kind_name(mode);
+    let link_target = if kind == "symlink" {
+        Some(fs::read_link(path)?.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+    // A file that cannot be read is still described from its metadata.
+    let (detected, unreadable) = match (kind == "file").then(|| detect_file(path)) {
+        Some(Ok(t)) => (Some(t), None),
+        Some(Err(e)) => (None, Some(format!("cannot read contents: {}", e))),
+        None => (None, None),
+    };
+    let mime = match (kind, &detected) {
+        (_, Some(t)) => t.mime,
+        ("file", _) => "application/octet-stream",
+        ("directory", _) => "inode/directory",
+        ("symlink", _) => "inode/symlink",
+        _ => "inode/x-special",
+    };
+
+    // Like the contents, attributes that cannot be read are only noted.
+    let (xattrs, xattrs_error) = match xattrs(path, dereference) {
+        Ok(attrs) => (attrs, None),
+        Err(e) => (BTreeMap::new(), Some(e.to_string())),
+    };
+
+    let timestamp = |ts: &libc::statx_timestamp, bit: libc::c_uint| {
+        (stx.stx_mask & bit != 0).then(|| format_time(ts.tv_sec, ts.tv_nsec))
+    };
+    let info = Info {
+        path: path.to_str().unwrap_or_default().to_owned(),
+        kind,
+        link_target,
+        size: stx.stx_size,
+        blocks: stx.stx_blocks,
+        io_block: stx.stx_blksize,
+        mode: format!("{:04o}", mode & 0o7777),
+        permissions: symbolic_mode(mode),
+        uid: stx.stx_uid,
+        user: user_name(stx.stx_uid),
+        gid: stx.stx_gid,
+        group: group_name(stx.stx_gid),
+        inode: stx.stx_ino,
+        device: format!("{}:{}", stx.stx_dev_major, stx.stx_dev_minor),
+        links: stx.stx_nlink,
+        accessed: timestamp(&stx.stx_atime, libc::STATX_ATIME),
+        modified: timestamp(&stx.stx_mtime, libc::STATX_MTIME),
+        changed: timestamp(&stx.stx_ctime, libc::STATX_CTIME),
+        born: timestamp(&stx.stx_btime, libc::STATX_BTIME),
+        xattrs,
+        xattrs_error,
+        mime,
+        encoding: detected.and_then(|t| t.encoding),
+        content: detected.map(|t| t.description),
+        warning: unreadable.or_else(|| detected.and_then(|t| extension_mismatch(path, &t))),
+    };
+
+    match format {
+        OutputFormat::Json => to_json(&info),
+        OutputFormat::Text => Ok(render_text(&info)),
+    }
+}
+
+fn statx(path: &Path, dereference: bool) -> io::Result<libc::statx> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let flags = if dereference { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+    let mut buf = MaybeUninit::<libc::statx>::zeroed();
+    // SAFETY: `c_path` is NUL-terminated and `buf` is large enough for the
+    // struct statx(2) fills in.
+    let rc = unsafe {
+        libc::statx(
+            libc::AT_FDCWD,
+            c_path.as_ptr(),
+            flags,
+            libc::STATX_BASIC_STATS | libc::STATX_BTIME,
+            buf.as_mut_ptr(),
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: statx succeeded, and the buffer was zeroed beforehand.
+    Ok(unsafe { buf.assume_init() })
+}
+
+/// A readable name for the file type bits of `mode`.
+pub fn kind_name(mode: u32) -> &'static str {
+    match mode & libc::S_IFMT {
+        libc::S_IFREG => "file",
+        libc::S_IFDIR => "directory",
+        libc::S_IFLNK => "symlink",
+        libc::S_IFIFO => "fifo",
+        libc::S_IFSOCK => "socket",
+        libc::S_IFCHR => "character device",
+        libc::S_IFBLK => "block device",
+        _ => "unknown",
+    }
+}
+
+/// Render a mode like `ls -l` does, e.g. `drwxr-sr-x`.
+pub fn symbolic_mode(mode: u32) -> String {
+    let kind = match mode & libc::S_IFMT {
+        libc::S_IFDIR => 'd',
+        libc::S_IFLNK => 'l',
+        libc::S_IFIFO => 'p',
+        libc::S_IFSOCK => 's',
+        libc::S_IFCHR => 'c',
+        libc::S_IFBLK => 'b',
+        _ => '-',
+    };
+    let mut s = String::from(kind);
+    // (read, write, execute, special bit, special char when executable, when not)
+    let triads = [
+        (0o400, 0o200, 0o100, 0o4000, 's', 'S'),
+        (0o040, 0o020, 0o010, 0o2000, 's', 'S'),
+        (0o004, 0o002, 0o001, 0o1000, 't', 'T'),
+    ];
+    for (r, w, x, special, on, off) in triads {
+        s.push(if mode & r != 0 { 'r' } else { '-' });
+        s.push(if mode & w != 0 { 'w' } else { '-' });
+        s.push(match (mode & x != 0, mode & special != 0) {
+            (true, true) => on,
+            (false, true) => off,
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    s
+}
+
+/// The login name for `uid`, if the user database knows it.
+pub fn user_name(uid: u32) -> Option<String> {
+    let mut pwd = MaybeUninit::<libc::passwd>::zeroed();
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: every pointer refers to a live buffer of the advertised size.
+    let rc = unsafe { libc::getpwuid_r(uid, pwd.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: on success pw_name points into `buf`, which is still alive.
+    Some(unsafe { CStr::from_ptr((*result).pw_name) }.to_string_lossy().into_owned())
+}
+
+/// The name of group `gid`, if the group database knows it.
+pub fn group_name(gid: u32) -> Option<String> {
+    let mut grp = MaybeUninit::<libc::group>::zeroed();
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: every pointer refers to a live buffer of the advertised size.
+    let rc = unsafe { libc::getgrgid_r(gid, grp.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: on success gr_name points into `buf`, which is still alive.
+    Some(unsafe { CStr::from_ptr((*result).gr_name) }.to_string_lossy().into_owned())
+}
+
+/// The uid of the user called `name`, if the user database knows it.
+pub fn user_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let mut pwd = MaybeUninit::<libc::passwd>::zeroed();
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: every pointer refers to a live buffer of the advertised size.
+    let rc = unsafe { libc::getpwnam_r(name.as_ptr(), pwd.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: on success `result` points to `pwd`, which is initialized.
+    Some(unsafe { (*result).pw_uid })
+}
+
+/// The gid of the group called `name`, if the group database knows it.
+pub fn group_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let mut grp = MaybeUninit::<libc::group>::zeroed();
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: every pointer refers to a live buffer of the advertised size.
+    let rc = unsafe { libc::getgrnam_r(name.as_ptr(), grp.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: on success `result` points to `grp`, which is initialized.
+    Some(unsafe { (*result).gr_gid })
+}
+
+fn format_time(secs: i64, nanos: u32) -> String {
+    match Local.timestamp_opt(secs, nanos).single() {
+        Some(t) => t.to_rfc3339_opts(SecondsFormat::Nanos, false),
+        None => DateTime::UNIX_EPOCH.to_rfc3339(),
+    }
+}
+
+/// Extended attributes of `path`. Values that are not UTF-8 are shown as hex,
+/// and values that cannot be read as unavailable. Filesystems without xattr
+/// support simply have none.
+fn xattrs(path: &Path, dereference: bool) -> Result<BTreeMap<String, String>> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let list = |buf: *mut libc::c_char, len: usize| {
+        // SAFETY: `buf` is null with len 0, or a live buffer of `len` bytes.
+        unsafe {
+            if dereference {
+                libc::listxattr(c_path.as_ptr(), buf, len)
+            } else {
+                libc::llistxattr(c_path.as_ptr(), buf, len)
+            }
+        }
+    };
+    let get = |name: &CStr, buf: *mut libc::c_void, len: usize| {
+        // SAFETY: as above, and `name` is NUL-terminated.
+        unsafe {
+            if dereference {
+                libc::getxattr(c_path.as_ptr(), name.as_ptr(), buf, len)
+            } else {
+                libc::lgetxattr(c_path.as_ptr(), name.as_ptr(), buf, len)
+            }
+        }
+    };
+    let unsupported = |e: &io::Error| e.raw_os_error() == Some(libc::ENOTSUP);
+
+    let mut attrs = BTreeMap::new();
+    let size = list(std::ptr::null_mut(), 0);
+    if size < 0 {
+        let err = io::Error::last_os_error();
+        return if unsupported(&err) { Ok(attrs) } else { Err(err.into()) };
+    }
+    let mut names = vec![0u8; size as usize];
+    let size = list(names.as_mut_ptr().cast(), names.len());
+    if size < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    names.truncate(size as usize);
+
+    for name in names.split(|b| *b == 0).filter(|n| !n.is_empty()) {
+        let c_name = CString::new(name)?;
+        let read = || -> io::Result<Vec<u8>> {
+            let len = get(&c_name, std::ptr::null_mut(), 0);
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut value = vec![0u8; len as usize];
+            let len = get(&c_name, value.as_mut_ptr().cast(), value.len());
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            value.truncate(len as usize);
+            Ok(value)
+        };
+        let shown = match read() {
+            Ok(value) => match String::from_utf8(value) {
+                Ok(s) => s,
+                Err(e) => format!("0x{}", crate::checksum::to_hex(e.as_bytes())),
+            },
+            // Removed since it was listed.
+            Err(e) if e.raw_os_error() == Some(libc::ENODATA) => continue,
+            Err(e) => format!("(unavailable: {})", e),
+        };
+        attrs.insert(OsStr::from_bytes(name).to_string_lossy().into_owned(), shown);
+    }
+    Ok(attrs)
+}
+
+fn render_text(info: &Info) -> String {
+    let or_dash = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".to_owned());
+    let mut lines = vec![format!("Path:        {}", info.path)];
+    match &info.link_target {
+        Some(target) => lines.push(format!("Type:        {} -> {}", info.kind, target)),
+        None => lines.push(format!("Type:        {}", info.kind)),
+    }
+    lines.push(format!(
+        "Size:        {} bytes ({} blocks, IO block {})",
+        info.size, info.blocks, info.io_block
+    ));
+    lines.push(format!("Permissions: {} ({})", info.permissions, info.mode));
+    lines.push(format!("Owner:       {} ({})", or_dash(&info.user), info.uid));
+    lines.push(format!("Group:       {} ({})", or_dash(&info.group), info.gid));
+    lines.push(format!(
+        "Inode:       {}  Links: {}  Device: {}",
+        info.inode, info.links, info.device
+    ));
+    lines.push(format!("Accessed:    {}", or_dash(&info.accessed)));
+    lines.push(format!("Modified:    {}", or_dash(&info.modified)));
+    lines.push(format!("Changed:     {}", or_dash(&info.changed)));
+    lines.push(format!("Born:        {}", or_dash(&info.born)));
+    match info.encoding {
+        Some(e) => lines.push(format!("MIME:        {}; charset={}", info.mime, e)),
+        None => lines.push(format!("MIME:        {}", info.mime)),
+    }
+    if let Some(content) = info.content {
+        lines.push(format!("Content:     {}", content));
+    }
+    if let Some(warning) = &info.warning {
+        lines.push(format!("Warning:     {}", warning));
+    }
+    for (name, value) in &info.xattrs {
+        lines.push(format!("Xattr:       {}={}", name, value));
+    }
+    if let Some(e) = &info.xattrs_error {
+        lines.push(format!("Xattr:       unavailable ({})", e));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    #[test]
+    fn test_symbolic_mode() {
+        let tests = [
+            (libc::S_IFREG | 0o644, "-rw-r--r--"),
+            (libc::S_IFDIR | 0o2755, "drwxr-sr-x"),
+            (libc::S_IFDIR | 0o1777, "drwxrwxrwt"),
+            (libc::S_IFREG | 0o4644, "-rwSr--r--"),
+            (libc::S_IFLNK | 0o777, "lrwxrwxrwx"),
+        ];
+        for (i, (mode, expected)) in tests.iter().enumerate() {
+            assert_eq!(symbolic_mode(*mode), *expected, "test[{}]: {:o}", i, mode);
+        }
+    }
+
+    #[test]
+    fn test_file_info() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            path: &'a str,
+            dereference: bool,
+            format: OutputFormat,
+            result: Result<&'a [&'a str]>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        // setup
+        fs::write(test_dir.join("plain.txt"), "hello\n").unwrap();
+        fs::set_permissions(test_dir.join("plain.txt"), fs::Permissions::from_mode(0o640)).unwrap();
+        fs::write(test_dir.join("utf8.txt"), "héllo\n").unwrap();
+        fs::write(test_dir.join("data.bin"), [0u8, 1, 2, 0xff]).unwrap();
+        fs::write(test_dir.join("empty"), "").unwrap();
+        symlink("plain.txt", test_dir.join("link")).unwrap();
+
+        let tests = &[
+            // failures
+            TestData {
+                path: "nonexistent",
+                dereference: false,
+                format: OutputFormat::Text,
+                result: Err(anyhow!("No such file")),
+            },
+            // successes
+            TestData {
+                path: "plain.txt",
+                dereference: false,
+                format: OutputFormat::Text,
+                result: Ok(&[
+                    "Type:        file\n",
+                    "Size:        6 bytes",
+                    "Permissions: -rw-r----- (0640)",
+                    "MIME:        text/plain; charset=us-ascii",
+                ]),
+            },
+            TestData {
+                path: "utf8.txt",
+                dereference: false,
+                format: OutputFormat::Json,
+                result: Ok(&["\"mime\": \"text/plain\"", "\"encoding\": \"utf-8\""]),
+            },
+            TestData {
+                path: "data.bin",
+                dereference: false,
+                format: OutputFormat::Json,
+                result: Ok(&["\"mime\": \"application/octet-stream\"", "\"links\": 1"]),
+            },
+            TestData {
+                path: "empty",
+                dereference: false,
+                format: OutputFormat::Text,
+                result: Ok(&["MIME:        inode/x-empty\nContent:     empty"]),
+            },
+            TestData {
+                path: "link",
+                dereference: false,
+                format: OutputFormat::Text,
+                result: Ok(&["Type:        symlink -> plain.txt", "lrwxrwxrwx"]),
+            },
+            TestData {
+                path: "link",
+                dereference: true,
+                format: OutputFormat::Text,
+                result: Ok(&["Type:        file\n", "Size:        6 bytes"]),
+            },
+            TestData {
+                path: ".",
+                dereference: false,
+                format: OutputFormat::Json,
+                result: Ok(&["\"type\": \"directory\"", "\"mime\": \"inode/directory\""]),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let actual = file_info(&test_dir.join(d.path), d.dereference, d.format);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+            match &d.result {
+                Ok(expected) => {
+                    let out = actual.as_ref().unwrap();
+                    for e in expected.iter() {
+                        assert!(out.contains(e), "{}", msg);
+                    }
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_xattrs() {
+        let binding = TempDir::new().unwrap();
+        let path = binding.path().join("tagged.txt");
+        defer!(binding.close().unwrap());
+        fs::write(&path, "x").unwrap();
+
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let value = b"reviewed";
+        // SAFETY: all pointers are valid for the given lengths.
+        let rc = unsafe {
+            libc::setxattr(c_path.as_ptr(), c"user.filey.status".as_ptr(), value.as_ptr().cast(), value.len(), 0)
+        };
+        if rc != 0 {
+            // The test filesystem does not support user xattrs.
+            return;
+        }
+        let out = file_info(&path, false, OutputFormat::Text).unwrap();
+        assert!(out.contains("Xattr:       user.filey.status=reviewed"), "{}", out);
+    }
+
+    #[test]
+    fn test_info_unreadable() {
+        let binding = TempDir::new().unwrap();
+        let path = binding.path().join("secret.txt");
+        defer!(binding.close().unwrap());
+        fs::write(&path, "x").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+        if fs::read(&path).is_ok() {
+            // Running as root, which can read anything.
+            return;
+        }
+
+        let out = file_info(&path, false, OutputFormat::Text).unwrap();
+        assert!(out.contains("Permissions: ---------- (0000)"), "{}", out);
+        assert!(out.contains("MIME:        application/octet-stream"), "{}", out);
+        assert!(out.contains("Warning:     cannot read contents: Permission denied"), "{}", out);
+    }
+}
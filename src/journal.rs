@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::cmd::{copy_preserving, write_atomic};
+
+/// How many operations the journal remembers.
+const MAX_OPERATIONS: usize = 50;
+
+/// One filesystem change made by a mutating command, with enough detail to
+/// reverse it. Paths are absolute.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Change {
+    Rename { from: PathBuf, to: PathBuf },
+    /// A deleted file, kept at `saved` in the journal's trash.
+    Delete { path: PathBuf, saved: PathBuf },
+}
+
+/// The changes made by one command invocation, in the order they happened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub command: String,
+    pub time: String,
+    pub changes: Vec<Change>,
+}
+
+/// Where the journal lives: `$FILEY_JOURNAL`, or `filey/journal.json` in the
+/// user's state dir.
+pub fn journal_path() -> PathBuf {
+    if let Some(path) = env::var_os("FILEY_JOURNAL") {
+        return PathBuf::from(path);
+    }
+    let state = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".local/state"),
+    };
+    state.join("filey/journal.json")
+}
+
+fn load(path: &Path) -> Result<Vec<Operation>> {
+    match fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data).map_err(|e| anyhow!("Corrupt journal {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(anyhow!("{}: {}", path.display(), e)),
+    }
+}
+
+fn save(path: &Path, ops: &[Operation]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(path, serde_json::to_string_pretty(ops)?.as_bytes(), true)
+}
+
+/// Append an operation to the journal at `path`, forgetting the oldest ones
+/// beyond `MAX_OPERATIONS`. Nothing is recorded when there are no changes.
+pub fn record(path: &Path, command: &str, changes: Vec<Change>) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut ops = load(path)?;
+    ops.push(Operation {
+        command: command.to_owned(),
+        time: chrono::Local::now().to_rfc3339(),
+        changes,
+    });
+    let excess = ops.len().saturating_sub(MAX_OPERATIONS);
+    for op in ops.drain(..excess) {
+        for change in op.changes {
+            if let Change::Delete { saved, .. } = change {
+                // Forgotten deletions can no longer be undone.
+                let _ = fs::remove_file(saved);
+            }
+        }
+    }
+    save(path, &ops)
+}
+
+/// Delete `file` recoverably by moving it into the trash next to the
+/// journal at `journal`, and return the change to record.
+pub fn stash(journal: &Path, file: &Path) -> Result<Change> {
+    let path = std::path::absolute(file)?;
+    let trash = journal.with_file_name("trash");
+    fs::create_dir_all(&trash)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stamp = chrono::Local::now().format("%Y%m%dT%H%M%S%.f");
+    let saved = trash.join(format!("{}-{}-{}", stamp, std::process::id(), name));
+    move_file(&path, &saved).map_err(|e| anyhow!("Cannot delete {}: {}", file.display(), e))?;
+    Ok(Change::Delete { path, saved })
+}
+
+/// Rename, or copy and remove when `from` and `to` are on different
+/// filesystems.
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            copy_preserving(from, to, false)?;
+            Ok(fs::remove_file(from)?)
+        }
+        res => Ok(res?),
+    }
+}
+
+/// Reverse the most recent operation in the journal, newest change first.
+/// A change that can no longer be reversed stops the undo; the changes not
+/// yet reversed stay in the journal so it can be retried.
+pub fn undo_last(path: &Path) -> Result<String> {
+    let mut ops = load(path)?;
+    let mut op = ops.pop().ok_or_else(|| anyhow!("Nothing to undo"))?;
+
+    let mut undone = 0;
+    while let Some(change) = op.changes.last() {
+        if let Err(e) = revert(change) {
+            ops.push(op);
+            save(path, &ops)?;
+            return Err(anyhow!("Undo stopped after {} changes: {}", undone, e));
+        }
+        op.changes.pop();
+        undone += 1;
+    }
+    save(path, &ops)?;
+
+    Ok(format!("Undid {} from {}: {} changes reverted", op.command, op.time, undone))
+}
+
+fn revert(change: &Change) -> Result<()> {
+    match change {
+        Change::Rename { from, to } => {
+            if from.symlink_metadata().is_ok() {
+                return Err(anyhow!("Cannot move {} back: {} exists", to.display(), from.display()));
+            }
+            fs::rename(to, from).map_err(|e| anyhow!("Cannot move {} back to {}: {}", to.display(), from.display(), e))
+        }
+        Change::Delete { path, saved } => {
+            if path.symlink_metadata().is_ok() {
+                return Err(anyhow!("Cannot restore {}: it exists", path.display()));
+            }
+            move_file(saved, path).map_err(|e| anyhow!("Cannot restore {}: {}", path.display(), e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_record_undo() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        let journal = test_dir.join("state/journal.json");
+
+        let err = undo_last(&journal).unwrap_err();
+        assert!(err.to_string().contains("Nothing to undo"), "{}", err);
+
+        let (a, b, c) = (test_dir.join("a"), test_dir.join("b"), test_dir.join("c"));
+        fs::write(&c, "c").unwrap();
+        record(&journal, "rename", vec![]).unwrap();
+        assert!(!journal.exists());
+        // a -> b, then b -> c, as if both had happened
+        record(
+            &journal,
+            "rename",
+            vec![
+                Change::Rename { from: a.clone(), to: b.clone() },
+                Change::Rename { from: b.clone(), to: c.clone() },
+            ],
+        )
+        .unwrap();
+
+        // the first revert moves c back to b; the second is blocked by a
+        fs::write(&a, "blocker").unwrap();
+        let err = undo_last(&journal).unwrap_err();
+        assert!(err.to_string().contains("Undo stopped after 1 changes: Cannot move"), "{}", err);
+        assert_eq!(fs::read_to_string(&b).unwrap(), "c");
+        assert_eq!(load(&journal).unwrap()[0].changes.len(), 1);
+
+        fs::remove_file(&a).unwrap();
+        let msg = undo_last(&journal).unwrap();
+        assert!(msg.starts_with("Undid rename from"), "{}", msg);
+        assert!(msg.ends_with("1 changes reverted"), "{}", msg);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "c");
+        assert!(load(&journal).unwrap().is_empty());
+
+        let change = stash(&journal, &a).unwrap();
+        assert!(!a.exists());
+        record(&journal, "rename --edit", vec![change]).unwrap();
+        undo_last(&journal).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "c");
+        assert_eq!(fs::read_dir(test_dir.join("state/trash")).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,393 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::{
+    fs,
+    os::unix::fs::symlink,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::find::{find_paths, Expr, Predicate};
+use crate::output::{paint, to_json, Color, OutputFormat};
+use crate::walk::Filter;
+
+/// Symlink hops followed before giving up, as the kernel does (ELOOP).
+const MAX_HOPS: usize = 40;
+
+/// Options for `link_file`.
+#[derive(Debug, Default)]
+pub struct LinkOptions {
+    /// Create a symbolic link instead of a hard link.
+    pub symbolic: bool,
+    /// Store the target relative to the link's directory instead of as an
+    /// absolute path.
+    pub relative: bool,
+}
+
+/// Create `link` pointing at `target`. Symbolic links store the target as an
+/// absolute path, or relative to the link with `opts.relative`; the target
+/// does not have to exist. Hard links need an existing target.
+pub fn link_file(target: &Path, link: &Path, opts: &LinkOptions) -> Result<String> {
+    if link.symlink_metadata().is_ok() {
+        return Err(anyhow!("Destination file exists"));
+    }
+    if opts.relative && !opts.symbolic {
+        return Err(anyhow!("Only symbolic links can be relative"));
+    }
+
+    if opts.symbolic {
+        let stored = if opts.relative {
+            relative_target(target, link)?
+        } else {
+            normalize(&std::path::absolute(target)?)
+        };
+        symlink(&stored, link)?;
+        Ok(format!(
+            "Linked file successfully: {} -> {}",
+            link.to_str().unwrap_or_default(),
+            stored.to_str().unwrap_or_default()
+        ))
+    } else {
+        fs::hard_link(target, link).map_err(|e| anyhow!("{}: {}", target.display(), e))?;
+        Ok(format!(
+            "Linked file successfully: {} => {}",
+            link.to_str().unwrap_or_default(),
+            target.to_str().unwrap_or_default()
+        ))
+    }
+}
+
+/// Resolve `.` and `..` lexically.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Resolve as much of `path` as exists through symlinks, keeping any
+/// missing tail as written.
+fn canonical_prefix(path: &Path) -> Result<PathBuf> {
+    let path = normalize(&std::path::absolute(path)?);
+    let mut existing = path.as_path();
+    let mut tail = Vec::new();
+    loop {
+        if let Ok(real) = fs::canonicalize(existing) {
+            return Ok(tail.iter().rev().fold(real, |p, c| p.join(c)));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                tail.push(name.to_owned());
+                existing = parent;
+            }
+            _ => return Ok(path),
+        }
+    }
+}
+
+/// The path of `target` relative to the directory `link` will live in,
+/// e.g. `../share/file`. Symlinks in either directory are resolved first
+/// so that `..` steps through the real tree.
+fn relative_target(target: &Path, link: &Path) -> Result<PathBuf> {
+    let target = canonical_prefix(target)?;
+    let dir = canonical_prefix(link.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")))?;
+
+    let common = target
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut rel: PathBuf = dir.components().skip(common).map(|_| "..").collect();
+    rel.extend(target.components().skip(common));
+    if rel.as_os_str().is_empty() {
+        rel.push(".");
+    }
+    Ok(rel)
+}
+
+#[derive(Debug, Serialize)]
+struct Hop {
+    link: String,
+    target: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Resolution {
+    path: String,
+    hops: Vec<Hop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canonical: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Follow the symlink at `path` hop by hop and resolve it to its canonical
+/// path. With `canonical_only` only the canonical path is printed, and
+/// `path` does not have to be a symlink.
+pub fn read_link(path: &Path, canonical_only: bool, format: OutputFormat) -> Result<String> {
+    let meta = path.symlink_metadata().map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    if canonical_only {
+        let real = fs::canonicalize(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        return Ok(real.to_str().unwrap_or_default().to_owned());
+    }
+    if !meta.file_type().is_symlink() {
+        return Err(anyhow!("{} is not a symbolic link", path.display()));
+    }
+
+    let mut report = Resolution {
+        path: path.to_str().unwrap_or_default().to_owned(),
+        hops: Vec::new(),
+        canonical: None,
+        error: None,
+    };
+    let mut current = path.to_path_buf();
+    loop {
+        match current.symlink_metadata() {
+            Err(_) => {
+                report.error = Some(format!("broken link: {} does not exist", current.display()));
+                break;
+            }
+            Ok(m) if !m.file_type().is_symlink() => break,
+            Ok(_) => {}
+        }
+        if report.hops.len() == MAX_HOPS {
+            report.error = Some("too many levels of symbolic links".to_owned());
+            break;
+        }
+        let target = fs::read_link(&current)?;
+        let next = current.parent().unwrap_or(Path::new("")).join(&target);
+        report.hops.push(Hop {
+            link: current.to_str().unwrap_or_default().to_owned(),
+            target: target.to_str().unwrap_or_default().to_owned(),
+        });
+        current = next;
+    }
+    if report.error.is_none() {
+        // Directories along the way may be symlinks too.
+        match fs::canonicalize(path) {
+            Ok(real) => report.canonical = Some(real.to_str().unwrap_or_default().to_owned()),
+            Err(e) => report.error = Some(e.to_string()),
+        }
+    }
+
+    match format {
+        OutputFormat::Json => to_json(&report),
+        OutputFormat::Text => {
+            let mut lines: Vec<String> = report.hops.iter().map(|h| format!("{} -> {}", h.link, h.target)).collect();
+            match (&report.canonical, &report.error) {
+                (Some(c), _) => lines.push(format!("canonical: {}", c)),
+                (_, Some(e)) => return Err(anyhow!("{}\n{}", lines.join("\n"), e)),
+                _ => {}
+            }
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LinkEntry {
+    path: String,
+    target: String,
+    broken: bool,
+}
+
+/// List the symlinks under `paths` with their targets, or with `broken`
+/// only those whose target cannot be reached.
+pub fn list_links(paths: &[PathBuf], broken: bool, color: bool, format: OutputFormat) -> Result<String> {
+    let (links, _) = find_paths(paths, &Expr::Test(Predicate::Type("symlink")), &Filter::default(), false)?;
+    let mut entries = Vec::new();
+    for link in links {
+        let dangling = fs::metadata(&link).is_err();
+        if broken && !dangling {
+            continue;
+        }
+        entries.push(LinkEntry {
+            path: link.to_str().unwrap_or_default().to_owned(),
+            target: fs::read_link(&link)?.to_str().unwrap_or_default().to_owned(),
+            broken: dangling,
+        });
+    }
+
+    match format {
+        OutputFormat::Json => to_json(&entries),
+        OutputFormat::Text => Ok(entries
+            .iter()
+            .map(|e| {
+                let line = format!("{} -> {}", e.path, e.target);
+                if e.broken {
+                    paint(&line, Color::Red, color)
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_link_file() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            target: &'a str,
+            link: &'a str,
+            opts: LinkOptions,
+            /// What a symlink stores, relative to the test dir when absolute.
+            stored: Result<&'a str>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        fs::create_dir_all(test_dir.join("share/docs")).unwrap();
+        fs::create_dir_all(test_dir.join("bin")).unwrap();
+        fs::write(test_dir.join("share/docs/guide.md"), "guide").unwrap();
+        symlink(test_dir.join("bin"), test_dir.join("bin-alias")).unwrap();
+
+        let tests = &[
+            // failures
+            TestData {
+                target: "share/docs/guide.md",
+                link: "share/docs/guide.md",
+                opts: LinkOptions::default(),
+                stored: Err(anyhow!("Destination file exists")),
+            },
+            TestData {
+                target: "missing",
+                link: "hard",
+                opts: LinkOptions::default(),
+                stored: Err(anyhow!("No such file")),
+            },
+            TestData {
+                target: "share/docs/guide.md",
+                link: "rel",
+                opts: LinkOptions {
+                    symbolic: false,
+                    relative: true,
+                },
+                stored: Err(anyhow!("Only symbolic links can be relative")),
+            },
+            // successes
+            TestData {
+                target: "share/docs/guide.md",
+                link: "hard.md",
+                opts: LinkOptions::default(),
+                stored: Ok(""),
+            },
+            TestData {
+                target: "share/./docs/../docs/guide.md",
+                link: "abs.md",
+                opts: LinkOptions {
+                    symbolic: true,
+                    relative: false,
+                },
+                stored: Ok("/share/docs/guide.md"),
+            },
+            TestData {
+                target: "share/docs/guide.md",
+                link: "bin/guide.md",
+                opts: LinkOptions {
+                    symbolic: true,
+                    relative: true,
+                },
+                stored: Ok("../share/docs/guide.md"),
+            },
+            // resolved through the directory symlink, and may dangle
+            TestData {
+                target: "share/docs/later.md",
+                link: "bin-alias/later.md",
+                opts: LinkOptions {
+                    symbolic: true,
+                    relative: true,
+                },
+                stored: Ok("../share/docs/later.md"),
+            },
+            TestData {
+                target: "share/docs",
+                link: "share/docs/self",
+                opts: LinkOptions {
+                    symbolic: true,
+                    relative: true,
+                },
+                stored: Ok("."),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let link = test_dir.join(d.link);
+            let actual = link_file(&test_dir.join(d.target), &link, &d.opts);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.stored {
+                Ok(expected) => {
+                    assert!(actual.unwrap().starts_with("Linked file successfully"), "{}", msg);
+                    if d.opts.symbolic {
+                        let stored = fs::read_link(&link).unwrap();
+                        let expected = match expected.strip_prefix('/') {
+                            Some(rest) => test_dir.join(rest),
+                            None => PathBuf::from(expected),
+                        };
+                        assert_eq!(stored, expected, "{}", msg);
+                    } else {
+                        let ino = |p: &Path| fs::metadata(p).unwrap().ino();
+                        assert_eq!(ino(&link), ino(&test_dir.join(d.target)), "{}", msg);
+                    }
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_and_list_links() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        fs::write(test_dir.join("real.txt"), "real").unwrap();
+        symlink("real.txt", test_dir.join("one")).unwrap();
+        symlink("one", test_dir.join("two")).unwrap();
+        symlink("nowhere", test_dir.join("dangling")).unwrap();
+        symlink("loop", test_dir.join("loop")).unwrap();
+
+        let real = fs::canonicalize(test_dir.join("real.txt")).unwrap();
+        let out = read_link(&test_dir.join("two"), false, OutputFormat::Text).unwrap();
+        let expected = format!(
+            "{0}/two -> one\n{0}/one -> real.txt\ncanonical: {1}",
+            test_dir.display(),
+            real.display()
+        );
+        assert_eq!(out, expected);
+        let out = read_link(&test_dir.join("two"), true, OutputFormat::Text).unwrap();
+        assert_eq!(out, real.to_str().unwrap());
+
+        let err = read_link(&test_dir.join("real.txt"), false, OutputFormat::Text).unwrap_err();
+        assert!(err.to_string().contains("is not a symbolic link"), "{}", err);
+        let err = read_link(&test_dir.join("dangling"), false, OutputFormat::Text).unwrap_err();
+        assert!(err.to_string().ends_with("nowhere does not exist"), "{}", err);
+        let err = read_link(&test_dir.join("loop"), false, OutputFormat::Text).unwrap_err();
+        assert!(err.to_string().ends_with("too many levels of symbolic links"), "{}", err);
+        let out = read_link(&test_dir.join("dangling"), false, OutputFormat::Json).unwrap();
+        assert!(out.contains("\"error\": \"broken link"), "{}", out);
+
+        let out = list_links(std::slice::from_ref(&test_dir), false, false, OutputFormat::Text).unwrap();
+        assert_eq!(out.lines().count(), 4, "{}", out);
+        let out = list_links(std::slice::from_ref(&test_dir), true, false, OutputFormat::Text).unwrap();
+        let expected = format!("{0}/dangling -> nowhere\n{0}/loop -> loop", test_dir.display());
+        assert_eq!(out, expected);
+    }
+}
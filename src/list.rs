@@ -0,0 +1,554 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    cmp::Ordering,
+    fs::{self, Metadata},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::cmd::format_size;
+use crate::info::{group_name, kind_name, symbolic_mode, user_name};
+use crate::output::{paint, to_json, Color, OutputFormat};
+use crate::walk::Filter;
+
+/// Orders for directory entries.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Byte order of the names.
+    Name,
+    /// Names with embedded numbers compared by value, so `f2` comes before `f10`.
+    #[default]
+    Natural,
+    /// Largest first.
+    Size,
+    /// Most recently modified first.
+    Mtime,
+}
+
+/// Options shared by `list_paths` and `tree_paths`.
+#[derive(Debug, Default)]
+pub struct ListOptions {
+    /// Show entries whose names start with a dot.
+    pub all: bool,
+    /// Show permissions, links, owners, sizes and modification times.
+    pub long: bool,
+    /// Print sizes like `1.5K` instead of bytes.
+    pub human: bool,
+    pub sort: SortKey,
+    pub reverse: bool,
+    /// How many directory levels to descend; `None` means no limit.
+    pub depth: Option<usize>,
+    pub filter: Filter,
+    pub color: bool,
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    name: String,
+    path: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    size: u64,
+    mode: String,
+    permissions: String,
+    links: u64,
+    user: String,
+    group: String,
+    modified: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    children: Option<Vec<Entry>>,
+    #[serde(skip)]
+    meta: Metadata,
+}
+
+impl Entry {
+    fn new(path: &Path, name: String) -> Result<Self> {
+        let meta = fs::symlink_metadata(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let target = if meta.is_symlink() {
+            Some(fs::read_link(path)?.to_string_lossy().into_owned())
+        } else {
+            None
+        };
+        let modified: DateTime<Local> = meta.modified()?.into();
+        Ok(Entry {
+            name,
+            path: path.to_str().unwrap_or_default().to_owned(),
+            kind: kind_name(meta.mode()),
+            size: meta.len(),
+            mode: format!("{:04o}", meta.mode() & 0o7777),
+            permissions: symbolic_mode(meta.mode()),
+            links: meta.nlink(),
+            user: user_name(meta.uid()).unwrap_or_else(|| meta.uid().to_string()),
+            group: group_name(meta.gid()).unwrap_or_else(|| meta.gid().to_string()),
+            modified: modified.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            target,
+            children: None,
+            meta,
+        })
+    }
+
+    fn is_dir(&self) -> bool {
+        self.meta.is_dir()
+    }
+}
+
+/// Compare names so that runs of digits are ordered by their value.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a, b);
+    loop {
+        let (Some(cx), Some(cy)) = (x.chars().next(), y.chars().next()) else {
+            return x.len().cmp(&y.len()).then_with(|| a.cmp(b));
+        };
+        if cx.is_ascii_digit() && cy.is_ascii_digit() {
+            let end_x = x.find(|c: char| !c.is_ascii_digit()).unwrap_or(x.len());
+            let end_y = y.find(|c: char| !c.is_ascii_digit()).unwrap_or(y.len());
+            let (nx, ny) = (x[..end_x].trim_start_matches('0'), y[..end_y].trim_start_matches('0'));
+            let ord = nx.len().cmp(&ny.len()).then_with(|| nx.cmp(ny));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            (x, y) = (&x[end_x..], &y[end_y..]);
+        } else {
+            let ord = cx.cmp(&cy);
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            (x, y) = (&x[cx.len_utf8()..], &y[cy.len_utf8()..]);
+        }
+    }
+}
+
+fn sort_entries(entries: &mut [Entry], opts: &ListOptions) {
+    entries.sort_by(|a, b| {
+        let ord = match opts.sort {
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Natural => natural_cmp(&a.name, &b.name),
+            SortKey::Size => b.size.cmp(&a.size).then_with(|| natural_cmp(&a.name, &b.name)),
+            SortKey::Mtime => b
+                .meta
+                .modified()
+                .ok()
+                .cmp(&a.meta.modified().ok())
+                .then_with(|| natural_cmp(&a.name, &b.name)),
+        };
+        if opts.reverse {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+}
+
+/// The visible entries of directory `dir`, sorted, with subdirectories filled
+/// in down to `depth` more levels. Symlinked directories are not followed.
+fn read_children(dir: &Path, depth: Option<usize>, opts: &ListOptions) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for item in fs::read_dir(dir).map_err(|e| anyhow!("{}: {}", dir.display(), e))? {
+        let item = item?;
+        let name = item.file_name().to_string_lossy().into_owned();
+        if !opts.all && name.starts_with('.') {
+            continue;
+        }
+        let path = item.path();
+        let is_dir = item.file_type()?.is_dir();
+        let allowed = if is_dir {
+            opts.filter.allows_dir(&path)
+        } else {
+            opts.filter.allows_file(&path)
+        };
+        if !allowed {
+            continue;
+        }
+        let mut entry = Entry::new(&path, name)?;
+        if is_dir && depth != Some(1) {
+            entry.children = Some(read_children(&path, depth.map(|d| d - 1), opts)?);
+        }
+        entries.push(entry);
+    }
+    sort_entries(&mut entries, opts);
+    Ok(entries)
+}
+
+/// Read each of `paths`, filling in directory contents down to the depth limit.
+fn read_roots(paths: &[PathBuf], opts: &ListOptions) -> Result<Vec<Entry>> {
+    if opts.depth == Some(0) {
+        return Err(anyhow!("Depth must be greater than zero"));
+    }
+    paths
+        .iter()
+        .map(|path| {
+            let mut root = Entry::new(path, path.to_str().unwrap_or_default().to_owned())?;
+            if root.is_dir() {
+                root.children = Some(read_children(path, opts.depth, opts)?);
+            }
+            Ok(root)
+        })
+        .collect()
+}
+
+fn paint_name(entry: &Entry, name: &str, color: bool) -> String {
+    let painted = if entry.is_dir() {
+        paint(name, Color::Cyan, color)
+    } else if entry.meta.is_symlink() {
+        paint(name, Color::Yellow, color)
+    } else if entry.meta.mode() & 0o111 != 0 {
+        paint(name, Color::Green, color)
+    } else {
+        name.to_owned()
+    };
+    match &entry.target {
+        Some(target) => format!("{} -> {}", painted, target),
+        None => painted,
+    }
+}
+
+/// Column widths for the long format, so the fields line up.
+#[derive(Default)]
+struct Widths {
+    links: usize,
+    user: usize,
+    group: usize,
+    size: usize,
+}
+
+impl Widths {
+    fn measure<'a>(entries: impl Iterator<Item = &'a Entry>, human: bool) -> Self {
+        entries.fold(Widths::default(), |w, e| Widths {
+            links: w.links.max(e.links.to_string().len()),
+            user: w.user.max(e.user.len()),
+            group: w.group.max(e.group.len()),
+            size: w.size.max(size_text(e.size, human).len()),
+        })
+    }
+}
+
+fn size_text(size: u64, human: bool) -> String {
+    if human {
+        format_size(size)
+    } else {
+        size.to_string()
+    }
+}
+
+fn long_fields(e: &Entry, w: &Widths, human: bool) -> String {
+    let modified: DateTime<Local> = e.meta.modified().map(DateTime::from).unwrap_or_default();
+    format!(
+        "{} {:>lw$} {:<uw$} {:<gw$} {:>sw$} {}",
+        e.permissions,
+        e.links,
+        e.user,
+        e.group,
+        size_text(e.size, human),
+        modified.format("%Y-%m-%d %H:%M"),
+        lw = w.links,
+        uw = w.user,
+        gw = w.group,
+        sw = w.size,
+    )
+}
+
+/// Flatten nested entries into (relative name, entry) rows, depth first.
+fn flatten<'a>(entries: &'a [Entry], prefix: &str, rows: &mut Vec<(String, &'a Entry)>) {
+    for e in entries {
+        let name = format!("{}{}", prefix, e.name);
+        rows.push((name.clone(), e));
+        if let Some(children) = &e.children {
+            flatten(children, &format!("{}/", name), rows);
+        }
+    }
+}
+
+/// An optional heading and the (name, entry) rows listed under it.
+type Section<'a> = (Option<&'a str>, Vec<(String, &'a Entry)>);
+
+/// List files and directory contents like `ls`. Directories are listed one
+/// level deep unless a larger depth is given, in which case nested entries
+/// are shown with their relative paths.
+pub fn list_paths(paths: &[PathBuf], opts: &ListOptions) -> Result<String> {
+    let roots = read_roots(paths, opts)?;
+
+    if opts.format == OutputFormat::Json {
+        let mut flat = Vec::new();
+        for root in roots {
+            match root.children {
+                Some(children) => flat.extend(children),
+                None => flat.push(root),
+            }
+        }
+        return to_json(&flat);
+    }
+
+    // Like ls, plain files come first and each directory gets a heading
+    // when more than one path was given.
+    let (dirs, files): (Vec<&Entry>, Vec<&Entry>) = roots.iter().partition(|r| r.children.is_some());
+    let mut sections: Vec<Section> = Vec::new();
+    if !files.is_empty() {
+        sections.push((None, files.iter().map(|e| (e.name.clone(), *e)).collect()));
+    }
+    for dir in &dirs {
+        let mut rows = Vec::new();
+        flatten(dir.children.as_deref().unwrap_or_default(), "", &mut rows);
+        let heading = (roots.len() > 1).then_some(dir.name.as_str());
+        sections.push((heading, rows));
+    }
+
+    let mut blocks = Vec::new();
+    for (heading, rows) in sections {
+        let mut lines = Vec::new();
+        if let Some(h) = heading {
+            lines.push(format!("{}:", h));
+        }
+        let widths = Widths::measure(rows.iter().map(|(_, e)| *e), opts.human);
+        for (name, e) in &rows {
+            let name = paint_name(e, name, opts.color);
+            if opts.long {
+                lines.push(format!("{} {}", long_fields(e, &widths, opts.human), name));
+            } else {
+                lines.push(name);
+            }
+        }
+        blocks.push(lines.join("\n"));
+    }
+    Ok(blocks.join("\n\n"))
+}
+
+/// Draw each of `paths` as an indented tree, followed by a count of the
+/// directories and files shown.
+pub fn tree_paths(paths: &[PathBuf], opts: &ListOptions) -> Result<String> {
+    let roots = read_roots(paths, opts)?;
+    if opts.format == OutputFormat::Json {
+        return to_json(&roots);
+    }
+
+    let mut all = Vec::new();
+    for root in &roots {
+        flatten(std::slice::from_ref(root), "", &mut all);
+    }
+    let widths = Widths::measure(all.iter().map(|(_, e)| *e), opts.human);
+
+    let mut lines = Vec::new();
+    let (mut dirs, mut files) = (0, 0);
+    for root in &roots {
+        lines.push(decorate(root, &root.name, &widths, opts));
+        draw(root.children.as_deref().unwrap_or_default(), "", &widths, opts, &mut lines, &mut dirs, &mut files);
+    }
+    let plural = |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+    lines.push(String::new());
+    lines.push(format!(
+        "{}, {}",
+        plural(dirs, "directory", "directories"),
+        plural(files, "file", "files")
+    ));
+    Ok(lines.join("\n"))
+}
+
+fn decorate(e: &Entry, name: &str, widths: &Widths, opts: &ListOptions) -> String {
+    let name = paint_name(e, name, opts.color);
+    if opts.long {
+        format!("[{}] {}", long_fields(e, widths, opts.human), name)
+    } else {
+        name
+    }
+}
+
+fn draw(
+    entries: &[Entry],
+    indent: &str,
+    widths: &Widths,
+    opts: &ListOptions,
+    lines: &mut Vec<String>,
+    dirs: &mut usize,
+    files: &mut usize,
+) {
+    for (i, e) in entries.iter().enumerate() {
+        let last = i + 1 == entries.len();
+        let (branch, next) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+        lines.push(format!("{}{}{}", indent, branch, decorate(e, &e.name, widths, opts)));
+        if e.is_dir() {
+            *dirs += 1;
+        } else {
+            *files += 1;
+        }
+        if let Some(children) = &e.children {
+            draw(children, &format!("{}{}", indent, next), widths, opts, lines, dirs, files);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::os::unix::fs::symlink;
+    use std::time::{Duration, SystemTime};
+
+    fn setup(dir: &Path) {
+        for (f, size, age) in [
+            ("file10.txt", 10, 30),
+            ("file2.txt", 2000, 10),
+            ("file1.txt", 1, 20),
+            ("sub/inner.rs", 5, 40),
+            ("sub/deep/x.rs", 5, 50),
+            (".hidden", 1, 60),
+        ] {
+            let p = dir.join(f);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(&p, "x".repeat(size)).unwrap();
+            let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 - age);
+            fs::File::options().write(true).open(&p).unwrap().set_modified(mtime).unwrap();
+        }
+        symlink("file1.txt", dir.join("link")).unwrap();
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec!["file10", "file2", "file1", "file02", "a", "file", "b1c10", "b1c9"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["a", "b1c9", "b1c10", "file", "file1", "file02", "file2", "file10"]);
+    }
+
+    #[test]
+    fn test_list_paths() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            opts: ListOptions,
+            result: Result<&'a str>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        setup(&test_dir);
+
+        let tests = &[
+            // failures
+            TestData {
+                opts: ListOptions {
+                    depth: Some(0),
+                    ..Default::default()
+                },
+                result: Err(anyhow!("Depth must be greater than zero")),
+            },
+            // successes
+            TestData {
+                opts: ListOptions {
+                    depth: Some(1),
+                    ..Default::default()
+                },
+                result: Ok("file1.txt\nfile2.txt\nfile10.txt\nlink -> file1.txt\nsub"),
+            },
+            TestData {
+                opts: ListOptions {
+                    depth: Some(1),
+                    sort: SortKey::Name,
+                    all: true,
+                    ..Default::default()
+                },
+                result: Ok(".hidden\nfile1.txt\nfile10.txt\nfile2.txt\nlink -> file1.txt\nsub"),
+            },
+            TestData {
+                opts: ListOptions {
+                    depth: Some(1),
+                    sort: SortKey::Mtime,
+                    reverse: true,
+                    filter: Filter::new(&["*.txt".to_owned()], &[]).unwrap(),
+                    ..Default::default()
+                },
+                result: Ok("file10.txt\nfile1.txt\nfile2.txt\nsub"),
+            },
+            TestData {
+                opts: ListOptions {
+                    filter: Filter::new(&[], &["deep".to_owned()]).unwrap(),
+                    ..Default::default()
+                },
+                result: Ok("file1.txt\nfile2.txt\nfile10.txt\nlink -> file1.txt\nsub\nsub/inner.rs"),
+            },
+            TestData {
+                opts: ListOptions {
+                    depth: Some(1),
+                    sort: SortKey::Size,
+                    filter: Filter::new(&["*.txt".to_owned()], &["sub".to_owned()]).unwrap(),
+                    ..Default::default()
+                },
+                result: Ok("file2.txt\nfile10.txt\nfile1.txt"),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let actual = list_paths(std::slice::from_ref(&test_dir), &d.opts);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+            match &d.result {
+                Ok(expected) => assert_eq!(actual.unwrap(), *expected, "{}", msg),
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_list_long_and_json() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        setup(&test_dir);
+
+        let opts = ListOptions {
+            long: true,
+            human: true,
+            depth: Some(1),
+            ..Default::default()
+        };
+        let out = list_paths(&[test_dir.join("file2.txt"), test_dir.join("file1.txt")], &opts).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", out);
+        assert!(lines[0].starts_with("-rw-"), "{}", out);
+        assert!(lines[0].contains(" 2.0K "), "{}", out);
+        assert!(lines[1].contains("    1 "), "{}", out);
+
+        let opts = ListOptions {
+            depth: Some(1),
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+        let out = list_paths(std::slice::from_ref(&test_dir), &opts).unwrap();
+        assert!(out.contains("\"name\": \"file2.txt\""), "{}", out);
+        assert!(out.contains("\"target\": \"file1.txt\""), "{}", out);
+    }
+
+    #[test]
+    fn test_tree_paths() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        setup(&test_dir);
+
+        let out = tree_paths(std::slice::from_ref(&test_dir), &ListOptions::default()).unwrap();
+        let expected = "\
+├── file1.txt
+├── file2.txt
+├── file10.txt
+├── link -> file1.txt
+└── sub
+    ├── deep
+    │   └── x.rs
+    └── inner.rs
+
+2 directories, 6 files";
+        assert!(out.ends_with(expected), "{}", out);
+
+        let opts = ListOptions {
+            depth: Some(1),
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+        let out = tree_paths(std::slice::from_ref(&test_dir), &opts).unwrap();
+        assert!(out.contains("\"children\": ["), "{}", out);
+        assert!(!out.contains("inner.rs"), "{}", out);
+    }
+}
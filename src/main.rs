@@ -1,15 +1,75 @@
 use std::path::Path;
 use clap::{Parser, Subcommand};
-use cmd::{cat_files, copy_file, create_file, delete_file};
+use cmd::{
+    cat_files_with, copy_file_with, create_file_from, delete_file, parse_mode, parse_size,
+    ConflictPolicy, CreateOptions, Source, TransferOptions,
+};
+use compress::Compression;
+use crypt::{decrypt_file, encrypt_file, read_passphrase, DecryptKey, EncryptKey};
+use template::{create_from_template, parse_var, template_dirs};
+use touch::{resolve_times, touch_file};
+use write::{write_file, WriteMode};
+use output::OutputFormat;
+use replace::{replace_in_files, ReplaceOptions};
+use walk::{walk_entries, walk_files, Filter};
+use split::{cat_manifest, split_file, SplitBy};
+use archive::{pack, unpack, ArchiveFormat};
+use diff::{diff_paths, DiffOptions};
+use sync::{sync_dirs, SyncOptions};
+use dedupe::{dedupe_files, DedupeAction, DedupeOptions, KeepPolicy};
+use info::file_info;
+use detect::{type_files, Category};
+use list::{list_paths, tree_paths, ListOptions, SortKey};
+use du::{disk_usage, DuFormat, DuOptions, DuView};
+use find::{find, parse_expr, parse_query};
+use grep::{grep_files, GrepOptions};
+use journal::{journal_path, undo_last};
+use link::{link_file, list_links, read_link, LinkOptions};
+use audit::{audit_paths, AuditOptions, Severity};
+use watch::{watch, WatchAction, WatchOptions};
+use daemon::{run_remote, serve, socket_path, Operation};
+use perm::{change_modes, change_owners, parse_mode_spec, parse_owner, ChmodOptions, ModeSpec};
+use rename::{edit_renames, rename_files, CaseStyle, DateSource, RenameOptions};
+use std::io::IsTerminal;
+use std::path::PathBuf;
 use anyhow::{anyhow, Result};
 
 pub mod cmd;
+pub mod template;
+pub mod touch;
+pub mod write;
+pub mod output;
+pub mod replace;
+pub mod walk;
+pub mod checksum;
+pub mod split;
+pub mod archive;
+pub mod compress;
+pub mod crypt;
+pub mod diff;
+pub mod sync;
+pub mod dedupe;
+pub mod info;
+pub mod detect;
+pub mod list;
+pub mod du;
+pub mod find;
+pub mod grep;
+pub mod journal;
+pub mod rename;
+pub mod link;
+pub mod perm;
+pub mod audit;
+pub mod watch;
+pub mod daemon;
 
 #[derive(Parser)]
 #[command(about="Perform common file operations easily.")]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    #[arg(long, global=true, value_name="SOCKET", help="Run create, copy, cat or del as a job on the daemon listening at SOCKET")]
+    remote: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -18,6 +78,24 @@ enum Commands {
     Create {
         #[arg(short)]
         text: Option<String>,
+        #[arg(short, long, help="Create missing parent directories")]
+        parents: bool,
+        #[arg(long, value_parser=parse_mode, help="Octal permission mode, e.g. 0640")]
+        mode: Option<u32>,
+        #[arg(long, conflicts_with_all=["text", "from"], help="Read contents from standard input")]
+        from_stdin: bool,
+        #[arg(long, conflicts_with="text", help="Read contents from an existing file")]
+        from: Option<String>,
+        #[arg(long, value_parser=parse_size, conflicts_with_all=["text", "from", "from_stdin"], help="Preallocate the file to a size, e.g. 10M")]
+        size: Option<u64>,
+        #[arg(long, requires="size", help="Create a sparse file instead of preallocating")]
+        sparse: bool,
+        #[arg(long, conflicts_with_all=["text", "from", "from_stdin", "size"], help="Render the named template into the new file")]
+        template: Option<String>,
+        #[arg(long="var", value_name="KEY=VALUE", value_parser=parse_var, requires="template", help="Set a template variable")]
+        vars: Vec<(String, String)>,
+        #[arg(long, value_enum, default_value_t=ConflictPolicy::Fail, help="What to do if the file already exists")]
+        on_conflict: ConflictPolicy,
         #[arg(required(true))]
         filename: String,
     },
@@ -27,15 +105,450 @@ enum Commands {
         src_file: String,
         #[arg(required(true))]
         dst_file: String,
+        #[arg(long, value_name="CODEC[:LEVEL]", help="Compress the copy with gzip, zstd or xz")]
+        compress: Option<Compression>,
+        #[arg(long, value_name="RECIPIENT", help="Encrypt the copy to an age public key or recipients file")]
+        encrypt_to: Vec<String>,
+        #[arg(short='L', long, overrides_with="no_dereference", help="Copy the file a symlink points to (default); only undoes an earlier -P")]
+        dereference: bool,
+        #[arg(short='P', long, overrides_with="dereference", help="Copy a symlink as a link")]
+        no_dereference: bool,
     },
-    #[command(about="Concatenate two existing files into a new location")]
+    #[command(about="Concatenate two existing files into a new location, or reassemble split parts")]
     Cat {
+        #[arg(required_unless_present("manifest"))]
+        src_file1: Option<String>,
+        #[arg(required_unless_present("manifest"))]
+        src_file2: Option<String>,
+        #[arg(required_unless_present("manifest"))]
+        dst_file: Option<String>,
+        #[arg(long, conflicts_with_all=["src_file1", "src_file2", "dst_file"], help="Reassemble and verify the parts listed in a split manifest")]
+        manifest: Option<String>,
+        #[arg(short, long, requires="manifest", help="Where to write the reassembled file (default: its original name)")]
+        output: Option<String>,
+        #[arg(long, conflicts_with="manifest", help="Decode gzip, zstd or xz sources, detected by content")]
+        decompress: bool,
+        #[arg(long, value_name="CODEC[:LEVEL]", conflicts_with="manifest", help="Compress the output with gzip, zstd or xz")]
+        compress: Option<Compression>,
+    },
+    #[command(about="Update file timestamps without changing contents")]
+    Touch {
+        #[arg(short, help="Change only the access time")]
+        access: bool,
+        #[arg(short, help="Change only the modification time")]
+        modify: bool,
+        #[arg(short, long, conflicts_with="reference", help="Use this time instead of now (RFC 3339, YYYY-MM-DD, @SECONDS or relative like -2h)")]
+        date: Option<String>,
+        #[arg(short, long, help="Use the times of this file instead of now")]
+        reference: Option<String>,
+        #[arg(short='c', long, help="Do not create missing files")]
+        no_create: bool,
         #[arg(required(true))]
-        src_file1: String,
+        filenames: Vec<String>,
+    },
+    #[command(about="Write text into an existing file atomically")]
+    #[command(group(clap::ArgGroup::new("position").required(true).args(["append", "prepend", "insert_at_line", "replace_between"])))]
+    #[command(group(clap::ArgGroup::new("input").required(true).args(["text", "from_stdin", "from"])))]
+    Write {
+        #[arg(short)]
+        text: Option<String>,
+        #[arg(long, help="Read the text from standard input")]
+        from_stdin: bool,
+        #[arg(long, help="Read the text from an existing file")]
+        from: Option<String>,
+        #[arg(long, help="Add the text at the end of the file")]
+        append: bool,
+        #[arg(long, help="Add the text at the start of the file")]
+        prepend: bool,
+        #[arg(long, value_name="N", help="Insert the text before line N")]
+        insert_at_line: Option<usize>,
+        #[arg(long, num_args=2, value_names=["START", "END"], help="Replace the lines between two marker lines")]
+        replace_between: Option<Vec<String>>,
         #[arg(required(true))]
-        src_file2: String,
+        filename: String,
+    },
+    #[command(about="Search and replace text across files")]
+    Replace {
         #[arg(required(true))]
-        dst_file: String,
+        pattern: String,
+        #[arg(required(true))]
+        replacement: String,
+        #[arg(required(true))]
+        paths: Vec<PathBuf>,
+        #[arg(short='E', long, help="Treat the pattern as a regular expression")]
+        regex: bool,
+        #[arg(short, long, help="Descend into directories")]
+        recursive: bool,
+        #[arg(long, help="Show a diff of pending changes without writing")]
+        preview: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Split a file into numbered parts")]
+    #[command(group(clap::ArgGroup::new("by").required(true).args(["bytes", "lines", "chunks"])))]
+    Split {
+        #[arg(required(true))]
+        filename: String,
+        #[arg(short, long, value_parser=parse_size, help="Size of each part, e.g. 100M")]
+        bytes: Option<u64>,
+        #[arg(short, long, help="Number of lines in each part")]
+        lines: Option<u64>,
+        #[arg(short='n', long, help="Split into this many parts of equal size")]
+        chunks: Option<u64>,
+        #[arg(short, long, help="Directory to write the parts to (default: next to the file)")]
+        output_dir: Option<String>,
+        #[arg(short, long, help="Also write a manifest with per-part checksums")]
+        manifest: bool,
+    },
+    #[command(about="Pack files and directories into a new archive")]
+    Pack {
+        #[arg(required(true))]
+        archive: PathBuf,
+        #[arg(required(true))]
+        paths: Vec<PathBuf>,
+        #[arg(long, value_enum, help="Archive format (default: from the archive's extension)")]
+        format: Option<ArchiveFormat>,
+    },
+    #[command(about="Extract an archive without overwriting existing files")]
+    Unpack {
+        #[arg(required(true))]
+        archive: PathBuf,
+        #[arg(default_value=".")]
+        dest: PathBuf,
+        #[arg(long, value_enum, help="Archive format (default: from the archive's extension)")]
+        format: Option<ArchiveFormat>,
+    },
+    #[command(about="Encrypt a file with age to recipients or a passphrase")]
+    Encrypt {
+        #[arg(required(true))]
+        src_file: PathBuf,
+        #[arg(required(true))]
+        dst_file: PathBuf,
+        #[arg(short, long, value_name="RECIPIENT", help="Age public key or recipients file; may be repeated")]
+        recipient: Vec<String>,
+        #[arg(short, long, conflicts_with="recipient", help="Encrypt with a passphrase from --passphrase-file or FILEY_PASSPHRASE")]
+        passphrase: bool,
+        #[arg(long, requires="passphrase", help="Read the passphrase from the first line of this file")]
+        passphrase_file: Option<PathBuf>,
+    },
+    #[command(about="Decrypt an age encrypted file")]
+    Decrypt {
+        #[arg(required(true))]
+        src_file: PathBuf,
+        #[arg(required(true))]
+        dst_file: PathBuf,
+        #[arg(short, long, value_name="FILE", help="Age identity file; may be repeated")]
+        identity: Vec<PathBuf>,
+        #[arg(short, long, conflicts_with="identity", help="Decrypt with a passphrase from --passphrase-file or FILEY_PASSPHRASE")]
+        passphrase: bool,
+        #[arg(long, requires="passphrase", help="Read the passphrase from the first line of this file")]
+        passphrase_file: Option<PathBuf>,
+    },
+    #[command(about="Compare two files or directories, exiting 0 if identical, 1 if different and 2 on error")]
+    Diff {
+        #[arg(required(true))]
+        a: PathBuf,
+        #[arg(required(true))]
+        b: PathBuf,
+        #[arg(short='U', long, value_name="N", default_value_t=3, help="Lines of context around each change")]
+        unified: usize,
+        #[arg(short, long, help="Compare directory entries by content hash instead of size and mtime")]
+        checksum: bool,
+        #[arg(long, help="Print a plain unified diff even on a terminal")]
+        no_color: bool,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Copy new and changed files from one directory to another")]
+    Sync {
+        #[arg(required(true))]
+        src: PathBuf,
+        #[arg(required(true))]
+        dst: PathBuf,
+        #[arg(short, long, help="Compare files by content hash instead of size and mtime")]
+        checksum: bool,
+        #[arg(long, help="Delete destination files that are not in the source")]
+        delete: bool,
+        #[arg(short='n', long, help="Show what would change without writing")]
+        dry_run: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Find files with identical contents and optionally collapse them")]
+    Dedupe {
+        #[arg(required(true))]
+        paths: Vec<PathBuf>,
+        #[arg(short, long, help="Descend into directories")]
+        recursive: bool,
+        #[arg(long, value_enum, default_value_t=DedupeAction::Report, help="What to do with each duplicate")]
+        action: DedupeAction,
+        #[arg(long, value_enum, default_value_t=KeepPolicy::Oldest, help="Which file of each set to keep")]
+        keep: KeepPolicy,
+        #[arg(short='n', long, help="Show what would be done without changing anything")]
+        dry_run: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Show detailed metadata for a file")]
+    Info {
+        #[arg(required(true))]
+        path: PathBuf,
+        #[arg(short='L', long, help="Describe the target of a symlink instead of the link")]
+        dereference: bool,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Identify file types from their contents")]
+    Type {
+        #[arg(required(true))]
+        paths: Vec<PathBuf>,
+        #[arg(short, long, help="Descend into directories")]
+        recursive: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="List files and directory contents")]
+    Ls {
+        #[arg(default_value=".")]
+        paths: Vec<PathBuf>,
+        #[arg(long, default_value_t=1, help="How many directory levels to list")]
+        depth: usize,
+        #[command(flatten)]
+        list: ListArgs,
+    },
+    #[command(about="Show directories as an indented tree")]
+    Tree {
+        #[arg(default_value=".")]
+        paths: Vec<PathBuf>,
+        #[arg(short='L', long, help="How many directory levels to descend (default: all)")]
+        depth: Option<usize>,
+        #[command(flatten)]
+        list: ListArgs,
+    },
+    #[command(about="Summarize disk usage per directory, file, extension or owner")]
+    Du {
+        #[arg(default_value=".")]
+        paths: Vec<PathBuf>,
+        #[arg(short='d', long, help="Deepest directory level to list (default: all)")]
+        max_depth: Option<usize>,
+        #[arg(short='H', long, help="Print sizes like 1.5K and 10M")]
+        human_readable: bool,
+        #[arg(long, help="Rank by apparent size instead of allocated size")]
+        apparent: bool,
+        #[arg(long, value_name="N", conflicts_with_all=["by_extension", "by_owner"], help="Show the N largest files and directories")]
+        top: Option<usize>,
+        #[arg(long, conflicts_with="by_owner", help="Break usage down by file extension")]
+        by_extension: bool,
+        #[arg(long, help="Break usage down by file owner")]
+        by_owner: bool,
+        #[arg(long, value_enum, default_value_t=DuFormat::Text)]
+        format: DuFormat,
+    },
+    #[command(
+        about="Search for files matching an expression",
+        after_help="\
+Tests:
+  --name GLOB, --iname GLOB   file name matches (--iname ignores case)
+  --regex RE                  whole path contains a match for RE
+  --size [+|-]N | A..B        size in bytes, with K, M, G or T suffixes
+  --mtime, --atime [+|-]AGE   modified/accessed more (+) or less (-) than AGE ago, e.g. 7d, 2h
+  --type f|d|l|p|s|c|b        kind of entry
+  --perm [-|/]MODE            octal mode exactly, all bits (-) or any bit (/)
+  --user NAME, --group NAME   owner by name or id
+  --empty                     empty file or directory
+  --contains RE               file contents contain a match for RE
+
+Operators, by increasing precedence: --or, --and (implied), --not, ( … ).
+Actions: --print0, --delete, --exec CMD [ARG|{}]… ';' or '+'."
+    )]
+    Find {
+        #[arg(trailing_var_arg=true, allow_hyphen_values=true, value_name="PATH|EXPRESSION")]
+        args: Vec<String>,
+    },
+    #[command(about="Search file contents for lines matching a regular expression")]
+    Grep {
+        #[arg(required(true))]
+        pattern: String,
+        #[arg(required(true))]
+        paths: Vec<PathBuf>,
+        #[arg(short, long, help="Descend into directories")]
+        recursive: bool,
+        #[arg(short, long, help="Match without regard to case")]
+        ignore_case: bool,
+        #[arg(short='F', long, help="Treat the pattern as a literal string")]
+        fixed_strings: bool,
+        #[arg(short='A', long, value_name="N", help="Lines of context after each match")]
+        after_context: Option<usize>,
+        #[arg(short='B', long, value_name="N", help="Lines of context before each match")]
+        before_context: Option<usize>,
+        #[arg(short='C', long, value_name="N", help="Lines of context around each match")]
+        context: Option<usize>,
+        #[arg(short, long, conflicts_with="files_with_matches", help="Only print the number of matching lines per file")]
+        count: bool,
+        #[arg(short='l', long, help="Only print the names of matching files")]
+        files_with_matches: bool,
+        #[arg(short='a', long, help="Search binary files as if they were text")]
+        text: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Rename many files at once from a pattern, showing the plan first")]
+    Rename {
+        #[arg(required(true))]
+        paths: Vec<PathBuf>,
+        #[arg(short, long, help="Descend into directories")]
+        recursive: bool,
+        #[arg(short, long, conflicts_with_all=["pattern", "replace", "case"], help="Edit the names in $VISUAL or $EDITOR; removed lines are deleted")]
+        edit: bool,
+        #[arg(short='m', long="match", value_name="REGEX", requires="replace", help="Rewrite only the parts of each name matching this")]
+        pattern: Option<String>,
+        #[arg(
+            short='s',
+            long,
+            value_name="TEMPLATE",
+            allow_hyphen_values=true,
+            help="New name or replacement; expands $1, {n[:WIDTH]}, {date}, {date:FORMAT}, {name}, {stem}, {ext}"
+        )]
+        replace: Option<String>,
+        #[arg(long, value_enum, help="Change the case of the new names")]
+        case: Option<CaseStyle>,
+        #[arg(long, default_value_t=1, help="First number used for the {n[:WIDTH]} counter")]
+        start: usize,
+        #[arg(long, value_enum, default_value_t=DateSource::Exif, help="Where {date} comes from")]
+        date_from: DateSource,
+        #[arg(short='n', long, help="Show the renames without applying them")]
+        dry_run: bool,
+        #[arg(short, long, help="Apply the renames without asking")]
+        yes: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Revert the last renames and deletions recorded in the journal")]
+    Undo,
+    #[command(about="Create a hard link, or a symbolic link with --symbolic")]
+    Link {
+        #[arg(required(true))]
+        target: PathBuf,
+        #[arg(required(true))]
+        link: PathBuf,
+        #[arg(short, long, help="Create a symbolic link storing the target's absolute path")]
+        symbolic: bool,
+        #[arg(short, long, requires="symbolic", help="Store the target relative to the link's directory")]
+        relative: bool,
+    },
+    #[command(about="Follow a symbolic link hop by hop to its canonical path")]
+    Readlink {
+        #[arg(required(true))]
+        path: PathBuf,
+        #[arg(short='f', long, help="Only print the canonical path; the path need not be a link")]
+        canonicalize: bool,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="List symbolic links and their targets")]
+    Links {
+        #[arg(default_value=".")]
+        paths: Vec<PathBuf>,
+        #[arg(long, help="Only list links whose target does not exist")]
+        broken: bool,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Change permissions, symbolically like u+rwX,go-w or in octal")]
+    Chmod {
+        #[arg(
+            required(true),
+            allow_hyphen_values=true,
+            help="Mode for files and directories alike, e.g. 644 or u+rwX,go-w; left out when both --files and --dirs are given"
+        )]
+        mode: String,
+        paths: Vec<PathBuf>,
+        #[arg(short='R', long, short_alias='r', help="Descend into directories")]
+        recursive: bool,
+        #[arg(long="files", value_name="MODE", value_parser=parse_mode_spec, help="Mode for files instead of MODE")]
+        file_mode: Option<ModeSpec>,
+        #[arg(long="dirs", value_name="MODE", value_parser=parse_mode_spec, help="Mode for directories instead of MODE")]
+        dir_mode: Option<ModeSpec>,
+        #[arg(short='n', long, help="Show the changes without making them")]
+        dry_run: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Change the owning user and/or group, by name or id")]
+    Chown {
+        #[arg(required(true), value_name="USER[:GROUP]", help="New owner: USER, USER:GROUP, USER: or :GROUP")]
+        owner: String,
+        #[arg(required(true))]
+        paths: Vec<PathBuf>,
+        #[arg(short='R', long, short_alias='r', help="Descend into directories")]
+        recursive: bool,
+        #[arg(short='n', long, help="Show the changes without making them")]
+        dry_run: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(
+        about="Report risky permissions, ownership and secret-looking files, exiting 1 on findings and 2 on error",
+        after_help="Checks: world-writable files and directories, group-writable directories without the sticky bit, \
+setuid and setgid files, owners and groups missing from the user database, and credential file names such as \
+id_rsa, .env and *.pem."
+    )]
+    Audit {
+        #[arg(default_value=".")]
+        paths: Vec<PathBuf>,
+        #[arg(long, value_enum, default_value_t=Severity::Low, help="Least severe finding that makes the audit fail")]
+        fail_on: Severity,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(about="Watch files and directories, printing changes and optionally running a command or sync on each")]
+    Watch {
+        #[arg(required(true))]
+        paths: Vec<PathBuf>,
+        #[arg(long, value_name="CMD", conflicts_with="sync", help="Run CMD with sh after each batch of changes, with the changed paths as $1, $2, ...")]
+        exec: Option<String>,
+        #[arg(long, value_name="DIR", help="Mirror the watched directory into DIR at start and after each batch of changes")]
+        sync: Option<PathBuf>,
+        #[arg(long, requires="sync", help="Remove entries from DIR that no longer exist in the watched directory")]
+        delete: bool,
+        #[arg(long, value_name="MS", default_value_t=200, help="Wait until no changes arrive for this many milliseconds")]
+        debounce: u64,
+        #[arg(long, help="Exit after the first batch of changes")]
+        once: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(
+        about="Run a daemon that queues create, copy, cat and delete jobs sent over a Unix socket",
+        after_help="The daemon speaks JSON-RPC 2.0, one message per line. Jobs are submitted with the methods create, \
+copy, cat and delete, and managed with status, jobs, cancel and wait. Pass \"wait\": true to receive progress \
+notifications and the final job status in the reply."
+    )]
+    Daemon {
+        #[arg(long, value_name="PATH", help="Socket to listen on (default: $FILEY_SOCKET or $XDG_RUNTIME_DIR/filey.sock)")]
+        socket: Option<PathBuf>,
+        #[arg(short, long, value_name="N", help="Run at most N jobs at once (default: number of CPUs)")]
+        jobs: Option<usize>,
     },
     #[command(about="Delete an existing file")]
     Del {
@@ -43,36 +556,690 @@ enum Commands {
         filename: String,
     },
 }
+/// Include/exclude globs shared by commands that walk directories.
+#[derive(clap::Args)]
+struct FilterArgs {
+    #[arg(long, value_name="GLOB", help="Only consider files matching this glob")]
+    include: Vec<String>,
+    #[arg(long, value_name="GLOB", help="Skip files and directories matching this glob")]
+    exclude: Vec<String>,
+    #[arg(long="type", value_enum, value_name="TYPE", help="Only consider files whose contents are of this type")]
+    types: Vec<Category>,
+    #[arg(long="where", value_name="EXPR", allow_hyphen_values=true, help="Only consider files matching a find expression, e.g. '--size +1M --mtime -7d'")]
+    expr: Option<String>,
+}
+
+impl FilterArgs {
+    fn filter(&self) -> Result<Filter> {
+        let filter = Filter::new(&self.include, &self.exclude)?.with_types(&self.types);
+        let Some(expr) = &self.expr else {
+            return Ok(filter);
+        };
+        let tokens = shlex::split(expr).ok_or_else(|| anyhow!("Invalid expression: {}", expr))?;
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        Ok(filter.with_expr(parse_expr(&tokens)?))
+    }
+}
+
+/// Display options shared by `ls` and `tree`.
+#[derive(clap::Args)]
+struct ListArgs {
+    #[arg(short, long, help="Show entries whose names start with a dot")]
+    all: bool,
+    #[arg(short, long, help="Show permissions, owners, sizes and modification times")]
+    long: bool,
+    #[arg(short='H', long, help="Show sizes like 1.5K and 20M")]
+    human_readable: bool,
+    #[arg(long, value_enum, default_value_t=SortKey::Natural)]
+    sort: SortKey,
+    #[arg(short, long, help="Reverse the sort order")]
+    reverse: bool,
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[arg(long, value_enum, default_value_t=OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+impl ListArgs {
+    fn options(&self, depth: Option<usize>) -> Result<ListOptions> {
+        Ok(ListOptions {
+            all: self.all,
+            long: self.long,
+            human: self.human_readable,
+            sort: self.sort,
+            reverse: self.reverse,
+            depth,
+            filter: self.filter.filter()?,
+            color: std::io::stdout().is_terminal(),
+            format: self.format,
+        })
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    // diff(1) reports differences and grep(1) a lack of matches with 1, so
+    // their errors use 2.
+    let error_code = if matches!(cli.command, Commands::Diff { .. } | Commands::Grep { .. } | Commands::Audit { .. }) { 2 } else { 1 };
+    let mut exit_code = 0;
+    // Entries the walk could not read, already reported on stderr.
+    let mut skipped = 0;
+    let mut walked = |(paths, errors): (Vec<PathBuf>, usize)| {
+        skipped += errors;
+        paths
+    };
+
+    if let Some(socket) = &cli.remote {
+        let msg = remote_operation(&cli.command).and_then(|op| run_remote(socket, &op))?;
+        if !msg.is_empty() {
+            println!("{}", msg);
+        }
+        return Ok(());
+    }
 
     let res = match &cli.command {
-        Commands::Create { filename, text } => {
-            create_file(Path::new(filename), text.as_deref())
+        Commands::Create {
+            filename,
+            text,
+            parents,
+            mode,
+            from_stdin,
+            from,
+            size,
+            sparse,
+            template,
+            vars,
+            on_conflict,
+        } => {
+            let source = match (text, from, from_stdin) {
+                (Some(t), _, _) => Source::Text(t),
+                (_, Some(f), _) => Source::File(Path::new(f)),
+                (_, _, true) => Source::Stdin,
+                _ => Source::Empty,
+            };
+            let opts = CreateOptions {
+                conflict: *on_conflict,
+                parents: *parents,
+                mode: *mode,
+                size: *size,
+                sparse: *sparse,
+                progress: None,
+            };
+            match template {
+                Some(name) => create_from_template(name, Path::new(filename), vars, &template_dirs(), &opts),
+                None => create_file_from(Path::new(filename), source, &opts),
+            }
         }
-        Commands::Copy { src_file, dst_file } => {
-            copy_file(Path::new(src_file), Path::new(dst_file))
+        Commands::Copy {
+            src_file,
+            dst_file,
+            compress,
+            encrypt_to,
+            dereference: _,
+            no_dereference,
+        } => {
+            let opts = TransferOptions {
+                decompress: false,
+                compress: *compress,
+                encrypt_to: encrypt_to.clone(),
+                no_dereference: *no_dereference,
+                progress: None,
+            };
+            copy_file_with(Path::new(src_file), Path::new(dst_file), &opts)
         }
         Commands::Cat {
             dst_file,
             src_file1,
             src_file2,
-        } => cat_files(
-            Path::new(src_file1),
-            Path::new(src_file2),
-            Path::new(dst_file),
-        ),
+            manifest,
+            output,
+            decompress,
+            compress,
+        } => match (manifest, src_file1, src_file2, dst_file) {
+            (Some(m), _, _, _) => cat_manifest(Path::new(m), output.as_deref().map(Path::new)),
+            (None, Some(src_file1), Some(src_file2), Some(dst_file)) => cat_files_with(
+                Path::new(src_file1),
+                Path::new(src_file2),
+                Path::new(dst_file),
+                &TransferOptions {
+                    decompress: *decompress,
+                    compress: *compress,
+                    encrypt_to: Vec::new(),
+                    no_dereference: false,
+                    progress: None,
+                },
+            ),
+            _ => unreachable!("clap requires the source and destination files"),
+        },
+        Commands::Touch {
+            access,
+            modify,
+            date,
+            reference,
+            no_create,
+            filenames,
+        } => resolve_times(date.as_deref(), reference.as_deref().map(Path::new), *access, *modify)
+            .and_then(|(atime, mtime)| {
+                filenames
+                    .iter()
+                    .map(|f| touch_file(Path::new(f), atime, mtime, *no_create))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .map(|msgs| msgs.join("\n")),
+        Commands::Write {
+            text,
+            from_stdin,
+            from,
+            append,
+            prepend,
+            insert_at_line,
+            replace_between,
+            filename,
+        } => {
+            let source = match (text, from, from_stdin) {
+                (Some(t), _, _) => Source::Text(t),
+                (_, Some(f), _) => Source::File(Path::new(f)),
+                _ => Source::Stdin,
+            };
+            let mode = match (append, prepend, insert_at_line, replace_between) {
+                (true, _, _, _) => WriteMode::Append,
+                (_, true, _, _) => WriteMode::Prepend,
+                (_, _, Some(n), _) => WriteMode::InsertAt(*n),
+                (_, _, _, Some(markers)) => WriteMode::ReplaceBetween(&markers[0], &markers[1]),
+                _ => unreachable!("clap requires a position"),
+            };
+            source
+                .read_all()
+                .and_then(|t| write_file(Path::new(filename), &t, &mode))
+        }
+        Commands::Replace {
+            pattern,
+            replacement,
+            paths,
+            regex,
+            recursive,
+            preview,
+            filter,
+            format,
+        } => filter
+            .filter()
+            .and_then(|f| walk_files(paths, *recursive, &f))
+            .map(&mut walked)
+            .and_then(|files| {
+                let opts = ReplaceOptions {
+                    regex: *regex,
+                    preview: *preview,
+                    color: std::io::stdout().is_terminal(),
+                    format: *format,
+                };
+                replace_in_files(pattern, replacement, &files, &opts)
+            })
+            .map(|(report, failed)| {
+                exit_code = i32::from(failed);
+                report
+            }),
+        Commands::Split {
+            filename,
+            bytes,
+            lines,
+            chunks,
+            output_dir,
+            manifest,
+        } => {
+            let by = match (bytes, lines, chunks) {
+                (Some(b), _, _) => SplitBy::Bytes(*b),
+                (_, Some(l), _) => SplitBy::Lines(*l),
+                (_, _, Some(c)) => SplitBy::Chunks(*c),
+                _ => unreachable!("clap requires a split mode"),
+            };
+            split_file(Path::new(filename), by, output_dir.as_deref().map(Path::new), *manifest)
+        }
+        Commands::Pack {
+            archive,
+            paths,
+            format,
+        } => pack(archive, paths, *format),
+        Commands::Unpack {
+            archive,
+            dest,
+            format,
+        } => unpack(archive, dest, *format),
+        Commands::Encrypt {
+            src_file,
+            dst_file,
+            recipient,
+            passphrase,
+            passphrase_file,
+        } => {
+            let key = if *passphrase {
+                read_passphrase(passphrase_file.as_deref()).map(EncryptKey::Passphrase)
+            } else {
+                Ok(EncryptKey::Recipients(recipient.clone()))
+            };
+            key.and_then(|k| encrypt_file(src_file, dst_file, &k))
+        }
+        Commands::Decrypt {
+            src_file,
+            dst_file,
+            identity,
+            passphrase,
+            passphrase_file,
+        } => {
+            let key = if *passphrase {
+                read_passphrase(passphrase_file.as_deref()).map(DecryptKey::Passphrase)
+            } else if identity.is_empty() {
+                Err(anyhow!("No identity given, use --identity or --passphrase"))
+            } else {
+                Ok(DecryptKey::Identities(identity.clone()))
+            };
+            key.and_then(|k| decrypt_file(src_file, dst_file, &k))
+        }
+        Commands::Diff {
+            a,
+            b,
+            unified,
+            checksum,
+            no_color,
+            format,
+        } => {
+            let opts = DiffOptions {
+                context: *unified,
+                checksum: *checksum,
+                color: !*no_color && std::io::stdout().is_terminal(),
+                format: *format,
+            };
+            diff_paths(a, b, &opts).map(|(report, differ)| {
+                exit_code = i32::from(differ);
+                report
+            })
+        }
+        Commands::Sync {
+            src,
+            dst,
+            checksum,
+            delete,
+            dry_run,
+            filter,
+            format,
+        } => filter.filter().and_then(|filter| {
+            let opts = SyncOptions {
+                checksum: *checksum,
+                delete: *delete,
+                dry_run: *dry_run,
+                filter,
+                color: std::io::stdout().is_terminal(),
+                format: *format,
+            };
+            sync_dirs(src, dst, &opts)
+        }),
+        Commands::Dedupe {
+            paths,
+            recursive,
+            action,
+            keep,
+            dry_run,
+            filter,
+            format,
+        } => filter
+            .filter()
+            .and_then(|f| walk_files(paths, *recursive, &f))
+            .map(&mut walked)
+            .and_then(|files| {
+                let opts = DedupeOptions {
+                    action: *action,
+                    keep: *keep,
+                    dry_run: *dry_run,
+                    color: std::io::stdout().is_terminal(),
+                    format: *format,
+                };
+                dedupe_files(&files, &opts)
+            }),
+        Commands::Info {
+            path,
+            dereference,
+            format,
+        } => file_info(path, *dereference, *format),
+        Commands::Type {
+            paths,
+            recursive,
+            filter,
+            format,
+        } => filter
+            .filter()
+            .and_then(|f| walk_files(paths, *recursive, &f))
+            .map(&mut walked)
+            .and_then(|files| type_files(&files, std::io::stdout().is_terminal(), *format)),
+        Commands::Ls { paths, depth, list } => list
+            .options(Some(*depth))
+            .and_then(|opts| list_paths(paths, &opts)),
+        Commands::Tree { paths, depth, list } => list
+            .options(*depth)
+            .and_then(|opts| tree_paths(paths, &opts)),
+        Commands::Du {
+            paths,
+            max_depth,
+            human_readable,
+            apparent,
+            top,
+            by_extension,
+            by_owner,
+            format,
+        } => {
+            let view = match (top, by_extension, by_owner) {
+                (Some(n), _, _) => DuView::Top(*n),
+                (_, true, _) => DuView::ByExtension,
+                (_, _, true) => DuView::ByOwner,
+                _ => DuView::Directories,
+            };
+            let opts = DuOptions {
+                view,
+                max_depth: *max_depth,
+                apparent: *apparent,
+                human: *human_readable,
+                format: *format,
+            };
+            disk_usage(paths, &opts)
+        }
+        Commands::Find { args } => parse_query(args).and_then(|q| find(&q)).map(|(out, failed)| {
+            exit_code = i32::from(failed);
+            out
+        }),
+        Commands::Grep {
+            pattern,
+            paths,
+            recursive,
+            ignore_case,
+            fixed_strings,
+            after_context,
+            before_context,
+            context,
+            count,
+            files_with_matches,
+            text,
+            filter,
+            format,
+        } => filter
+            .filter()
+            .and_then(|f| walk_files(paths, *recursive, &f))
+            .map(&mut walked)
+            .and_then(|files| {
+                let opts = GrepOptions {
+                    ignore_case: *ignore_case,
+                    fixed: *fixed_strings,
+                    before: before_context.or(*context).unwrap_or(0),
+                    after: after_context.or(*context).unwrap_or(0),
+                    count: *count,
+                    files_only: *files_with_matches,
+                    binary: *text,
+                    with_filename: *recursive || files.len() > 1,
+                    color: std::io::stdout().is_terminal(),
+                    format: *format,
+                };
+                grep_files(pattern, &files, &opts)
+            })
+            .map(|(report, found, failed)| {
+                // Like grep: 2 when a file could not be read, else 1 for no match.
+                exit_code = if failed { 2 } else { i32::from(!found) };
+                report
+            }),
+        Commands::Rename {
+            paths,
+            recursive,
+            edit,
+            pattern,
+            replace,
+            case,
+            start,
+            date_from,
+            dry_run,
+            yes,
+            filter,
+            format,
+        } => filter
+            .filter()
+            .and_then(|f| walk_files(paths, *recursive, &f))
+            .map(&mut walked)
+            .and_then(|files| {
+                let opts = RenameOptions {
+                    pattern: pattern.clone(),
+                    template: replace.clone(),
+                    case: *case,
+                    start: *start,
+                    date_source: *date_from,
+                    dry_run: *dry_run,
+                    yes: *yes,
+                    journal: Some(journal_path()),
+                    format: *format,
+                };
+                if *edit {
+                    edit_renames(&files, &opts)
+                } else {
+                    rename_files(&files, &opts)
+                }
+            }),
+        Commands::Undo => undo_last(&journal_path()),
+        Commands::Link {
+            target,
+            link,
+            symbolic,
+            relative,
+        } => {
+            let opts = LinkOptions {
+                symbolic: *symbolic,
+                relative: *relative,
+            };
+            link_file(target, link, &opts)
+        }
+        Commands::Readlink {
+            path,
+            canonicalize,
+            format,
+        } => read_link(path, *canonicalize, *format),
+        Commands::Links { paths, broken, format } => {
+            list_links(paths, *broken, std::io::stdout().is_terminal(), *format)
+        }
+        Commands::Chmod {
+            mode,
+            paths,
+            recursive,
+            file_mode,
+            dir_mode,
+            dry_run,
+            filter,
+            format,
+        } => chmod_modes(mode, paths, file_mode, dir_mode).and_then(|(paths, file_mode, dir_mode)| {
+            filter
+                .filter()
+                .and_then(|f| walk_entries(&paths, *recursive, &f))
+                .map(&mut walked)
+                .and_then(|paths| {
+                    let opts = ChmodOptions {
+                        file_mode: Some(file_mode),
+                        dir_mode: Some(dir_mode),
+                        dry_run: *dry_run,
+                        format: *format,
+                    };
+                    change_modes(&paths, &opts)
+                })
+        }),
+        Commands::Chown {
+            owner,
+            paths,
+            recursive,
+            dry_run,
+            filter,
+            format,
+        } => parse_owner(owner).and_then(|owner| {
+            filter
+                .filter()
+                .and_then(|f| walk_entries(paths, *recursive, &f))
+                .map(&mut walked)
+                .and_then(|paths| change_owners(&paths, owner, *dry_run, *format))
+        }),
+        Commands::Audit {
+            paths,
+            fail_on,
+            filter,
+            format,
+        } => filter
+            .filter()
+            .and_then(|f| {
+                let opts = AuditOptions {
+                    fail_on: *fail_on,
+                    color: std::io::stdout().is_terminal(),
+                    format: *format,
+                };
+                audit_paths(paths, &f, &opts)
+            })
+            .map(|(report, failed)| {
+                exit_code = i32::from(failed);
+                report
+            }),
+        Commands::Watch {
+            paths,
+            exec,
+            sync,
+            delete,
+            debounce,
+            once,
+            filter,
+            format,
+        } => filter.filter().and_then(|filter| {
+            let action = match (exec, sync) {
+                (Some(cmd), _) => WatchAction::Exec(cmd.clone()),
+                (None, Some(dst)) => WatchAction::Sync {
+                    dst: dst.clone(),
+                    opts: SyncOptions {
+                        delete: *delete,
+                        filter: filter.clone(),
+                        color: std::io::stdout().is_terminal(),
+                        ..Default::default()
+                    },
+                },
+                (None, None) => WatchAction::Print,
+            };
+            let opts = WatchOptions {
+                debounce: std::time::Duration::from_millis(*debounce),
+                filter,
+                action,
+                once: *once,
+                format: *format,
+            };
+            watch(paths, &opts, &mut std::io::stdout(), &mut std::io::stderr())
+        }),
+        Commands::Daemon { socket, jobs } => {
+            let jobs = jobs.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+            serve(&socket.clone().unwrap_or_else(socket_path), jobs, &mut std::io::stdout())
+        }
         Commands::Del { filename } => delete_file(Path::new(filename)),
     };
 
+    if skipped > 0 {
+        exit_code = exit_code.max(error_code);
+    }
     match res {
         Ok(msg) => {
-            println!("{}", msg);
+            if !msg.is_empty() {
+                println!("{}", msg);
+            }
+            if exit_code != 0 {
+                std::process::exit(exit_code);
+            }
             Ok(())
         }
+        Err(e) if error_code != 1 => {
+            eprintln!("Error: {}", e);
+            std::process::exit(error_code)
+        }
         Err(e) => {
             Err(anyhow!(e.to_string()))
         }
     }
   
 }
+
+/// Sort out the positional arguments of `chmod`: MODE comes first unless
+/// both --files and --dirs are given, which leave nothing for it, so that
+/// every argument is a path.
+fn chmod_modes(
+    mode: &str,
+    paths: &[PathBuf],
+    file_mode: &Option<ModeSpec>,
+    dir_mode: &Option<ModeSpec>,
+) -> Result<(Vec<PathBuf>, ModeSpec, ModeSpec)> {
+    if let (Some(file_mode), Some(dir_mode)) = (file_mode, dir_mode) {
+        let paths = std::iter::once(PathBuf::from(mode)).chain(paths.iter().cloned()).collect();
+        return Ok((paths, file_mode.clone(), dir_mode.clone()));
+    }
+    if paths.is_empty() {
+        return Err(anyhow!("No paths given"));
+    }
+    let mode = parse_mode_spec(mode)?;
+    Ok((
+        paths.to_vec(),
+        file_mode.clone().unwrap_or_else(|| mode.clone()),
+        dir_mode.clone().unwrap_or(mode),
+    ))
+}
+
+/// The daemon job for a command run with `--remote`. Only commands that map
+/// onto a single `cmd` operation can run remotely.
+fn remote_operation(command: &Commands) -> Result<Operation> {
+    match command {
+        Commands::Create { from_stdin: true, .. } => Err(anyhow!("--from-stdin cannot be used with --remote")),
+        Commands::Create { template: Some(_), .. } => Err(anyhow!("--template cannot be used with --remote")),
+        Commands::Create {
+            filename,
+            text,
+            parents,
+            mode,
+            from,
+            size,
+            sparse,
+            on_conflict,
+            ..
+        } => Ok(Operation::Create {
+            path: PathBuf::from(filename),
+            text: text.clone(),
+            from: from.as_ref().map(PathBuf::from),
+            parents: *parents,
+            mode: mode.map(|m| format!("{:04o}", m)),
+            size: *size,
+            sparse: *sparse,
+            on_conflict: *on_conflict,
+        }),
+        Commands::Copy {
+            src_file,
+            dst_file,
+            compress,
+            encrypt_to,
+            no_dereference,
+            ..
+        } => Ok(Operation::Copy {
+            src: PathBuf::from(src_file),
+            dst: PathBuf::from(dst_file),
+            compress: compress.map(|c| c.to_string()),
+            encrypt_to: encrypt_to.clone(),
+            no_dereference: *no_dereference,
+        }),
+        Commands::Cat { manifest: Some(_), .. } => Err(anyhow!("--manifest cannot be used with --remote")),
+        Commands::Cat {
+            src_file1: Some(src1),
+            src_file2: Some(src2),
+            dst_file: Some(dst),
+            decompress,
+            compress,
+            ..
+        } => Ok(Operation::Cat {
+            src1: PathBuf::from(src1),
+            src2: PathBuf::from(src2),
+            dst: PathBuf::from(dst),
+            decompress: *decompress,
+            compress: compress.map(|c| c.to_string()),
+        }),
+        Commands::Del { filename } => Ok(Operation::Delete {
+            path: PathBuf::from(filename),
+        }),
+        _ => Err(anyhow!("Only create, copy, cat and del can run with --remote")),
+    }
+}
@@ -0,0 +1,468 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::{
+    fs::{self, Permissions},
+    os::unix::fs::{chown, MetadataExt, PermissionsExt},
+    path::PathBuf,
+};
+
+use crate::cmd::parse_mode;
+use crate::info::{group_id, group_name, symbolic_mode, user_id, user_name};
+use crate::output::{to_json, OutputFormat};
+
+/// A mode to set, as given to chmod(1): an absolute octal mode like `755`,
+/// or comma-separated symbolic clauses like `u+rwX,go-w`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModeSpec {
+    Octal(u32),
+    Symbolic(Vec<Clause>),
+}
+
+/// One symbolic clause: the bits of the classes in `who`, changed by each
+/// action in turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clause {
+    /// Mask of the permission bits the clause may touch.
+    who: u32,
+    actions: Vec<(char, Perms)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Perms {
+    /// Any of `rwxXst`.
+    Bits(String),
+    /// The current permissions of another class, `u`, `g` or `o`.
+    Copy(char),
+}
+
+/// Parse a chmod(1) mode. A clause without `u`, `g`, `o` or `a` applies to
+/// everyone; unlike chmod(1) the umask is not consulted.
+pub fn parse_mode_spec(spec: &str) -> Result<ModeSpec> {
+    let invalid = || anyhow!("Invalid mode: {}", spec);
+    if !spec.is_empty() && spec.chars().all(|c| c.is_digit(8)) {
+        return Ok(ModeSpec::Octal(parse_mode(spec)?));
+    }
+
+    let mut clauses = Vec::new();
+    for clause in spec.split(',') {
+        let mut chars = clause.chars().peekable();
+        let mut who = 0;
+        while let Some(c) = chars.next_if(|c| "ugoa".contains(*c)) {
+            who |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                _ => 0o7777,
+            };
+        }
+        if who == 0 {
+            who = 0o7777;
+        }
+
+        let mut actions = Vec::new();
+        while let Some(op) = chars.next() {
+            if !"+-=".contains(op) {
+                return Err(invalid());
+            }
+            let perms = match chars.next_if(|c| "ugo".contains(*c)) {
+                Some(class) => Perms::Copy(class),
+                None => {
+                    let mut bits = String::new();
+                    while let Some(c) = chars.next_if(|c| "rwxXst".contains(*c)) {
+                        bits.push(c);
+                    }
+                    Perms::Bits(bits)
+                }
+            };
+            actions.push((op, perms));
+        }
+        if actions.is_empty() {
+            return Err(invalid());
+        }
+        clauses.push(Clause { who, actions });
+    }
+    Ok(ModeSpec::Symbolic(clauses))
+}
+
+impl ModeSpec {
+    /// The permission bits that result from applying this to a file or
+    /// directory currently at `mode`.
+    pub fn apply(&self, mode: u32, is_dir: bool) -> u32 {
+        let clauses = match self {
+            ModeSpec::Octal(m) => return *m,
+            ModeSpec::Symbolic(clauses) => clauses,
+        };
+        let mut mode = mode & 0o7777;
+        for clause in clauses {
+            for (op, perms) in &clause.actions {
+                let bits = match perms {
+                    Perms::Copy(class) => {
+                        let shift = match class {
+                            'u' => 6,
+                            'g' => 3,
+                            _ => 0,
+                        };
+                        ((mode >> shift) & 0o7) * 0o111
+                    }
+                    Perms::Bits(chars) => chars.chars().fold(0, |bits, c| {
+                        bits | match c {
+                            'r' => 0o444,
+                            'w' => 0o222,
+                            'x' => 0o111,
+                            // execute only for directories and files that
+                            // someone may already execute
+                            'X' if is_dir || mode & 0o111 != 0 => 0o111,
+                            's' => 0o6000,
+                            't' => 0o1000,
+                            _ => 0,
+                        }
+                    }),
+                } & clause.who;
+                mode = match op {
+                    '+' => mode | bits,
+                    '-' => mode & !bits,
+                    _ => (mode & !clause.who) | bits,
+                };
+            }
+        }
+        mode
+    }
+}
+
+/// Options for `change_modes`.
+#[derive(Debug, Default)]
+pub struct ChmodOptions {
+    /// Mode for everything that is not a directory.
+    pub file_mode: Option<ModeSpec>,
+    /// Mode for directories.
+    pub dir_mode: Option<ModeSpec>,
+    /// Report the changes without making them.
+    pub dry_run: bool,
+    pub format: OutputFormat,
+}
+
+/// A user and/or group to hand files over to; `None` leaves that id alone.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// Parse an owner as given to chown(1): `USER`, `USER:GROUP`, `USER:` or
+/// `:GROUP`, where each part is a name or a numeric id.
+pub fn parse_owner(spec: &str) -> Result<Owner> {
+    let (user, group) = spec.split_once(':').unwrap_or((spec, ""));
+    let resolve = |name: &str, lookup: fn(&str) -> Option<u32>, what: &str| -> Result<Option<u32>> {
+        if name.is_empty() {
+            return Ok(None);
+        }
+        lookup(name)
+            .or_else(|| name.parse().ok())
+            .map(Some)
+            .ok_or_else(|| anyhow!("Unknown {}: {}", what, name))
+    };
+    let owner = Owner {
+        uid: resolve(user, user_id, "user")?,
+        gid: resolve(group, group_id, "group")?,
+    };
+    if owner == Owner::default() {
+        return Err(anyhow!("Invalid owner: {}", spec));
+    }
+    Ok(owner)
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    path: String,
+    before: String,
+    after: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    changes: Vec<Entry>,
+    unchanged: usize,
+    dry_run: bool,
+}
+
+/// Change the permissions of `paths`, using `opts.dir_mode` for directories
+/// and `opts.file_mode` for everything else. Paths whose mode would stay the
+/// same are left alone.
+pub fn change_modes(paths: &[PathBuf], opts: &ChmodOptions) -> Result<String> {
+    let mut report = Report {
+        changes: Vec::new(),
+        unchanged: 0,
+        dry_run: opts.dry_run,
+    };
+    for path in paths {
+        let meta = fs::metadata(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let spec = if meta.is_dir() { &opts.dir_mode } else { &opts.file_mode };
+        let before = meta.mode() & 0o7777;
+        let after = match spec {
+            Some(spec) => spec.apply(before, meta.is_dir()),
+            None => before,
+        };
+        if after == before {
+            report.unchanged += 1;
+            continue;
+        }
+        if !opts.dry_run {
+            fs::set_permissions(path, Permissions::from_mode(after))
+                .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        }
+        let kind = meta.mode() & libc::S_IFMT;
+        let show = |mode: u32| format!("{:04o} ({})", mode, &symbolic_mode(kind | mode)[1..]);
+        report.changes.push(Entry {
+            path: path.to_str().unwrap_or_default().to_owned(),
+            before: show(before),
+            after: show(after),
+        });
+    }
+    render(&report, "mode", opts.format)
+}
+
+/// Hand `paths` over to `owner`. Paths that already belong to it are left
+/// alone.
+pub fn change_owners(paths: &[PathBuf], owner: Owner, dry_run: bool, format: OutputFormat) -> Result<String> {
+    let mut report = Report {
+        changes: Vec::new(),
+        unchanged: 0,
+        dry_run,
+    };
+    let show = |uid: u32, gid: u32| {
+        let user = user_name(uid).unwrap_or_else(|| uid.to_string());
+        let group = group_name(gid).unwrap_or_else(|| gid.to_string());
+        format!("{}:{}", user, group)
+    };
+    for path in paths {
+        let meta = fs::metadata(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let (uid, gid) = (owner.uid.unwrap_or(meta.uid()), owner.gid.unwrap_or(meta.gid()));
+        if (uid, gid) == (meta.uid(), meta.gid()) {
+            report.unchanged += 1;
+            continue;
+        }
+        if !dry_run {
+            chown(path, owner.uid, owner.gid).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        }
+        report.changes.push(Entry {
+            path: path.to_str().unwrap_or_default().to_owned(),
+            before: show(meta.uid(), meta.gid()),
+            after: show(uid, gid),
+        });
+    }
+    render(&report, "owner", format)
+}
+
+fn render(report: &Report, what: &str, format: OutputFormat) -> Result<String> {
+    if format == OutputFormat::Json {
+        return to_json(report);
+    }
+    let mut lines: Vec<String> = report
+        .changes
+        .iter()
+        .map(|e| format!("{}: {} -> {}", e.path, e.before, e.after))
+        .collect();
+    let n = report.changes.len();
+    lines.push(if report.dry_run {
+        format!("Would change {} of {} files ({} unchanged)", what, n, report.unchanged)
+    } else {
+        format!("Changed {} of {} files successfully ({} unchanged)", what, n, report.unchanged)
+    });
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_parse_mode_spec() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            spec: &'a str,
+            mode: u32,
+            is_dir: bool,
+            result: Result<u32>,
+        }
+
+        let tests = &[
+            // failures
+            TestData {
+                spec: "",
+                mode: 0,
+                is_dir: false,
+                result: Err(anyhow!("Invalid mode")),
+            },
+            TestData {
+                spec: "17777",
+                mode: 0,
+                is_dir: false,
+                result: Err(anyhow!("Invalid octal mode")),
+            },
+            TestData {
+                spec: "u",
+                mode: 0,
+                is_dir: false,
+                result: Err(anyhow!("Invalid mode")),
+            },
+            TestData {
+                spec: "u+rq",
+                mode: 0,
+                is_dir: false,
+                result: Err(anyhow!("Invalid mode")),
+            },
+            TestData {
+                spec: "u+r,",
+                mode: 0,
+                is_dir: false,
+                result: Err(anyhow!("Invalid mode")),
+            },
+            // successes
+            TestData {
+                spec: "755",
+                mode: 0o4644,
+                is_dir: false,
+                result: Ok(0o755),
+            },
+            TestData {
+                spec: "u+rwX,go-w",
+                mode: 0o466,
+                is_dir: false,
+                result: Ok(0o644),
+            },
+            TestData {
+                spec: "u+rwX,go-w",
+                mode: 0o466,
+                is_dir: true,
+                result: Ok(0o744),
+            },
+            TestData {
+                spec: "a+X",
+                mode: 0o744,
+                is_dir: false,
+                result: Ok(0o755),
+            },
+            TestData {
+                spec: "+x",
+                mode: 0o600,
+                is_dir: false,
+                result: Ok(0o711),
+            },
+            TestData {
+                spec: "go=",
+                mode: 0o755,
+                is_dir: false,
+                result: Ok(0o700),
+            },
+            TestData {
+                spec: "g=u-w",
+                mode: 0o640,
+                is_dir: false,
+                result: Ok(0o640),
+            },
+            TestData {
+                spec: "u+s,g+s,o+t",
+                mode: 0o755,
+                is_dir: true,
+                result: Ok(0o7755),
+            },
+            TestData {
+                spec: "o+s",
+                mode: 0o755,
+                is_dir: false,
+                result: Ok(0o755),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let actual = parse_mode_spec(d.spec).map(|m| m.apply(d.mode, d.is_dir));
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok(expected) => assert_eq!(actual.unwrap(), *expected, "{}", msg),
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_owner() {
+        assert!(parse_owner(":").unwrap_err().to_string().contains("Invalid owner"));
+        assert!(parse_owner("no-such-user-here").unwrap_err().to_string().contains("Unknown user"));
+        assert!(parse_owner(":no-such-group-here").unwrap_err().to_string().contains("Unknown group"));
+        assert_eq!(parse_owner("root").unwrap(), Owner { uid: Some(0), gid: None });
+        assert_eq!(parse_owner("12345:").unwrap(), Owner { uid: Some(12345), gid: None });
+        assert_eq!(parse_owner(":54321").unwrap(), Owner { uid: None, gid: Some(54321) });
+        assert_eq!(parse_owner("0:0").unwrap(), Owner { uid: Some(0), gid: Some(0) });
+    }
+
+    #[test]
+    fn test_change_modes() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let (dir, file) = (test_dir.join("dir"), test_dir.join("dir/file"));
+        fs::create_dir(&dir).unwrap();
+        fs::write(&file, "x").unwrap();
+        fs::set_permissions(&dir, Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(&file, Permissions::from_mode(0o600)).unwrap();
+        let paths = [dir.clone(), file.clone()];
+        let mode = |p: &PathBuf| fs::metadata(p).unwrap().mode() & 0o7777;
+
+        let opts = ChmodOptions {
+            file_mode: Some(parse_mode_spec("644").unwrap()),
+            dir_mode: Some(parse_mode_spec("go+rX").unwrap()),
+            dry_run: true,
+            ..Default::default()
+        };
+        let out = change_modes(&paths, &opts).unwrap();
+        assert!(out.contains("dir: 0700 (rwx------) -> 0755 (rwxr-xr-x)\n"), "{}", out);
+        assert!(out.ends_with("Would change mode of 2 files (0 unchanged)"), "{}", out);
+        assert_eq!((mode(&dir), mode(&file)), (0o700, 0o600));
+
+        let opts = ChmodOptions { dry_run: false, ..opts };
+        let out = change_modes(&paths, &opts).unwrap();
+        assert!(out.ends_with("Changed mode of 2 files successfully (0 unchanged)"), "{}", out);
+        assert_eq!((mode(&dir), mode(&file)), (0o755, 0o644));
+
+        let opts = ChmodOptions {
+            file_mode: Some(parse_mode_spec("a-w").unwrap()),
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+        let out = change_modes(&paths, &opts).unwrap();
+        assert!(out.contains("\"before\": \"0644 (rw-r--r--)\",\n      \"after\": \"0444 (r--r--r--)\""), "{}", out);
+        assert!(out.contains("\"unchanged\": 1"), "{}", out);
+        assert_eq!((mode(&dir), mode(&file)), (0o755, 0o444));
+    }
+
+    #[test]
+    fn test_change_owners() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let file = test_dir.join("file");
+        fs::write(&file, "x").unwrap();
+        let meta = fs::metadata(&file).unwrap();
+
+        let same = Owner {
+            uid: Some(meta.uid()),
+            gid: None,
+        };
+        let out = change_owners(std::slice::from_ref(&file), same, false, OutputFormat::Text).unwrap();
+        assert_eq!(out, "Changed owner of 0 files successfully (1 unchanged)");
+
+        let other = Owner {
+            uid: Some(54321),
+            gid: Some(54321),
+        };
+        let out = change_owners(std::slice::from_ref(&file), other, true, OutputFormat::Json).unwrap();
+        assert!(out.contains("\"after\": \"54321:54321\""), "{}", out);
+        assert!(out.contains("\"dry_run\": true"), "{}", out);
+        assert_eq!(fs::metadata(&file).unwrap().uid(), meta.uid());
+    }
+}
@@ -0,0 +1,842 @@
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDateTime};
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::cmd::delete_file;
+use crate::journal::{record, stash, Change};
+use crate::output::{to_json, OutputFormat};
+
+/// Case conversion applied to new names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CaseStyle {
+    Lower,
+    Upper,
+    /// Capitalize each word of the name, leaving the extension alone.
+    Title,
+}
+
+/// Where `{date}` in a template takes its date from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DateSource {
+    Mtime,
+    /// The EXIF capture date, or the mtime for files without one.
+    #[default]
+    Exif,
+}
+
+/// Options for `rename_files`.
+#[derive(Debug)]
+pub struct RenameOptions {
+    /// Regular expression matched against each file name; the template
+    /// replaces every match. Without one the template is the whole new name.
+    pub pattern: Option<String>,
+    /// The new name, or the replacement for each match of `pattern`. Besides
+    /// `$1`/`${name}` capture groups it expands `{n}`, `{n:WIDTH}`, `{date}`,
+    /// `{date:FORMAT}`, `{name}`, `{stem}` and `{ext}`.
+    pub template: Option<String>,
+    pub case: Option<CaseStyle>,
+    /// The number `{n}` starts counting from.
+    pub start: usize,
+    pub date_source: DateSource,
+    /// Show the plan without renaming anything.
+    pub dry_run: bool,
+    /// Rename without asking for confirmation.
+    pub yes: bool,
+    /// Journal to record the renames in, so they can be undone.
+    pub journal: Option<PathBuf>,
+    pub format: OutputFormat,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        RenameOptions {
+            pattern: None,
+            template: None,
+            case: None,
+            start: 1,
+            date_source: DateSource::default(),
+            dry_run: false,
+            yes: false,
+            journal: None,
+            format: OutputFormat::default(),
+        }
+    }
+}
+
+/// One planned move.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    renames: &'a [Rename],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    deletes: &'a [PathBuf],
+    unchanged: usize,
+    applied: bool,
+}
+
+/// Rename `files` according to `opts`. The plan is always shown; it is
+/// applied after confirmation, or straight away with `opts.yes`.
+pub fn rename_files(files: &[PathBuf], opts: &RenameOptions) -> Result<String> {
+    let plan = plan_renames(files, opts)?;
+    execute(&plan, &[], files.len(), opts, "rename")
+}
+
+/// Let the user rename and delete `files` by editing a list of them in
+/// `$VISUAL` or `$EDITOR`, like vidir: each line holds a number and a path,
+/// changing a path renames (or moves) that file and removing a line deletes
+/// it. The result goes through the same checks and confirmation as
+/// `rename_files`.
+pub fn edit_renames(files: &[PathBuf], opts: &RenameOptions) -> Result<String> {
+    let width = files.len().to_string().len();
+    let mut buffer = String::new();
+    for (i, f) in files.iter().enumerate() {
+        match f.to_str() {
+            Some(name) if !name.contains('\n') => buffer.push_str(&format!("{:0width$}\t{}\n", i + 1, name)),
+            _ => return Err(anyhow!("Cannot edit the name of {}", f.display())),
+        }
+    }
+    let mut tmp = tempfile::Builder::new().prefix("filey-rename-").suffix(".txt").tempfile()?;
+    tmp.write_all(buffer.as_bytes())?;
+    tmp.flush()?;
+    run_editor(tmp.path())?;
+    // Editors often replace the file rather than rewrite it, so read it
+    // again by name.
+    let edited = std::fs::read_to_string(tmp.path())?;
+
+    let (plan, deletes) = parse_edits(files, &edited)?;
+    check_plan(&plan, &deletes)?;
+    execute(&plan, &deletes, files.len(), opts, "rename --edit")
+}
+
+/// Open `path` in the user's editor and wait for it to exit. The editor
+/// setting may carry arguments, e.g. `code --wait`.
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .map_err(|e| anyhow!("Cannot run {}: {}", editor, e))?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}, nothing was changed", editor, status));
+    }
+    Ok(())
+}
+
+/// Turn an edited buffer back into renames and deletions. Blank lines and
+/// lines starting with `#` are ignored. The path is everything after the
+/// tab that follows the number, so names may start with spaces.
+fn parse_edits(files: &[PathBuf], text: &str) -> Result<(Vec<Rename>, Vec<PathBuf>)> {
+    let mut edited: Vec<Option<&str>> = vec![None; files.len()];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let lineno = i + 1;
+        let (id, path) = line
+            .split_once('\t')
+            .ok_or_else(|| anyhow!("Line {}: expected a number and a path", lineno))?;
+        let n = id
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=files.len()).contains(n))
+            .ok_or_else(|| anyhow!("Line {}: unknown file number {}", lineno, id))?;
+        if path.is_empty() {
+            return Err(anyhow!("Line {}: empty path", lineno));
+        }
+        if edited[n - 1].replace(path).is_some() {
+            return Err(anyhow!("Line {}: file {} is listed more than once", lineno, id));
+        }
+    }
+
+    let mut plan = Vec::new();
+    let mut deletes = Vec::new();
+    for (from, to) in files.iter().zip(edited) {
+        match to {
+            None => deletes.push(from.clone()),
+            Some(to) if Path::new(to) != from => plan.push(Rename {
+                from: from.clone(),
+                to: PathBuf::from(to),
+            }),
+            Some(_) => {}
+        }
+    }
+    Ok((plan, deletes))
+}
+
+/// Apply a checked plan once confirmed, showing it alongside the question,
+/// and return the plan as applied.
+fn execute(plan: &[Rename], deletes: &[PathBuf], total: usize, opts: &RenameOptions, command: &str) -> Result<String> {
+    let mut report = Report {
+        renames: plan,
+        deletes,
+        unchanged: total - plan.len() - deletes.len(),
+        applied: false,
+    };
+    if (plan.is_empty() && deletes.is_empty()) || opts.dry_run {
+        return render(&report, opts.format);
+    }
+    if !opts.yes {
+        // The question goes to the terminal, so the plan is shown as text.
+        let question = format!("{}\nApply these changes?", render(&report, OutputFormat::Text)?);
+        if !confirm(&question)? {
+            return match opts.format {
+                OutputFormat::Json => render(&report, opts.format),
+                OutputFormat::Text => Ok("Aborted, nothing was changed".to_owned()),
+            };
+        }
+    }
+    apply_and_record(plan, deletes, opts.journal.as_deref(), command)?;
+    report.applied = true;
+    render(&report, opts.format)
+}
+
+/// Ask a yes/no question on the terminal. Without a terminal there is no one
+/// to ask, which is an error rather than an implicit yes.
+pub fn confirm(question: &str) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!("Not applying changes without confirmation: pass --yes, or --dry-run to preview"));
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Work out the new path of every file, leaving out the ones whose name does
+/// not change, and check the result with `check_plan`.
+pub fn plan_renames(files: &[PathBuf], opts: &RenameOptions) -> Result<Vec<Rename>> {
+    if opts.template.is_none() && opts.case.is_none() {
+        return Err(anyhow!("Nothing to do: give a new name with --replace, or --case"));
+    }
+    let pattern = match (&opts.pattern, &opts.template) {
+        (Some(_), None) => return Err(anyhow!("--match needs a --replace template")),
+        (Some(p), Some(_)) => Some(Regex::new(p).map_err(|e| anyhow!("Invalid pattern: {}", e))?),
+        _ => None,
+    };
+    if let Some(template) = &opts.template {
+        // Report template mistakes once rather than once per file.
+        let check = Context {
+            path: Path::new(""),
+            n: opts.start,
+            date_source: opts.date_source,
+        };
+        expand(template, &check, pattern.is_some())?;
+    }
+
+    let mut plan = Vec::new();
+    for (i, path) in files.iter().enumerate() {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Cannot rename {}", path.display()))?
+            .to_string_lossy();
+        let mut new = name.to_string();
+        if let Some(template) = &opts.template {
+            let ctx = Context {
+                path,
+                n: opts.start + i,
+                date_source: opts.date_source,
+            };
+            let replacement = expand(template, &ctx, pattern.is_some())?;
+            new = match &pattern {
+                Some(re) => re.replace_all(&name, replacement.as_str()).into_owned(),
+                None => replacement,
+            };
+        }
+        if let Some(case) = opts.case {
+            new = convert_case(&new, case);
+        }
+        if new.is_empty() || new == "." || new == ".." || new.contains('/') {
+            return Err(anyhow!("Invalid new name for {}: {:?}", path.display(), new));
+        }
+        if new != name {
+            plan.push(Rename {
+                from: path.clone(),
+                to: path.with_file_name(new),
+            });
+        }
+    }
+    check_plan(&plan, &[])?;
+    Ok(plan)
+}
+
+/// Reject plans that would lose a file: two sources sharing a target, or a
+/// target that already exists and is neither being renamed away nor among
+/// the `vacated` paths deleted beforehand, or a target whose directory does
+/// not exist or is itself moving. Chains and cycles among the sources are
+/// fine; `apply_plan` orders them.
+pub fn check_plan(plan: &[Rename], vacated: &[PathBuf]) -> Result<()> {
+    let mut sources = HashSet::new();
+    for r in plan {
+        if !sources.insert(std::path::absolute(&r.from)?) {
+            return Err(anyhow!("{} is listed more than once", r.from.display()));
+        }
+    }
+    let vacated = vacated.iter().map(std::path::absolute).collect::<io::Result<HashSet<_>>>()?;
+    let mut targets: HashMap<PathBuf, &Path> = HashMap::new();
+    for r in plan {
+        let to = std::path::absolute(&r.to)?;
+        if let Some(other) = targets.insert(to.clone(), &r.from) {
+            return Err(anyhow!(
+                "Collision: {} and {} would both be renamed to {}",
+                other.display(),
+                r.from.display(),
+                r.to.display()
+            ));
+        }
+        if !sources.contains(&to) && !vacated.contains(&to) && r.to.symlink_metadata().is_ok() {
+            return Err(anyhow!("Collision: {} would overwrite {}", r.from.display(), r.to.display()));
+        }
+        let parent = to.parent().unwrap_or(Path::new("/"));
+        if !parent.is_dir() || sources.contains(parent) || vacated.contains(parent) {
+            return Err(anyhow!(
+                "Cannot rename {} to {}: {} is not an existing directory",
+                r.from.display(),
+                r.to.display(),
+                parent.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Delete `deletes`, then apply a checked plan. With a journal the deleted
+/// files are kept in its trash and everything done is recorded, even when a
+/// later step failed.
+pub fn apply_and_record(plan: &[Rename], deletes: &[PathBuf], journal: Option<&Path>, command: &str) -> Result<()> {
+    let mut changes = Vec::new();
+    let mut apply = || -> Result<()> {
+        for path in deletes {
+            match journal {
+                Some(journal) => changes.push(stash(journal, path)?),
+                None => {
+                    delete_file(path)?;
+                }
+            }
+        }
+        apply_plan(plan, &mut changes)
+    };
+    let res = apply();
+    if let Some(journal) = journal {
+        record(journal, command, changes)?;
+    }
+    res
+}
+
+/// Perform the moves of a checked plan, pushing each one onto `changes` as
+/// it happens. A move waits until its target has been vacated; when only
+/// cycles are left (a→b, b→a), one source steps aside to a temporary name.
+pub fn apply_plan(plan: &[Rename], changes: &mut Vec<Change>) -> Result<()> {
+    let mut pending = plan
+        .iter()
+        .map(|r| Ok((std::path::absolute(&r.from)?, std::path::absolute(&r.to)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut temps = 0;
+    while !pending.is_empty() {
+        let ready = {
+            let sources: HashSet<&PathBuf> = pending.iter().map(|(from, _)| from).collect();
+            pending.iter().position(|(_, to)| !sources.contains(to))
+        };
+        let (from, to) = match ready {
+            Some(i) => pending.remove(i),
+            None => {
+                temps += 1;
+                let from = pending[0].0.clone();
+                let name = from.file_name().unwrap_or_default().to_string_lossy();
+                let tmp = from.with_file_name(format!(".{}.filey-{}-{}", name, std::process::id(), temps));
+                pending[0].0 = tmp.clone();
+                (from, tmp)
+            }
+        };
+        rename_noclobber(&from, &to).map_err(|e| anyhow!("Cannot rename {} to {}: {}", from.display(), to.display(), e))?;
+        changes.push(Change::Rename { from, to });
+    }
+    Ok(())
+}
+
+/// Rename without ever replacing an existing `to`.
+fn rename_noclobber(from: &Path, to: &Path) -> io::Result<()> {
+    let cstr = |p: &Path| CString::new(p.as_os_str().as_bytes()).map_err(io::Error::other);
+    let (c_from, c_to) = (cstr(from)?, cstr(to)?);
+    // SAFETY: both pointers are NUL-terminated strings that outlive the call.
+    let rc = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            c_from.as_ptr(),
+            libc::AT_FDCWD,
+            c_to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if rc == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    // Some filesystems do not support the flag; check by hand there.
+    if err.raw_os_error() == Some(libc::EINVAL) {
+        if to.symlink_metadata().is_ok() {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+        }
+        return std::fs::rename(from, to);
+    }
+    Err(err)
+}
+
+#[derive(Debug)]
+struct Context<'a> {
+    /// Empty when only checking the template.
+    path: &'a Path,
+    n: usize,
+    date_source: DateSource,
+}
+
+/// Expand the `{…}` placeholders of a template. `${…}` belongs to the regex
+/// replacement syntax and is left alone. With `escape`, for a template that
+/// goes through `Regex::replace_all`, values are escaped so that a `$` in a
+/// file name stays literal.
+fn expand(template: &str, ctx: &Context, escape: bool) -> Result<String> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .map(|c| open + c)
+            .ok_or_else(|| anyhow!("Unclosed {{ in template {}", template))?;
+        out.push_str(&rest[..open]);
+        let inner = &rest[open + 1..close];
+        if out.ends_with('$') {
+            out.push_str(&rest[open..=close]);
+        } else {
+            let value = placeholder(inner, ctx)?;
+            out.push_str(&if escape { value.replace('$', "$$") } else { value });
+        }
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn placeholder(inner: &str, ctx: &Context) -> Result<String> {
+    let (key, arg) = match inner.split_once(':') {
+        Some((k, a)) => (k, Some(a)),
+        None => (inner, None),
+    };
+    let path = ctx.path;
+    Ok(match (key, arg) {
+        ("n", None) => ctx.n.to_string(),
+        ("n", Some(w)) => {
+            let width: usize = w.parse().map_err(|_| anyhow!("Invalid width in {{{}}}", inner))?;
+            format!("{:0width$}", ctx.n, width = width)
+        }
+        ("date", fmt) => {
+            let fmt = fmt.unwrap_or("%Y-%m-%d");
+            if StrftimeItems::new(fmt).any(|i| matches!(i, Item::Error)) {
+                return Err(anyhow!("Invalid date format in {{{}}}", inner));
+            }
+            if path.as_os_str().is_empty() {
+                return Ok(String::new());
+            }
+            file_date(path, ctx.date_source)?.format(fmt).to_string()
+        }
+        ("name", None) => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        ("stem", None) => path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+        ("ext", None) => path.extension().unwrap_or_default().to_string_lossy().into_owned(),
+        _ => return Err(anyhow!("Unknown placeholder {{{}}}", inner)),
+    })
+}
+
+/// The date `{date}` stands for, in local time.
+fn file_date(path: &Path, source: DateSource) -> Result<NaiveDateTime> {
+    if source == DateSource::Exif {
+        if let Some(date) = exif_date(path) {
+            return Ok(date);
+        }
+    }
+    let mtime = path
+        .metadata()
+        .and_then(|m| m.modified())
+        .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    Ok(DateTime::<Local>::from(mtime).naive_local())
+}
+
+/// The capture date recorded in a photo's EXIF data, if any.
+fn exif_date(path: &Path) -> Option<NaiveDateTime> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime].iter().find_map(|tag| {
+        match &exif.get_field(*tag, exif::In::PRIMARY)?.value {
+            exif::Value::Ascii(v) => {
+                let s = std::str::from_utf8(v.first()?).ok()?;
+                NaiveDateTime::parse_from_str(s, "%Y:%m:%d %H:%M:%S").ok()
+            }
+            _ => None,
+        }
+    })
+}
+
+fn convert_case(name: &str, case: CaseStyle) -> String {
+    match case {
+        CaseStyle::Lower => name.to_lowercase(),
+        CaseStyle::Upper => name.to_uppercase(),
+        CaseStyle::Title => {
+            let (stem, ext) = match name.rfind('.') {
+                Some(i) if i > 0 => name.split_at(i),
+                _ => (name, ""),
+            };
+            let mut out = String::new();
+            let mut start = true;
+            for c in stem.chars() {
+                if start {
+                    out.extend(c.to_uppercase());
+                } else {
+                    out.extend(c.to_lowercase());
+                }
+                start = !c.is_alphanumeric();
+            }
+            out + ext
+        }
+    }
+}
+
+fn render(report: &Report, format: OutputFormat) -> Result<String> {
+    if format == OutputFormat::Json {
+        return to_json(report);
+    }
+    let shown: Vec<(String, String)> = report
+        .renames
+        .iter()
+        .map(|r| (r.from.to_string_lossy().into_owned(), r.to.to_string_lossy().into_owned()))
+        .chain(report.deletes.iter().map(|p| (p.to_string_lossy().into_owned(), "(deleted)".to_owned())))
+        .collect();
+    let width = shown.iter().map(|(from, _)| from.chars().count()).max().unwrap_or(0);
+    let mut lines: Vec<String> = shown
+        .iter()
+        .map(|(from, to)| format!("{:<width$}  ->  {}", from, to, width = width))
+        .collect();
+    lines.push(summary(report, report.applied));
+    Ok(lines.join("\n"))
+}
+
+fn summary(report: &Report, applied: bool) -> String {
+    let (renamed, deleted) = (report.renames.len(), report.deletes.len());
+    match (applied, deleted) {
+        (true, 0) => format!("Renamed {} files successfully", renamed),
+        (true, _) => format!("Renamed {} and deleted {} files successfully", renamed, deleted),
+        (false, 0) => format!("Would rename {} files ({} unchanged)", renamed, report.unchanged),
+        (false, _) => format!(
+            "Would rename {} and delete {} files ({} unchanged)",
+            renamed, deleted, report.unchanged
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::fs;
+
+    /// A JPEG holding nothing but an EXIF DateTime of 2021-03-04 05:06:07.
+    fn jpeg_with_date() -> Vec<u8> {
+        let mut tiff = b"II*\0\x08\0\0\0".to_vec();
+        tiff.extend([1, 0]); // one entry: DateTime, ASCII, 20 bytes at offset 26
+        tiff.extend([0x32, 0x01, 2, 0, 20, 0, 0, 0, 26, 0, 0, 0]);
+        tiff.extend([0, 0, 0, 0]);
+        tiff.extend(b"2021:03:04 05:06:07\0");
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff);
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+        jpeg.extend(((app1.len() + 2) as u16).to_be_bytes());
+        jpeg.extend(app1);
+        jpeg.extend([0xff, 0xd9]);
+        jpeg
+    }
+
+    #[test]
+    fn test_plan_renames() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            files: &'a [&'a str],
+            opts: RenameOptions,
+            result: Result<&'a [(&'a str, &'a str)]>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        for f in ["IMG_0001.JPG", "IMG_0002.JPG", "notes.txt", "a", "b", "a$b"] {
+            fs::write(test_dir.join(f), f).unwrap();
+        }
+        fs::write(test_dir.join("photo.jpg"), jpeg_with_date()).unwrap();
+
+        let template = |t: &str| RenameOptions {
+            template: Some(t.to_owned()),
+            ..Default::default()
+        };
+        let substitute = |p: &str, t: &str| RenameOptions {
+            pattern: Some(p.to_owned()),
+            template: Some(t.to_owned()),
+            ..Default::default()
+        };
+
+        let tests = &[
+            // failures
+            TestData {
+                files: &["a"],
+                opts: RenameOptions::default(),
+                result: Err(anyhow!("Nothing to do")),
+            },
+            TestData {
+                files: &["a"],
+                opts: template("{bogus}"),
+                result: Err(anyhow!("Unknown placeholder {{bogus}}")),
+            },
+            TestData {
+                files: &["a"],
+                opts: template("{date:%Q}"),
+                result: Err(anyhow!("Invalid date format")),
+            },
+            TestData {
+                files: &["a"],
+                opts: template("x/y"),
+                result: Err(anyhow!("Invalid new name")),
+            },
+            TestData {
+                files: &["a", "b"],
+                opts: template("same"),
+                result: Err(anyhow!("would both be renamed to")),
+            },
+            TestData {
+                files: &["a"],
+                opts: template("notes.txt"),
+                result: Err(anyhow!("would overwrite")),
+            },
+            // successes
+            TestData {
+                files: &["IMG_0001.JPG", "IMG_0002.JPG"],
+                opts: substitute(r"^IMG_0*(\d+)\.JPG$", "holiday-{n:3}-$1.jpg"),
+                result: Ok(&[("IMG_0001.JPG", "holiday-001-1.jpg"), ("IMG_0002.JPG", "holiday-002-2.jpg")]),
+            },
+            TestData {
+                files: &["IMG_0001.JPG", "notes.txt"],
+                opts: RenameOptions {
+                    case: Some(CaseStyle::Lower),
+                    ..Default::default()
+                },
+                result: Ok(&[("IMG_0001.JPG", "img_0001.jpg")]),
+            },
+            TestData {
+                files: &["notes.txt"],
+                opts: RenameOptions {
+                    template: Some("my {stem}.{ext}".to_owned()),
+                    case: Some(CaseStyle::Title),
+                    ..Default::default()
+                },
+                result: Ok(&[("notes.txt", "My Notes.txt")]),
+            },
+            TestData {
+                files: &["photo.jpg"],
+                opts: template("{date:%Y%m%d_%H%M%S}.{ext}"),
+                result: Ok(&[("photo.jpg", "20210304_050607.jpg")]),
+            },
+            TestData {
+                files: &["a", "b"],
+                opts: substitute("^(a|b)$", "${1}x"),
+                result: Ok(&[("a", "ax"), ("b", "bx")]),
+            },
+            TestData {
+                files: &["a$b"],
+                opts: template("x-{name}"),
+                result: Ok(&[("a$b", "x-a$b")]),
+            },
+            TestData {
+                files: &["a$b"],
+                opts: substitute("^a", "{name}-"),
+                result: Ok(&[("a$b", "a$b-$b")]),
+            },
+            TestData {
+                files: &["a", "b"],
+                opts: RenameOptions {
+                    template: Some("{n:2}".to_owned()),
+                    start: 9,
+                    ..Default::default()
+                },
+                result: Ok(&[("a", "09"), ("b", "10")]),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let files: Vec<PathBuf> = d.files.iter().map(|f| test_dir.join(f)).collect();
+            let actual = plan_renames(&files, &d.opts);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok(expected) => {
+                    let expected: Vec<Rename> = expected
+                        .iter()
+                        .map(|(from, to)| Rename {
+                            from: test_dir.join(from),
+                            to: test_dir.join(to),
+                        })
+                        .collect();
+                    assert_eq!(actual.unwrap(), expected, "{}", msg);
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_edits() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            text: &'a str,
+            renames: &'a [(&'a str, &'a str)],
+            deletes: &'a [&'a str],
+            result: Result<()>,
+        }
+
+        let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"].iter().map(PathBuf::from).collect();
+        let tests = &[
+            // failures
+            TestData {
+                text: "1\ta.txt\nnonsense\n",
+                renames: &[],
+                deletes: &[],
+                result: Err(anyhow!("Line 2: expected a number and a path")),
+            },
+            TestData {
+                text: "4\td.txt\n",
+                renames: &[],
+                deletes: &[],
+                result: Err(anyhow!("Line 1: unknown file number 4")),
+            },
+            TestData {
+                text: "1\tx\n1\ty\n",
+                renames: &[],
+                deletes: &[],
+                result: Err(anyhow!("Line 2: file 1 is listed more than once")),
+            },
+            TestData {
+                text: "1\t\n",
+                renames: &[],
+                deletes: &[],
+                result: Err(anyhow!("Line 1: empty path")),
+            },
+            TestData {
+                text: "1 a.txt\n",
+                renames: &[],
+                deletes: &[],
+                result: Err(anyhow!("Line 1: expected a number and a path")),
+            },
+            // successes
+            TestData {
+                text: "1\ta.txt\n2\tb.txt\n3\tc.txt\n",
+                renames: &[],
+                deletes: &[],
+                result: Ok(()),
+            },
+            TestData {
+                text: "# comment\n\n3\tsub/C.txt\n1\ta.txt\n",
+                renames: &[("c.txt", "sub/C.txt")],
+                deletes: &["b.txt"],
+                result: Ok(()),
+            },
+            TestData {
+                text: "1\t a.txt\n2\tb.txt \n3\tc.txt\n",
+                renames: &[("a.txt", " a.txt"), ("b.txt", "b.txt ")],
+                deletes: &[],
+                result: Ok(()),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let actual = parse_edits(&files, d.text);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok(()) => {
+                    let (plan, deleted) = actual.unwrap();
+                    let renames: Vec<Rename> = d
+                        .renames
+                        .iter()
+                        .map(|(from, to)| Rename {
+                            from: PathBuf::from(from),
+                            to: PathBuf::from(to),
+                        })
+                        .collect();
+                    assert_eq!(plan, renames, "{}", msg);
+                    assert_eq!(deleted, d.deletes.iter().map(PathBuf::from).collect::<Vec<_>>(), "{}", msg);
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_plan() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+        let journal = test_dir.join("journal.json");
+
+        for f in ["a", "b", "c", "d"] {
+            fs::write(test_dir.join(f), f).unwrap();
+        }
+        // a cycle (a -> b -> c -> a) and a chain (d -> e)
+        let files: Vec<PathBuf> = ["a", "b", "c", "d"].iter().map(|f| test_dir.join(f)).collect();
+        let opts = RenameOptions {
+            pattern: Some("^(.)$".to_owned()),
+            template: Some("x$1".to_owned()),
+            yes: true,
+            journal: Some(journal.clone()),
+            ..Default::default()
+        };
+        let mut plan = plan_renames(&files, &opts).unwrap();
+        for (r, to) in plan.iter_mut().zip(["b", "c", "a", "e"]) {
+            r.to = test_dir.join(to);
+        }
+        check_plan(&plan, &[]).unwrap();
+        apply_and_record(&plan, &[], Some(&journal), "rename").unwrap();
+        for (f, contents) in [("b", "a"), ("c", "b"), ("a", "c"), ("e", "d")] {
+            assert_eq!(fs::read_to_string(test_dir.join(f)).unwrap(), contents);
+        }
+        assert!(!test_dir.join("d").exists());
+
+        crate::journal::undo_last(&journal).unwrap();
+        for f in ["a", "b", "c", "d"] {
+            assert_eq!(fs::read_to_string(test_dir.join(f)).unwrap(), f);
+        }
+        assert_eq!(fs::read_dir(&test_dir).unwrap().count(), 5);
+
+        let out = rename_files(&files, &opts).unwrap();
+        assert!(out.ends_with("Renamed 4 files successfully"), "{}", out);
+        assert_eq!(fs::read_to_string(test_dir.join("xa")).unwrap(), "a");
+    }
+}
@@ -0,0 +1,310 @@
+use anyhow::{anyhow, Result};
+use regex::{NoExpand, Regex};
+use serde::Serialize;
+use similar::TextDiff;
+use std::path::{Path, PathBuf};
+
+use crate::cmd::write_atomic;
+use crate::output::{paint, to_json, Color, OutputFormat};
+
+/// Options for `replace_in_files`.
+#[derive(Debug, Default)]
+pub struct ReplaceOptions {
+    /// Treat the pattern as a regular expression; `$1`/`${name}` in the
+    /// replacement expand to capture groups. Otherwise both are literal.
+    pub regex: bool,
+    /// Report what would change without writing anything.
+    pub preview: bool,
+    pub color: bool,
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct FileReport {
+    path: String,
+    matches: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    preview: bool,
+    files: Vec<FileReport>,
+    total_matches: usize,
+    files_changed: usize,
+    files_failed: usize,
+}
+
+/// Replace every occurrence of `pattern` in `files`, rewriting each changed
+/// file atomically, and report the number of matches per file. A file that
+/// cannot be read or written is reported and the rest are still processed.
+/// Returns the rendered report and whether any file failed.
+pub fn replace_in_files(
+    pattern: &str,
+    replacement: &str,
+    files: &[PathBuf],
+    opts: &ReplaceOptions,
+) -> Result<(String, bool)> {
+    let re = if opts.regex {
+        Regex::new(pattern)
+    } else {
+        Regex::new(&regex::escape(pattern))
+    }
+    .map_err(|e| anyhow!("Invalid pattern: {}", e))?;
+
+    let mut report = Report {
+        preview: opts.preview,
+        files: Vec::new(),
+        total_matches: 0,
+        files_changed: 0,
+        files_failed: 0,
+    };
+
+    for path in files {
+        let file = replace_in_file(&re, replacement, path, opts).unwrap_or_else(|e| {
+            report.files_failed += 1;
+            FileReport {
+                path: path.to_str().unwrap_or_default().to_owned(),
+                matches: 0,
+                skipped: None,
+                diff: None,
+                error: Some(e.to_string()),
+            }
+        });
+        report.total_matches += file.matches;
+        if file.matches > 0 {
+            report.files_changed += 1;
+        }
+        report.files.push(file);
+    }
+
+    let failed = report.files_failed > 0;
+    let out = match opts.format {
+        OutputFormat::Json => to_json(&report)?,
+        OutputFormat::Text => render_text(&report, opts.color),
+    };
+    Ok((out, failed))
+}
+
+fn replace_in_file(re: &Regex, replacement: &str, path: &Path, opts: &ReplaceOptions) -> Result<FileReport> {
+    let name = path.to_str().unwrap_or_default().to_owned();
+    let bytes = std::fs::read(path)?;
+    let Ok(text) = String::from_utf8(bytes) else {
+        return Ok(FileReport {
+            path: name,
+            matches: 0,
+            skipped: Some("not valid UTF-8".to_owned()),
+            diff: None,
+            error: None,
+        });
+    };
+
+    let matches = re.find_iter(&text).count();
+    let mut diff = None;
+    if matches > 0 {
+        let replaced = if opts.regex {
+            re.replace_all(&text, replacement)
+        } else {
+            re.replace_all(&text, NoExpand(replacement))
+        };
+        if opts.preview {
+            diff = Some(unified_diff(&text, &replaced, &name));
+        } else {
+            write_atomic(path, replaced.as_bytes(), true)?;
+        }
+    }
+
+    Ok(FileReport {
+        path: name,
+        matches,
+        skipped: None,
+        diff,
+        error: None,
+    })
+}
+
+fn unified_diff(old: &str, new: &str, name: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(2)
+        .header(name, name)
+        .to_string()
+}
+
+/// Colorize one line of a unified diff.
+pub fn paint_diff_line(line: &str, color: bool) -> String {
+    if line.starts_with("---") || line.starts_with("+++") {
+        paint(line, Color::Yellow, color)
+    } else if line.starts_with('-') {
+        paint(line, Color::Red, color)
+    } else if line.starts_with('+') {
+        paint(line, Color::Green, color)
+    } else if line.starts_with("@@") {
+        paint(line, Color::Cyan, color)
+    } else {
+        line.to_owned()
+    }
+}
+
+fn render_text(report: &Report, color: bool) -> String {
+    let mut lines = Vec::new();
+    for file in &report.files {
+        if let Some(diff) = &file.diff {
+            for line in diff.lines() {
+                lines.push(paint_diff_line(line, color));
+            }
+        }
+        match (&file.error, &file.skipped) {
+            (Some(e), _) => lines.push(paint(&format!("{}: failed ({})", file.path, e), Color::Red, color)),
+            (None, Some(reason)) => lines.push(format!("{}: skipped ({})", file.path, reason)),
+            (None, None) => lines.push(format!("{}: {} matches", file.path, file.matches)),
+        }
+    }
+
+    let verb = if report.preview { "Would replace" } else { "Replaced" };
+    let mut summary = format!(
+        "{} {} matches in {} files",
+        verb, report.total_matches, report.files_changed
+    );
+    if report.files_failed > 0 {
+        summary.push_str(&format!(", {} failed", report.files_failed));
+    }
+    lines.push(summary);
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::fixture::ChildPath;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_replace_in_files() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            pattern: &'a str,
+            replacement: &'a str,
+            file: &'a str,
+            opts: ReplaceOptions,
+            contents: &'a str,
+            result: Result<&'a str>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let original = "version = 1.2.3\nname = a.b\n";
+        let tests = &[
+            // failures
+            TestData {
+                pattern: "(",
+                replacement: "",
+                file: "bad_regex.txt",
+                opts: ReplaceOptions {
+                    regex: true,
+                    ..Default::default()
+                },
+                contents: original,
+                result: Err(anyhow!("Invalid pattern")),
+            },
+            // successes
+            TestData {
+                pattern: "a.b",
+                replacement: "$1",
+                file: "literal.txt",
+                opts: ReplaceOptions::default(),
+                contents: "version = 1.2.3\nname = $1\n",
+                result: Ok("1 matches"),
+            },
+            TestData {
+                pattern: r"(\d+)\.(\d+)\.(\d+)",
+                replacement: "$1.$2.${3}0",
+                file: "regex.txt",
+                opts: ReplaceOptions {
+                    regex: true,
+                    ..Default::default()
+                },
+                contents: "version = 1.2.30\nname = a.b\n",
+                result: Ok("Replaced 1 matches in 1 files"),
+            },
+            TestData {
+                pattern: "=",
+                replacement: ":",
+                file: "preview.txt",
+                opts: ReplaceOptions {
+                    preview: true,
+                    ..Default::default()
+                },
+                contents: original,
+                result: Ok("+name : a.b"),
+            },
+            TestData {
+                pattern: "missing",
+                replacement: "x",
+                file: "json.txt",
+                opts: ReplaceOptions {
+                    format: OutputFormat::Json,
+                    ..Default::default()
+                },
+                contents: original,
+                result: Ok("\"total_matches\": 0"),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let path = test_dir.join(d.file);
+            std::fs::write(&path, original).unwrap();
+            let actual = replace_in_files(d.pattern, d.replacement, std::slice::from_ref(&path), &d.opts);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok(expected) => {
+                    let (out, failed) = actual.unwrap();
+                    assert!(out.contains(expected), "{}", msg);
+                    assert!(!failed, "{}", msg);
+                    ChildPath::new(&path).assert(d.contents);
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_replace_skips_binary() {
+        let binding = TempDir::new().unwrap();
+        let path = binding.path().join("bin.dat");
+        defer!(binding.close().unwrap());
+
+        std::fs::write(&path, [0xff, 0xfe, b'a']).unwrap();
+        let (out, _) = replace_in_files("a", "b", std::slice::from_ref(&path), &ReplaceOptions::default()).unwrap();
+        assert!(out.contains("skipped"), "{}", out);
+        assert_eq!(std::fs::read(&path).unwrap(), [0xff, 0xfe, b'a']);
+    }
+
+    #[test]
+    fn test_replace_continues_after_failure() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let files: Vec<PathBuf> = ["a.txt", "missing.txt", "b.txt"].iter().map(|f| test_dir.join(f)).collect();
+        std::fs::write(&files[0], "foo").unwrap();
+        std::fs::write(&files[2], "foo foo").unwrap();
+
+        let (out, failed) = replace_in_files("foo", "bar", &files, &ReplaceOptions::default()).unwrap();
+        assert!(failed);
+        assert!(out.contains("missing.txt: failed (No such file"), "{}", out);
+        assert!(out.ends_with("Replaced 3 matches in 2 files, 1 failed"), "{}", out);
+        ChildPath::new(&files[0]).assert("bar");
+        ChildPath::new(&files[2]).assert("bar bar");
+    }
+}
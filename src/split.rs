@@ -0,0 +1,334 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, Permissions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+use crate::checksum::{sha256_file, HashingWriter};
+use crate::cmd::write_atomic_with;
+
+/// How `split_file` sizes its parts.
+#[derive(Debug, Clone, Copy)]
+pub enum SplitBy {
+    Bytes(u64),
+    Lines(u64),
+    Chunks(u64),
+}
+
+/// Describes the parts of a split file so `cat` can reassemble and verify it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub file: String,
+    pub size: u64,
+    pub sha256: String,
+    pub parts: Vec<Part>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Part {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Split `source` into numbered parts named `<file>.partNNN` in `out_dir`
+/// (default: next to the source), optionally writing `<file>.manifest`.
+/// Parts are written under temporary names and only given their own once
+/// all of them are complete, so a failure leaves nothing behind. Existing
+/// parts are never overwritten.
+pub fn split_file(source: &Path, by: SplitBy, out_dir: Option<&Path>, manifest: bool) -> Result<String> {
+    let size = std::fs::metadata(source)?.len();
+    let file_name = source
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow!("Invalid source file name: {}", source.display()))?;
+    let out_dir = match out_dir {
+        Some(d) => d,
+        None => source.parent().unwrap_or(Path::new("")),
+    };
+
+    let count = match by {
+        SplitBy::Bytes(0) | SplitBy::Lines(0) | SplitBy::Chunks(0) => {
+            return Err(anyhow!("Part size must be greater than zero"))
+        }
+        SplitBy::Bytes(n) => Some(size.div_ceil(n).max(1)),
+        SplitBy::Chunks(k) => Some(k),
+        // The number of parts is only known once the input runs out.
+        SplitBy::Lines(_) => None,
+    };
+    let width = count.unwrap_or(0).to_string().len().max(3);
+    let mut reader = BufReader::new(File::open(source)?);
+    let mut parts = Vec::new();
+    // Finished parts, waiting to be moved to their final names.
+    let mut pending: Vec<(NamedTempFile, PathBuf)> = Vec::new();
+
+    for i in 0.. {
+        let done = match count {
+            Some(c) => i >= c,
+            None => i > 0 && reader.fill_buf()?.is_empty(),
+        };
+        if done {
+            break;
+        }
+
+        let name = format!("{}.part{:0width$}", file_name, i + 1, width = width);
+        let path = out_dir.join(&name);
+        let mut tmp = temp_in(out_dir)?;
+        let mut writer = HashingWriter::new(BufWriter::new(tmp.as_file_mut()));
+        match by {
+            SplitBy::Bytes(n) => {
+                io::copy(&mut (&mut reader).take(n), &mut writer)?;
+            }
+            SplitBy::Chunks(k) => {
+                // Spread the remainder over the first parts so sizes differ by at most one byte.
+                let n = size / k + u64::from(i < size % k);
+                io::copy(&mut (&mut reader).take(n), &mut writer)?;
+            }
+            SplitBy::Lines(n) => {
+                let mut line = Vec::new();
+                for _ in 0..n {
+                    line.clear();
+                    if reader.read_until(b'\n', &mut line)? == 0 {
+                        break;
+                    }
+                    writer.write_all(&line)?;
+                }
+            }
+        }
+        writer.flush()?;
+        let (_, size, sha256) = writer.into_parts();
+        tmp.as_file().sync_all()?;
+        parts.push(Part { name, size, sha256 });
+        pending.push((tmp, path));
+    }
+
+    let mut msg = format!(
+        "Split file successfully: {} into {} parts",
+        source.to_str().unwrap_or_default(),
+        parts.len()
+    );
+
+    if manifest {
+        let manifest = Manifest {
+            file: file_name.to_owned(),
+            size,
+            sha256: sha256_file(source)?,
+            parts,
+        };
+        let path = out_dir.join(format!("{}.manifest", file_name));
+        let mut tmp = temp_in(out_dir)?;
+        tmp.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+        tmp.as_file().sync_all()?;
+        msg.push_str(&format!(", manifest {}", path.to_str().unwrap_or_default()));
+        pending.push((tmp, path));
+    }
+
+    // Temporary files not yet persisted are removed when dropped; parts
+    // already in place are taken back.
+    let mut placed: Vec<PathBuf> = Vec::new();
+    for (tmp, path) in pending {
+        if let Err(e) = tmp.persist_noclobber(&path) {
+            for p in &placed {
+                let _ = std::fs::remove_file(p);
+            }
+            return Err(anyhow!("{}: {}", path.display(), e.error));
+        }
+        placed.push(path);
+    }
+
+    Ok(msg)
+}
+
+/// A temporary file in `dir` that gets 0666 filtered through the umask,
+/// like a regular create.
+fn temp_in(dir: &Path) -> Result<NamedTempFile> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    Ok(tempfile::Builder::new()
+        .prefix(".filey-")
+        .suffix(".tmp")
+        .permissions(Permissions::from_mode(0o666))
+        .tempfile_in(dir)?)
+}
+
+/// Reassemble the file described by `manifest_path`, verifying every part and
+/// the final result. Writes to `dst`, or to the original file name next to
+/// the manifest, and never overwrites an existing file.
+pub fn cat_manifest(manifest_path: &Path, dst: Option<&Path>) -> Result<String> {
+    let manifest: Manifest = serde_json::from_slice(&std::fs::read(manifest_path)?)
+        .map_err(|e| anyhow!("Invalid manifest {}: {}", manifest_path.display(), e))?;
+    // Names come from a file we did not necessarily write; keep them inside
+    // the manifest's directory.
+    let plain = |n: &str| !n.is_empty() && Path::new(n).file_name() == Some(n.as_ref());
+    if let Some(bad) = std::iter::once(&manifest.file)
+        .chain(manifest.parts.iter().map(|p| &p.name))
+        .find(|n| !plain(n))
+    {
+        return Err(anyhow!("Invalid file name in manifest: {}", bad));
+    }
+    let dir = manifest_path.parent().unwrap_or(Path::new(""));
+    let dst: PathBuf = match dst {
+        Some(d) => d.to_path_buf(),
+        None => dir.join(&manifest.file),
+    };
+    if dst.exists() {
+        return Err(anyhow!("Destination file exists"));
+    }
+
+    write_atomic_with(&dst, false, |file| {
+        let mut whole = HashingWriter::new(BufWriter::new(file));
+        for part in &manifest.parts {
+            let mut part_hash = HashingWriter::new(&mut whole);
+            let mut src = File::open(dir.join(&part.name)).map_err(|e| anyhow!("{}: {}", part.name, e))?;
+            io::copy(&mut src, &mut part_hash)?;
+            let (_, size, sha256) = part_hash.into_parts();
+            if size != part.size || sha256 != part.sha256 {
+                return Err(anyhow!("Checksum mismatch for part {}", part.name));
+            }
+        }
+
+        let (mut writer, size, sha256) = whole.into_parts();
+        if size != manifest.size || sha256 != manifest.sha256 {
+            return Err(anyhow!("Checksum mismatch for reassembled file {}", manifest.file));
+        }
+        writer.flush()?;
+        Ok(())
+    })?;
+
+    let msg = format!(
+        "Concatenated files successfully: {} parts verified into {}",
+        manifest.parts.len(),
+        dst.to_str().unwrap_or_default()
+    );
+
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::fixture::ChildPath;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_split_file() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            by: SplitBy,
+            parts: &'a [&'a str],
+            result: Result<()>,
+        }
+
+        let contents = "one\ntwo\nthree\nfour\nfive";
+        let tests = &[
+            // failures
+            TestData {
+                by: SplitBy::Bytes(0),
+                parts: &[],
+                result: Err(anyhow!("greater than zero")),
+            },
+            // successes
+            TestData {
+                by: SplitBy::Bytes(10),
+                parts: &["one\ntwo\nth", "ree\nfour\nf", "ive"],
+                result: Ok(()),
+            },
+            TestData {
+                by: SplitBy::Lines(2),
+                parts: &["one\ntwo\n", "three\nfour\n", "five"],
+                result: Ok(()),
+            },
+            TestData {
+                by: SplitBy::Lines(5),
+                parts: &[contents],
+                result: Ok(()),
+            },
+            TestData {
+                by: SplitBy::Chunks(4),
+                parts: &["one\ntw", "o\nthre", "e\nfour", "\nfive"],
+                result: Ok(()),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let binding = TempDir::new().unwrap();
+            let test_dir = binding.to_path_buf();
+            defer!(binding.close().unwrap());
+            let src = test_dir.join("data.txt");
+            std::fs::write(&src, contents).unwrap();
+
+            let actual = split_file(&src, d.by, None, false);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok(()) => {
+                    assert!(actual.is_ok(), "{}", msg);
+                    for (n, part) in d.parts.iter().enumerate() {
+                        ChildPath::new(test_dir.join(format!("data.txt.part{:03}", n + 1))).assert(*part);
+                    }
+                    let next = test_dir.join(format!("data.txt.part{:03}", d.parts.len() + 1));
+                    assert!(!next.exists(), "{}", msg);
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_leaves_nothing_on_failure() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let src = test_dir.join("data.txt");
+        std::fs::write(&src, "one\ntwo\nthree\n").unwrap();
+        std::fs::write(test_dir.join("data.txt.part002"), "taken").unwrap();
+
+        let err = split_file(&src, SplitBy::Lines(1), None, true).unwrap_err();
+        assert!(err.to_string().contains("data.txt.part002"), "{}", err);
+        let mut names: Vec<_> = std::fs::read_dir(&test_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["data.txt", "data.txt.part002"]);
+        ChildPath::new(test_dir.join("data.txt.part002")).assert("taken");
+    }
+
+    #[test]
+    fn test_cat_manifest() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let parts_dir = test_dir.join("parts");
+        std::fs::create_dir(&parts_dir).unwrap();
+        let src = test_dir.join("big.bin");
+        let contents: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&src, &contents).unwrap();
+
+        split_file(&src, SplitBy::Bytes(4096), Some(&parts_dir), true).unwrap();
+        let manifest = parts_dir.join("big.bin.manifest");
+
+        // refuses to overwrite the original
+        let err = cat_manifest(&manifest, Some(&src)).unwrap_err();
+        assert!(err.to_string().contains("exists"), "{}", err);
+
+        // reassembles next to the manifest by default
+        cat_manifest(&manifest, None).unwrap();
+        assert_eq!(std::fs::read(parts_dir.join("big.bin")).unwrap(), contents);
+
+        // detects corruption without leaving output behind
+        let dst = test_dir.join("copy.bin");
+        std::fs::write(parts_dir.join("big.bin.part002"), b"tampered").unwrap();
+        let err = cat_manifest(&manifest, Some(&dst)).unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch for part big.bin.part002"), "{}", err);
+        assert!(!dst.exists());
+        assert_eq!(std::fs::read_dir(&test_dir).unwrap().count(), 2);
+    }
+}
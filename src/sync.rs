@@ -0,0 +1,413 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    fs::{self, Metadata},
+    os::unix::fs::{symlink, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use walkdir::WalkDir;
+
+use crate::checksum::sha256_file;
+use crate::cmd::copy_preserving;
+use crate::output::{paint, to_json, Color, OutputFormat};
+use crate::touch::touch_file;
+use crate::walk::Filter;
+
+/// Options for `sync_dirs`.
+#[derive(Debug, Default)]
+pub struct SyncOptions {
+    /// Compare files by content hash instead of size and mtime.
+    pub checksum: bool,
+    /// Remove destination entries that do not exist in the source.
+    pub delete: bool,
+    /// Report what would change without touching the destination.
+    pub dry_run: bool,
+    pub filter: Filter,
+    pub color: bool,
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    dry_run: bool,
+    added: Vec<String>,
+    updated: Vec<String>,
+    deleted: Vec<String>,
+    /// FIFOs, sockets and devices, which are never copied.
+    skipped: Vec<String>,
+    unchanged: usize,
+}
+
+/// Make `dst` a copy of the directory `src`, copying only entries that are
+/// new or changed. Files are replaced atomically and keep the source's
+/// permissions and times; symlinks are copied as links. Special files are
+/// skipped and reported. Entries excluded by the filter are neither copied
+/// nor deleted.
+pub fn sync_dirs(src: &Path, dst: &Path, opts: &SyncOptions) -> Result<String> {
+    if !fs::metadata(src).map_err(|e| anyhow!("{}: {}", src.display(), e))?.is_dir() {
+        return Err(anyhow!("Source is not a directory: {}", src.display()));
+    }
+    if dst.exists() && !dst.is_dir() {
+        return Err(anyhow!("Destination is not a directory: {}", dst.display()));
+    }
+    let (real_src, real_dst) = (fs::canonicalize(src)?, canonicalize_missing(dst)?);
+    if real_dst.starts_with(&real_src) {
+        return Err(anyhow!("Destination is inside the source directory"));
+    }
+    if opts.delete && real_src.starts_with(&real_dst) {
+        return Err(anyhow!("Source is inside the destination directory, refusing to --delete"));
+    }
+    if !opts.dry_run {
+        fs::create_dir_all(dst)?;
+    }
+
+    let mut report = Report {
+        dry_run: opts.dry_run,
+        ..Default::default()
+    };
+    let mut seen = BTreeSet::new();
+    // Directory times are restored last, once nothing more is written inside.
+    let mut dir_times = Vec::new();
+
+    for entry in walk(src, &opts.filter) {
+        let entry = entry?;
+        let rel = entry.path().strip_prefix(src)?.to_path_buf();
+        let src_meta = entry.metadata()?;
+        let target = dst.join(&rel);
+        let dst_meta = fs::symlink_metadata(&target).ok();
+        let name = display(&rel, &src_meta);
+        seen.insert(rel);
+
+        if let Some(kind) = special_kind(&src_meta) {
+            report.skipped.push(format!("{} ({})", name, kind));
+            continue;
+        }
+
+        let same_type = dst_meta
+            .as_ref()
+            .is_some_and(|m| m.file_type() == src_meta.file_type());
+        if let Some(m) = dst_meta.as_ref().filter(|_| !same_type) {
+            // Something else is in the way; the source wins.
+            if !opts.dry_run {
+                remove(&target, m)?;
+            }
+        }
+
+        let changed = match &dst_meta {
+            Some(m) if same_type => {
+                if src_meta.is_dir() {
+                    m.permissions().mode() != src_meta.permissions().mode()
+                } else if src_meta.is_symlink() {
+                    fs::read_link(entry.path())? != fs::read_link(&target)?
+                } else {
+                    file_changed(entry.path(), &src_meta, &target, m, opts.checksum)?
+                }
+            }
+            _ => true,
+        };
+        if !changed {
+            report.unchanged += usize::from(!src_meta.is_dir());
+            continue;
+        }
+
+        if !opts.dry_run {
+            if src_meta.is_dir() {
+                if !same_type {
+                    fs::create_dir(&target)?;
+                }
+                fs::set_permissions(&target, src_meta.permissions())?;
+                dir_times.push((target.clone(), src_meta.modified()?));
+            } else if src_meta.is_symlink() {
+                if same_type {
+                    fs::remove_file(&target)?;
+                }
+                symlink(fs::read_link(entry.path())?, &target)?;
+            } else {
+                copy_preserving(entry.path(), &target, true)?;
+            }
+        }
+        if same_type {
+            report.updated.push(name);
+        } else {
+            report.added.push(name);
+        }
+    }
+
+    if opts.delete {
+        delete_extraneous(dst, &seen, opts, &mut report)?;
+    }
+    for (dir, mtime) in dir_times.iter().rev() {
+        touch_file(dir, None, Some(*mtime), true)?;
+    }
+
+    match opts.format {
+        OutputFormat::Json => to_json(&report),
+        OutputFormat::Text => Ok(render_text(src, dst, &report, opts.color)),
+    }
+}
+
+fn walk<'a>(root: &Path, filter: &'a Filter) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> + 'a {
+    WalkDir::new(root)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            let kind = e.file_type();
+            if kind.is_dir() {
+                filter.allows_dir(e.path())
+            } else if kind.is_file() || kind.is_symlink() {
+                filter.allows_file(e.path())
+            } else {
+                // Opening a FIFO to detect its type would block.
+                filter.allows_path(e.path())
+            }
+        })
+}
+
+/// Resolve `path` like `fs::canonicalize`, allowing its trailing components
+/// not to exist yet.
+fn canonicalize_missing(path: &Path) -> Result<PathBuf> {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    loop {
+        match fs::canonicalize(if existing.as_os_str().is_empty() { Path::new(".") } else { existing }) {
+            Ok(real) => return Ok(missing.iter().rev().fold(real, |p, c| p.join(c))),
+            Err(e) => match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    missing.push(name.to_owned());
+                    existing = parent;
+                }
+                _ => return Err(anyhow!("{}: {}", path.display(), e)),
+            },
+        }
+    }
+}
+
+/// The kind of a file that sync cannot copy, if it is one.
+fn special_kind(meta: &Metadata) -> Option<&'static str> {
+    let kind = meta.file_type();
+    if kind.is_fifo() {
+        Some("fifo")
+    } else if kind.is_socket() {
+        Some("socket")
+    } else if kind.is_char_device() {
+        Some("character device")
+    } else if kind.is_block_device() {
+        Some("block device")
+    } else {
+        None
+    }
+}
+
+fn display(rel: &Path, meta: &Metadata) -> String {
+    let mut s = rel.to_str().unwrap_or_default().to_owned();
+    if meta.is_dir() {
+        s.push('/');
+    }
+    s
+}
+
+/// The quick check compares size and mtime; with `checksum` sizes are still
+/// compared first, and only equal sizes are hashed.
+fn file_changed(src: &Path, src_meta: &Metadata, dst: &Path, dst_meta: &Metadata, checksum: bool) -> Result<bool> {
+    if src_meta.len() != dst_meta.len() {
+        return Ok(true);
+    }
+    if checksum {
+        return Ok(sha256_file(src)? != sha256_file(dst)?);
+    }
+    let mtime = |m: &Metadata| m.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    Ok(mtime(src_meta) != mtime(dst_meta))
+}
+
+fn remove(path: &Path, meta: &Metadata) -> Result<()> {
+    if meta.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn delete_extraneous(dst: &Path, seen: &BTreeSet<PathBuf>, opts: &SyncOptions, report: &mut Report) -> Result<()> {
+    if !dst.exists() {
+        return Ok(());
+    }
+    // Collect first: removing a directory mid-walk would leave the walker
+    // trying to read what is already gone.
+    let mut extra: Vec<(PathBuf, fs::Metadata)> = Vec::new();
+    for entry in walk(dst, &opts.filter) {
+        let entry = entry?;
+        let rel = entry.path().strip_prefix(dst)?.to_path_buf();
+        if seen.contains(&rel) || extra.iter().any(|(r, _)| rel.starts_with(r)) {
+            continue;
+        }
+        extra.push((rel, entry.metadata()?));
+    }
+    for (rel, meta) in extra {
+        if !opts.dry_run {
+            remove(&dst.join(&rel), &meta)?;
+        }
+        report.deleted.push(display(&rel, &meta));
+    }
+    Ok(())
+}
+
+fn render_text(src: &Path, dst: &Path, report: &Report, color: bool) -> String {
+    let mut lines = Vec::new();
+    lines.extend(report.added.iter().map(|p| paint(&format!("+ {}", p), Color::Green, color)));
+    lines.extend(report.updated.iter().map(|p| paint(&format!("~ {}", p), Color::Yellow, color)));
+    lines.extend(report.deleted.iter().map(|p| paint(&format!("- {}", p), Color::Red, color)));
+    lines.extend(report.skipped.iter().map(|p| format!("skipped {}", p)));
+
+    let verb = if report.dry_run { "Would sync" } else { "Synced" };
+    lines.push(format!(
+        "{} {} to {}: {} added, {} updated, {} deleted, {} unchanged",
+        verb,
+        src.to_str().unwrap_or_default(),
+        dst.to_str().unwrap_or_default(),
+        report.added.len(),
+        report.updated.len(),
+        report.deleted.len(),
+        report.unchanged
+    ));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::fixture::ChildPath;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn write(root: &Path, files: &[(&str, &str)]) {
+        for (f, contents) in files {
+            let p = root.join(f);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(p, contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sync_dirs() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let (src, dst) = (test_dir.join("src"), test_dir.join("dst"));
+        write(&src, &[("a.txt", "alpha"), ("sub/b.txt", "beta"), ("skip.log", "log")]);
+        fs::set_permissions(src.join("a.txt"), fs::Permissions::from_mode(0o600)).unwrap();
+        symlink("a.txt", src.join("link")).unwrap();
+        let opts = SyncOptions {
+            filter: Filter::new(&[], &["*.log".to_owned()]).unwrap(),
+            ..Default::default()
+        };
+
+        // initial sync copies everything but excluded files
+        let out = sync_dirs(&src, &dst, &opts).unwrap();
+        assert!(out.contains("4 added, 0 updated, 0 deleted, 0 unchanged"), "{}", out);
+        ChildPath::new(dst.join("sub/b.txt")).assert("beta");
+        assert!(!dst.join("skip.log").exists());
+        assert_eq!(fs::read_link(dst.join("link")).unwrap(), Path::new("a.txt"));
+        let (m_src, m_dst) = (fs::metadata(src.join("a.txt")).unwrap(), fs::metadata(dst.join("a.txt")).unwrap());
+        assert_eq!(m_dst.permissions().mode() & 0o777, 0o600);
+        assert_eq!(m_dst.modified().unwrap(), m_src.modified().unwrap());
+
+        // a second run has nothing to do
+        let out = sync_dirs(&src, &dst, &opts).unwrap();
+        assert!(out.contains("0 added, 0 updated, 0 deleted, 3 unchanged"), "{}", out);
+
+        // changes are picked up, extraneous files survive without --delete
+        write(&src, &[("sub/b.txt", "BETA!")]);
+        write(&dst, &[("extra/deep/f", "f"), ("extra/x.txt", "x"), ("keep.log", "kept")]);
+        let out = sync_dirs(&src, &dst, &opts).unwrap();
+        assert!(out.contains("~ sub/b.txt"), "{}", out);
+        assert!(dst.join("extra/x.txt").exists());
+
+        // dry run reports deletions without performing them
+        let dry = SyncOptions {
+            delete: true,
+            dry_run: true,
+            filter: opts.filter.clone(),
+            ..Default::default()
+        };
+        let out = sync_dirs(&src, &dst, &dry).unwrap();
+        assert!(out.contains("- extra/\nWould sync"), "{}", out);
+        assert!(dst.join("extra/x.txt").exists());
+
+        let delete = SyncOptions { dry_run: false, ..dry };
+        let out = sync_dirs(&src, &dst, &delete).unwrap();
+        assert!(out.contains("0 added, 0 updated, 1 deleted"), "{}", out);
+        assert!(!dst.join("extra").exists());
+        // excluded files are left alone
+        assert!(dst.join("keep.log").exists());
+
+        // existing directories take on the source's permissions
+        fs::set_permissions(src.join("sub"), fs::Permissions::from_mode(0o700)).unwrap();
+        let out = sync_dirs(&src, &dst, &opts).unwrap();
+        assert!(out.contains("~ sub/\n"), "{}", out);
+        assert_eq!(fs::metadata(dst.join("sub")).unwrap().permissions().mode() & 0o777, 0o700);
+    }
+
+    #[test]
+    fn test_sync_skips_special_files() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let (src, dst) = (test_dir.join("src"), test_dir.join("dst"));
+        write(&src, &[("a.txt", "alpha")]);
+        let fifo = std::ffi::CString::new(src.join("pipe").to_str().unwrap()).unwrap();
+        // SAFETY: `fifo` is a valid NUL-terminated path.
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+
+        let out = sync_dirs(&src, &dst, &SyncOptions::default()).unwrap();
+        assert!(out.contains("skipped pipe (fifo)"), "{}", out);
+        assert!(out.contains("1 added"), "{}", out);
+        assert!(!dst.join("pipe").exists());
+    }
+
+    #[test]
+    fn test_sync_checksum() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let (src, dst) = (test_dir.join("src"), test_dir.join("dst"));
+        write(&src, &[("f.txt", "aaaa")]);
+        write(&dst, &[("f.txt", "bbbb")]);
+        let mtime = fs::metadata(src.join("f.txt")).unwrap().modified().unwrap();
+        touch_file(&dst.join("f.txt"), None, Some(mtime), true).unwrap();
+
+        // same size and mtime fool the quick check
+        let out = sync_dirs(&src, &dst, &SyncOptions::default()).unwrap();
+        assert!(out.contains("0 updated"), "{}", out);
+
+        let opts = SyncOptions {
+            checksum: true,
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+        let out = sync_dirs(&src, &dst, &opts).unwrap();
+        assert!(out.contains("\"updated\": [\n    \"f.txt\"\n  ]"), "{}", out);
+        ChildPath::new(dst.join("f.txt")).assert("aaaa");
+
+        let err = sync_dirs(&src, &src.join("inner"), &opts).unwrap_err();
+        assert!(err.to_string().contains("inside the source"), "{}", err);
+        let err = sync_dirs(&src, &src.join("../src/./new/inner"), &opts).unwrap_err();
+        assert!(err.to_string().contains("inside the source"), "{}", err);
+        let delete = SyncOptions {
+            delete: true,
+            ..Default::default()
+        };
+        let err = sync_dirs(&src, &test_dir, &delete).unwrap_err();
+        assert!(err.to_string().contains("Source is inside the destination"), "{}", err);
+        let err = sync_dirs(&src.join("f.txt"), &dst, &opts).unwrap_err();
+        assert!(err.to_string().contains("not a directory"), "{}", err);
+    }
+}
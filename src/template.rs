@@ -0,0 +1,290 @@
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::cmd::{create_file_from, CreateOptions, Source};
+
+/// Directories searched for templates, in priority order: the project's
+/// `.filey/templates`, `$FILEY_TEMPLATE_DIR`, then the user's config dir.
+pub fn template_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(".filey/templates")];
+    if let Some(dir) = env::var_os("FILEY_TEMPLATE_DIR") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(config) = env::var_os("XDG_CONFIG_HOME") {
+        dirs.push(PathBuf::from(config).join("filey/templates"));
+    } else if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".config/filey/templates"));
+    }
+    dirs
+}
+
+/// Locate template `name` (or `name.tmpl`) in the first directory containing it.
+pub fn find_template(name: &str, dirs: &[PathBuf]) -> Result<PathBuf> {
+    dirs.iter()
+        .flat_map(|d| [d.join(name), d.join(format!("{}.tmpl", name))])
+        .find(|p| p.is_file())
+        .ok_or_else(|| anyhow!("Template not found: {}", name))
+}
+
+/// Variables every template can use without passing `--var`.
+pub fn builtin_vars(dst: &Path) -> HashMap<String, String> {
+    let now = chrono::Local::now();
+    let user = env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_default();
+    let file = |s: Option<&std::ffi::OsStr>| s.and_then(|s| s.to_str()).unwrap_or_default().to_owned();
+
+    HashMap::from([
+        ("date".to_owned(), now.format("%Y-%m-%d").to_string()),
+        ("datetime".to_owned(), now.to_rfc3339()),
+        ("year".to_owned(), now.format("%Y").to_string()),
+        ("user".to_owned(), user),
+        ("filename".to_owned(), file(dst.file_name())),
+        ("stem".to_owned(), file(dst.file_stem())),
+        ("ext".to_owned(), file(dst.extension())),
+    ])
+}
+
+/// Parse a `KEY=VALUE` command line variable.
+pub fn parse_var(s: &str) -> Result<(String, String)> {
+    let (k, v) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Invalid variable, expected KEY=VALUE: {}", s))?;
+    if k.is_empty() {
+        return Err(anyhow!("Invalid variable, expected KEY=VALUE: {}", s));
+    }
+    Ok((k.to_owned(), v.to_owned()))
+}
+
+#[derive(Debug)]
+enum Node<'a> {
+    Text(&'a str),
+    Var { name: &'a str, default: Option<&'a str> },
+    If { name: &'a str, then: Vec<Node<'a>>, otherwise: Vec<Node<'a>> },
+}
+
+/// Render a template. Supported tags:
+///
+/// - `{{name}}` substitutes a variable, failing if it is undefined
+/// - `{{name|fallback}}` substitutes `fallback` when `name` is undefined
+/// - `{{#if name}}...{{else}}...{{/if}}` keeps a branch depending on whether
+///   `name` is set to something other than empty, `0` or `false`
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut rest = template;
+    let nodes = match parse(&mut rest)? {
+        (nodes, Close::Eof) => nodes,
+        (_, Close::Else) => return Err(anyhow!("Unexpected {{{{else}}}} in template")),
+        (_, Close::EndIf) => return Err(anyhow!("Unexpected {{{{/if}}}} in template")),
+    };
+    let mut out = String::with_capacity(template.len());
+    emit(&nodes, vars, &mut out)?;
+    Ok(out)
+}
+
+/// What stopped `parse`: the end of input or a block tag the caller owns.
+enum Close {
+    Eof,
+    Else,
+    EndIf,
+}
+
+fn parse<'a>(rest: &mut &'a str) -> Result<(Vec<Node<'a>>, Close)> {
+    let mut nodes = Vec::new();
+    loop {
+        let Some(start) = rest.find("{{") else {
+            if !rest.is_empty() {
+                nodes.push(Node::Text(rest));
+            }
+            return Ok((nodes, Close::Eof));
+        };
+        if start > 0 {
+            nodes.push(Node::Text(&rest[..start]));
+        }
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| anyhow!("Unterminated tag in template"))?
+            + start;
+        let tag = rest[start + 2..end].trim();
+        *rest = &rest[end + 2..];
+
+        match tag {
+            "else" => return Ok((nodes, Close::Else)),
+            "/if" => return Ok((nodes, Close::EndIf)),
+            _ => {}
+        }
+
+        if let Some(name) = tag.strip_prefix("#if") {
+            let name = name.trim();
+            let (then, otherwise) = match parse(rest)? {
+                (then, Close::EndIf) => (then, Vec::new()),
+                (then, Close::Else) => match parse(rest)? {
+                    (otherwise, Close::EndIf) => (then, otherwise),
+                    (_, Close::Else) => return Err(anyhow!("Unexpected {{{{else}}}} in template")),
+                    (_, Close::Eof) => return Err(anyhow!("Unclosed {{{{#if {}}}}} in template", name)),
+                },
+                (_, Close::Eof) => return Err(anyhow!("Unclosed {{{{#if {}}}}} in template", name)),
+            };
+            nodes.push(Node::If { name, then, otherwise });
+        } else {
+            let (name, default) = match tag.split_once('|') {
+                Some((n, d)) => (n.trim(), Some(d.trim())),
+                None => (tag, None),
+            };
+            if name.is_empty() {
+                return Err(anyhow!("Empty variable name in template"));
+            }
+            nodes.push(Node::Var { name, default });
+        }
+    }
+}
+
+fn emit(nodes: &[Node], vars: &HashMap<String, String>, out: &mut String) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(t) => out.push_str(t),
+            Node::Var { name, default } => match (vars.get(*name), default) {
+                (Some(v), _) => out.push_str(v),
+                (None, Some(d)) => out.push_str(d),
+                (None, None) => return Err(anyhow!("Undefined template variable: {}", name)),
+            },
+            Node::If { name, then, otherwise } => {
+                let set = vars
+                    .get(*name)
+                    .is_some_and(|v| !v.is_empty() && v != "0" && v != "false");
+                emit(if set { then } else { otherwise }, vars, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Render template `name` into `dst` using the built-in variables overlaid
+/// with `vars`. Conflicts with an existing `dst` follow `opts.conflict`.
+pub fn create_from_template(
+    name: &str,
+    dst: &Path,
+    vars: &[(String, String)],
+    dirs: &[PathBuf],
+    opts: &CreateOptions,
+) -> Result<String> {
+    let path = find_template(name, dirs)?;
+    let template = std::fs::read_to_string(&path)?;
+
+    let mut all_vars = builtin_vars(dst);
+    all_vars.extend(vars.iter().cloned());
+    let rendered = render(&template, &all_vars)?;
+
+    create_file_from(dst, Source::Text(&rendered), opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::ConflictPolicy;
+    use assert_fs::fixture::ChildPath;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_render() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            template: &'a str,
+            result: Result<&'a str>,
+        }
+
+        let vars = HashMap::from([
+            ("name".to_owned(), "foo".to_owned()),
+            ("empty".to_owned(), "".to_owned()),
+            ("off".to_owned(), "false".to_owned()),
+        ]);
+
+        let tests = &[
+            // failures
+            TestData {
+                template: "{{missing}}",
+                result: Err(anyhow!("Undefined template variable")),
+            },
+            TestData {
+                template: "{{#if name}}yes",
+                result: Err(anyhow!("Unclosed")),
+            },
+            TestData {
+                template: "text {{/if}}",
+                result: Err(anyhow!("Unexpected")),
+            },
+            TestData {
+                template: "{{name",
+                result: Err(anyhow!("Unterminated")),
+            },
+            // successes
+            TestData {
+                template: "fn {{ name }}() {}",
+                result: Ok("fn foo() {}"),
+            },
+            TestData {
+                template: "{{missing|bar}}-{{name|bar}}",
+                result: Ok("bar-foo"),
+            },
+            TestData {
+                template: "{{#if name}}a{{else}}b{{/if}}{{#if empty}}c{{else}}d{{/if}}{{#if off}}e{{/if}}",
+                result: Ok("ad"),
+            },
+            TestData {
+                template: "{{#if name}}[{{#if missing}}x{{else}}{{name}}{{/if}}]{{/if}}",
+                result: Ok("[foo]"),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let actual = render(d.template, &vars);
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+            match &d.result {
+                Ok(expected) => assert_eq!(actual.unwrap(), *expected, "{}", msg),
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_create_from_template() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let templates = ChildPath::new(test_dir.join("templates"));
+        templates
+            .child("module.tmpl")
+            .write_str("// {{stem}} by {{author|anon}}\npub fn {{name}}() {}\n")
+            .unwrap();
+        let dirs = vec![test_dir.join("nonexistent"), templates.to_path_buf()];
+        let dst = test_dir.join("out.rs");
+        let vars = vec![("name".to_owned(), "foo".to_owned())];
+
+        create_from_template("module", &dst, &vars, &dirs, &CreateOptions::default()).unwrap();
+        ChildPath::new(&dst).assert("// out by anon\npub fn foo() {}\n");
+
+        // refuses to overwrite by default
+        let err = create_from_template("module", &dst, &vars, &dirs, &CreateOptions::default());
+        assert!(err.unwrap_err().to_string().contains("File exists"));
+
+        let opts = CreateOptions {
+            conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        let vars = vec![
+            ("name".to_owned(), "bar".to_owned()),
+            ("author".to_owned(), "me".to_owned()),
+        ];
+        create_from_template("module", &dst, &vars, &dirs, &opts).unwrap();
+        ChildPath::new(&dst).assert("// out by me\npub fn bar() {}\n");
+
+        let err = create_from_template("nope", &dst, &vars, &dirs, &opts);
+        assert!(err.unwrap_err().to_string().contains("Template not found"));
+    }
+}
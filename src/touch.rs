@@ -0,0 +1,306 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+use std::{
+    ffi::CString,
+    fs::metadata,
+    io,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::cmd::create_file;
+
+/// Update the access and/or modification time of `file_path`, creating it
+/// empty first unless `no_create` is set. A `None` time is left untouched.
+pub fn touch_file(
+    file_path: &Path,
+    atime: Option<SystemTime>,
+    mtime: Option<SystemTime>,
+    no_create: bool,
+) -> Result<String> {
+    if !file_path.exists() {
+        if no_create {
+            return Ok(format!(
+                "Skipped missing file: {}",
+                file_path.to_str().unwrap_or_default()
+            ));
+        }
+        create_file(file_path, None)?;
+    }
+
+    let times = [to_timespec(atime), to_timespec(mtime)];
+    let path = CString::new(file_path.as_os_str().as_bytes())?;
+    // SAFETY: `path` is a valid NUL-terminated string and `times` holds
+    // exactly the two entries utimensat(2) reads.
+    let rc = unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let msg = format!(
+        "Touched file successfully: {}",
+        file_path.to_str().unwrap_or_default()
+    );
+
+    Ok(msg)
+}
+
+fn to_timespec(time: Option<SystemTime>) -> libc::timespec {
+    let Some(time) = time else {
+        return libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_OMIT,
+        };
+    };
+    let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_nanos() as i64),
+        Err(e) => {
+            // Before the epoch: borrow a second so the nanoseconds stay positive.
+            let d = e.duration();
+            match d.subsec_nanos() {
+                0 => (-(d.as_secs() as i64), 0),
+                n => (-(d.as_secs() as i64) - 1, 1_000_000_000 - n as i64),
+            }
+        }
+    };
+    libc::timespec {
+        tv_sec: secs as libc::time_t,
+        tv_nsec: nanos as _,
+    }
+}
+
+/// Work out the (atime, mtime) pair to apply. Times come from `reference`,
+/// `date` or the current time, and are filtered by the `-a`/`-m` selection;
+/// selecting neither means both.
+pub fn resolve_times(
+    date: Option<&str>,
+    reference: Option<&Path>,
+    access: bool,
+    modify: bool,
+) -> Result<(Option<SystemTime>, Option<SystemTime>)> {
+    let (atime, mtime) = match (reference, date) {
+        (Some(r), _) => {
+            let meta = metadata(r)?;
+            (
+                from_parts(meta.atime(), meta.atime_nsec()),
+                from_parts(meta.mtime(), meta.mtime_nsec()),
+            )
+        }
+        (None, Some(d)) => {
+            let t = parse_date(d)?;
+            (t, t)
+        }
+        (None, None) => {
+            let now = SystemTime::now();
+            (now, now)
+        }
+    };
+
+    let both = !access && !modify;
+    Ok((
+        (both || access).then_some(atime),
+        (both || modify).then_some(mtime),
+    ))
+}
+
+fn from_parts(secs: i64, nanos: i64) -> SystemTime {
+    let t = UNIX_EPOCH + std::time::Duration::from_nanos(nanos as u64);
+    if secs >= 0 {
+        t + std::time::Duration::from_secs(secs as u64)
+    } else {
+        t - std::time::Duration::from_secs(secs.unsigned_abs())
+    }
+}
+
+/// Parse an absolute or relative date:
+///
+/// - RFC 3339, e.g. `2024-05-01T12:00:00.5+02:00`
+/// - a local date, e.g. `2024-05-01`
+/// - `@SECONDS` since the epoch
+/// - `now`, `yesterday`, `tomorrow`
+/// - an offset from now, e.g. `-2h`, `+30m`, `3 days ago`
+pub fn parse_date(s: &str) -> Result<SystemTime> {
+    let s = s.trim();
+    let invalid = || anyhow!("Invalid date: {}", s);
+    let now = Local::now();
+
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.into());
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        let midnight = d.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        let t = Local.from_local_datetime(&midnight).earliest().ok_or_else(invalid)?;
+        return Ok(t.into());
+    }
+    if let Some(secs) = s.strip_prefix('@') {
+        let secs: i64 = secs.parse().map_err(|_| invalid())?;
+        let offset = std::time::Duration::from_secs(secs.unsigned_abs());
+        let t = if secs >= 0 { UNIX_EPOCH.checked_add(offset) } else { UNIX_EPOCH.checked_sub(offset) };
+        return t.ok_or_else(invalid);
+    }
+
+    let offset = match s {
+        "now" => Duration::zero(),
+        "yesterday" => -Duration::days(1),
+        "tomorrow" => Duration::days(1),
+        _ => {
+            let (body, sign) = if let Some(b) = s.strip_suffix("ago") {
+                (b.trim(), -1)
+            } else if let Some(b) = s.strip_prefix('-') {
+                (b, -1)
+            } else {
+                (s.strip_prefix('+').unwrap_or(s), 1)
+            };
+            let split = body.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+            let (num, unit) = body.split_at(split);
+            let num: i64 = num.parse().map_err(|_| invalid())?;
+            let unit = parse_unit(unit.trim()).ok_or_else(invalid)?;
+            unit.checked_mul(sign * i32::try_from(num).map_err(|_| invalid())?)
+                .ok_or_else(invalid)?
+        }
+    };
+
+    Ok(now.checked_add_signed(offset).ok_or_else(invalid)?.into())
+}
+
+/// The length of a unit in a relative date, e.g. `h` or `days`.
+pub fn parse_unit(unit: &str) -> Option<Duration> {
+    Some(match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => Duration::seconds(1),
+        "m" | "min" | "mins" | "minute" | "minutes" => Duration::minutes(1),
+        "h" | "hour" | "hours" => Duration::hours(1),
+        "d" | "day" | "days" => Duration::days(1),
+        "w" | "week" | "weeks" => Duration::weeks(1),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::fixture::ChildPath;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    #[test]
+    fn test_touch_file() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            path: &'a Path,
+            atime: Option<SystemTime>,
+            mtime: Option<SystemTime>,
+            no_create: bool,
+            result: Result<()>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        // setup
+        let existing = test_dir.join("existing.txt");
+        std::fs::write(&existing, "keep me").unwrap();
+        let t1 = from_parts(1_000_000_000, 123_456_789);
+        let t2 = from_parts(1_500_000_000, 987_654_321);
+        let before = metadata(&existing).unwrap();
+        let old_atime = from_parts(before.atime(), before.atime_nsec());
+
+        let tests = &[
+            // failures
+            TestData {
+                path: &test_dir.join("missing/dir/file.txt"),
+                atime: None,
+                mtime: None,
+                no_create: false,
+                result: Err(anyhow!("No such file")),
+            },
+            // successes
+            TestData {
+                path: &test_dir.join("new.txt"),
+                atime: Some(t1),
+                mtime: Some(t2),
+                no_create: false,
+                result: Ok(()),
+            },
+            TestData {
+                path: &existing,
+                atime: None,
+                mtime: Some(t2),
+                no_create: false,
+                result: Ok(()),
+            },
+            TestData {
+                path: &test_dir.join("skipped.txt"),
+                atime: Some(t1),
+                mtime: Some(t1),
+                no_create: true,
+                result: Ok(()),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let msg = format!("test[{}]: {:?}", i, d);
+            let actual_result = touch_file(d.path, d.atime, d.mtime, d.no_create);
+            let msg = format!("{}, result: {:?}", msg, actual_result);
+
+            match &d.result {
+                Ok(()) => {
+                    assert!(actual_result.is_ok(), "{}", msg);
+                    if d.no_create {
+                        ChildPath::new(d.path).assert(predicates::path::missing());
+                        continue;
+                    }
+                    let meta = metadata(d.path).unwrap();
+                    let atime = from_parts(meta.atime(), meta.atime_nsec());
+                    let mtime = from_parts(meta.mtime(), meta.mtime_nsec());
+                    assert_eq!(atime, d.atime.unwrap_or(old_atime), "{}", msg);
+                    assert_eq!(mtime, d.mtime.unwrap(), "{}", msg);
+                }
+                Err(e) => {
+                    let actual = actual_result.unwrap_err().to_string();
+                    assert!(actual.contains(&e.to_string()), "{}", msg);
+                }
+            }
+        }
+        ChildPath::new(&existing).assert("keep me");
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let rfc = parse_date("2024-05-01T12:00:00.5Z").unwrap();
+        assert_eq!(rfc, from_parts(1_714_564_800, 500_000_000));
+        assert_eq!(parse_date("@86400").unwrap(), from_parts(86_400, 0));
+        assert_eq!(parse_date("@-1").unwrap(), from_parts(-1, 0));
+
+        let now = SystemTime::now();
+        let two_hours = std::time::Duration::from_secs(2 * 3600);
+        for s in ["-2h", "2 hours ago", "120 minutes ago"] {
+            let t = parse_date(s).unwrap();
+            let delta = SystemTime::now().duration_since(t).unwrap();
+            assert!(delta >= two_hours && delta < two_hours * 2, "{}", s);
+        }
+        assert!(parse_date("+1d").unwrap() > now);
+        assert!(parse_date("yesterday").unwrap() < now);
+        assert!(parse_date("2024-05-01").is_ok());
+        for s in ["2000000000w", "-2000000000w", "9999999999d"] {
+            let err = parse_date(s).unwrap_err();
+            assert!(err.to_string().contains("Invalid date"), "{}: {}", s, err);
+        }
+
+        for s in ["", "soon", "5 fortnights ago", "2024-13-01"] {
+            assert!(parse_date(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_resolve_times() {
+        let (a, m) = resolve_times(Some("@10"), None, true, false).unwrap();
+        assert_eq!(a, Some(from_parts(10, 0)));
+        assert_eq!(m, None);
+        let (a, m) = resolve_times(Some("@10"), None, false, false).unwrap();
+        assert_eq!((a, m), (Some(from_parts(10, 0)), Some(from_parts(10, 0))));
+        assert!(resolve_times(None, Some(Path::new("/nonexistent")), false, false).is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Result};
+use glob::Pattern;
+use std::path::{Path, PathBuf};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::detect::{detect_file, Category};
+use crate::find::Expr;
+
+/// Include/exclude glob rules shared by the commands that walk directories.
+/// A pattern matches either a path's file name or the whole path, so both
+/// `*.rs` and `src/**/*.rs` work. Excluded directories are not descended.
+/// Files can also be restricted to content types detected from their bytes,
+/// and to those matching a `find` expression.
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    types: Vec<Category>,
+    expr: Option<Expr>,
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        let compile = |globs: &[String]| {
+            globs
+                .iter()
+                .map(|g| Pattern::new(g).map_err(|e| anyhow!("Invalid glob {}: {}", g, e)))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Filter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            types: Vec::new(),
+            expr: None,
+        })
+    }
+
+    /// Only allow files whose contents fall into one of `types`.
+    pub fn with_types(mut self, types: &[Category]) -> Self {
+        self.types = types.to_vec();
+        self
+    }
+
+    /// Only allow files that satisfy `expr`, evaluated on their own
+    /// (not followed) metadata.
+    pub fn with_expr(mut self, expr: Expr) -> Self {
+        self.expr = Some(expr);
+        self
+    }
+
+    fn matches_any(patterns: &[Pattern], path: &Path) -> bool {
+        let name = path.file_name().map(Path::new);
+        patterns
+            .iter()
+            .any(|p| p.matches_path(path) || name.is_some_and(|n| p.matches_path(n)))
+    }
+
+    /// Whether a directory should be descended into.
+    pub fn allows_dir(&self, path: &Path) -> bool {
+        !Self::matches_any(&self.exclude, path)
+    }
+
+    /// Whether a path passes the include and exclude rules, without looking
+    /// at its contents, e.g. for a file that no longer exists.
+    pub fn allows_path(&self, path: &Path) -> bool {
+        (self.include.is_empty() || Self::matches_any(&self.include, path)) && !Self::matches_any(&self.exclude, path)
+    }
+
+    /// Whether a file passes the include, exclude, type and expression
+    /// rules. Files that cannot be read never match a type.
+    pub fn allows_file(&self, path: &Path) -> bool {
+        self.allows_path(path)
+            && self
+                .expr
+                .as_ref()
+                .is_none_or(|e| std::fs::symlink_metadata(path).is_ok_and(|m| e.matches(path, &m)))
+            && (self.types.is_empty() || detect_file(path).is_ok_and(|t| self.types.contains(&t.category)))
+    }
+}
+
+/// Walk the tree under `root` without following symlinks, yielding the root
+/// itself and every entry below it, sorted by name within each directory.
+/// Directories the filter excludes are not descended; all other rules are
+/// left to the caller. With `contents_first` a directory comes after
+/// everything inside it.
+pub fn walk_tree<'a>(
+    root: &Path,
+    filter: &'a Filter,
+    contents_first: bool,
+) -> impl Iterator<Item = walkdir::Result<DirEntry>> + 'a {
+    WalkDir::new(root)
+        .sort_by_file_name()
+        .contents_first(contents_first)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_type().is_dir() || filter.allows_dir(e.path()))
+}
+
+/// Collect the regular files named by `paths`, descending into directories
+/// when `recursive` is set. Results are sorted within each directory.
+/// Entries below a root that cannot be read are reported on stderr and
+/// skipped; the second value counts them.
+pub fn walk_files(paths: &[PathBuf], recursive: bool, filter: &Filter) -> Result<(Vec<PathBuf>, usize)> {
+    let mut files = Vec::new();
+    let mut errors = 0;
+    for path in paths {
+        let meta = std::fs::metadata(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        if !meta.is_dir() {
+            if filter.allows_file(path) {
+                files.push(path.clone());
+            }
+            continue;
+        }
+        if !recursive {
+            return Err(anyhow!("{} is a directory, use --recursive", path.display()));
+        }
+        for entry in walk_tree(path, filter, false) {
+            let Some(entry) = skip_unreadable(entry, &mut errors) else {
+                continue;
+            };
+            if entry.file_type().is_file() && filter.allows_file(entry.path()) {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    Ok((files, errors))
+}
+
+/// Like `walk_files`, but directories are collected too, each before its
+/// contents. Directories are subject only to the exclude rules. Symlinks
+/// met inside a tree are skipped rather than followed.
+pub fn walk_entries(paths: &[PathBuf], recursive: bool, filter: &Filter) -> Result<(Vec<PathBuf>, usize)> {
+    let mut entries = Vec::new();
+    let mut errors = 0;
+    for path in paths {
+        let meta = std::fs::metadata(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        if !meta.is_dir() {
+            if filter.allows_file(path) {
+                entries.push(path.clone());
+            }
+            continue;
+        }
+        if !recursive {
+            entries.push(path.clone());
+            continue;
+        }
+        for entry in walk_tree(path, filter, false) {
+            let Some(entry) = skip_unreadable(entry, &mut errors) else {
+                continue;
+            };
+            let kind = entry.file_type();
+            if entry.depth() == 0 || kind.is_dir() || (kind.is_file() && filter.allows_file(entry.path())) {
+                entries.push(entry.into_path());
+            }
+        }
+    }
+    Ok((entries, errors))
+}
+
+/// Report a walk error on stderr and count it, as `find` does.
+fn skip_unreadable(entry: walkdir::Result<DirEntry>, errors: &mut usize) -> Option<DirEntry> {
+    match entry {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            *errors += 1;
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::path::Component;
+
+    #[test]
+    fn test_walk_files() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            paths: &'a [&'a str],
+            recursive: bool,
+            include: &'a [&'a str],
+            exclude: &'a [&'a str],
+            result: Result<&'a [&'a str]>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        // setup
+        for f in ["a.rs", "b.txt", "src/c.rs", "src/d.txt", "target/e.rs"] {
+            let p = test_dir.join(f);
+            std::fs::create_dir_all(p.parent().unwrap()).unwrap();
+            std::fs::write(p, f).unwrap();
+        }
+
+        let tests = &[
+            // failures
+            TestData {
+                paths: &["."],
+                recursive: false,
+                include: &[],
+                exclude: &[],
+                result: Err(anyhow!("use --recursive")),
+            },
+            TestData {
+                paths: &["nonexistent"],
+                recursive: true,
+                include: &[],
+                exclude: &[],
+                result: Err(anyhow!("No such file")),
+            },
+            TestData {
+                paths: &["."],
+                recursive: true,
+                include: &["["],
+                exclude: &[],
+                result: Err(anyhow!("Invalid glob")),
+            },
+            // successes
+            TestData {
+                paths: &["a.rs", "b.txt"],
+                recursive: false,
+                include: &[],
+                exclude: &[],
+                result: Ok(&["a.rs", "b.txt"]),
+            },
+            TestData {
+                paths: &["."],
+                recursive: true,
+                include: &[],
+                exclude: &[],
+                result: Ok(&["a.rs", "b.txt", "src/c.rs", "src/d.txt", "target/e.rs"]),
+            },
+            TestData {
+                paths: &["."],
+                recursive: true,
+                include: &["*.rs"],
+                exclude: &["target"],
+                result: Ok(&["a.rs", "src/c.rs"]),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let paths: Vec<PathBuf> = d.paths.iter().map(|p| test_dir.join(p)).collect();
+            let include: Vec<String> = d.include.iter().map(|s| s.to_string()).collect();
+            let exclude: Vec<String> = d.exclude.iter().map(|s| s.to_string()).collect();
+            let actual = Filter::new(&include, &exclude).and_then(|f| walk_files(&paths, d.recursive, &f));
+            let msg = format!("test[{}]: {:?}, result: {:?}", i, d, actual);
+
+            match &d.result {
+                Ok(expected) => {
+                    let (actual, errors) = actual.unwrap();
+                    assert_eq!(errors, 0, "{}", msg);
+                    let actual: Vec<PathBuf> = actual
+                        .iter()
+                        .map(|p| {
+                            let rel = p.strip_prefix(&test_dir).unwrap();
+                            rel.components().filter(|c| *c != Component::CurDir).collect()
+                        })
+                        .collect();
+                    let expected: Vec<PathBuf> = expected.iter().map(PathBuf::from).collect();
+                    assert_eq!(actual, expected, "{}", msg);
+                }
+                Err(e) => assert!(actual.unwrap_err().to_string().contains(&e.to_string()), "{}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk_types() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        std::fs::write(test_dir.join("logo.dat"), b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+        std::fs::write(test_dir.join("notes.png"), "not an image").unwrap();
+        std::fs::write(test_dir.join("data.gz"), [0x1f, 0x8b, 8, 0]).unwrap();
+
+        let filter = Filter::new(&[], &[]).unwrap().with_types(&[Category::Image, Category::Compressed]);
+        let (files, _) = walk_files(std::slice::from_ref(&test_dir), true, &filter).unwrap();
+        let names: Vec<_> = files.iter().map(|p| p.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["data.gz", "logo.dat"]);
+    }
+
+    #[test]
+    fn test_walk_expr() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        std::fs::write(test_dir.join("empty.txt"), "").unwrap();
+        std::fs::write(test_dir.join("full.txt"), "x").unwrap();
+
+        let expr = crate::find::parse_expr(&["--not", "--empty"]).unwrap();
+        let filter = Filter::new(&[], &[]).unwrap().with_expr(expr);
+        let (files, _) = walk_files(std::slice::from_ref(&test_dir), true, &filter).unwrap();
+        assert_eq!(files, [test_dir.join("full.txt")]);
+    }
+    #[test]
+    fn test_walk_unreadable_dir() {
+        use std::os::unix::fs::PermissionsExt;
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let locked = test_dir.join("locked");
+        std::fs::create_dir(&locked).unwrap();
+        std::fs::write(locked.join("hidden.txt"), "").unwrap();
+        std::fs::write(test_dir.join("seen.txt"), "").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        defer!(std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap());
+        if std::fs::read_dir(&locked).is_ok() {
+            // Running as root, which can read anything.
+            return;
+        }
+
+        let roots = std::slice::from_ref(&test_dir);
+        let (files, errors) = walk_files(roots, true, &Filter::default()).unwrap();
+        assert_eq!((files, errors), (vec![test_dir.join("seen.txt")], 1));
+        let (entries, errors) = walk_entries(roots, true, &Filter::default()).unwrap();
+        assert_eq!((entries, errors), (vec![test_dir.clone(), locked.clone(), test_dir.join("seen.txt")], 1));
+    }
+}
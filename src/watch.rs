@@ -0,0 +1,538 @@
+use anyhow::{anyhow, Result};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+use walkdir::WalkDir;
+
+use crate::output::OutputFormat;
+use crate::sync::{sync_dirs, SyncOptions};
+use crate::walk::Filter;
+
+/// What happened to a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+/// One change seen by a `Watcher`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Event {
+    pub kind: EventKind,
+    pub path: PathBuf,
+    /// The old path of a renamed entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<PathBuf>,
+}
+
+impl Event {
+    fn new(kind: EventKind, path: PathBuf) -> Self {
+        Event { kind, path, from: None }
+    }
+}
+
+const MASK: WatchMask = WatchMask::CREATE
+    .union(WatchMask::MODIFY)
+    .union(WatchMask::DELETE)
+    .union(WatchMask::DELETE_SELF)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO);
+
+/// Watches files and directory trees with inotify. New directories are
+/// watched as they appear; directories the filter excludes are not.
+pub struct Watcher {
+    inotify: Inotify,
+    /// The paths given to `new`.
+    roots: Vec<PathBuf>,
+    /// The path each watch was added for.
+    watched: HashMap<WatchDescriptor, PathBuf>,
+    filter: Filter,
+    debounce: Duration,
+}
+
+impl Watcher {
+    /// Start watching `paths`. A batch of changes is complete once none
+    /// have arrived for `debounce`.
+    pub fn new(paths: &[PathBuf], filter: Filter, debounce: Duration) -> Result<Self> {
+        let mut watcher = Watcher {
+            inotify: Inotify::init()?,
+            roots: paths.to_vec(),
+            watched: HashMap::new(),
+            filter,
+            debounce,
+        };
+        for path in paths {
+            fs::metadata(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+            watcher.add_tree(path)?;
+        }
+        Ok(watcher)
+    }
+
+    /// Watch `root` and every directory below it, returning the entries
+    /// found below it.
+    fn add_tree(&mut self, root: &Path) -> Result<Vec<PathBuf>> {
+        let filter = &self.filter;
+        let mut watches = self.inotify.watches();
+        let mut found = Vec::new();
+        let walker = WalkDir::new(root)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !e.file_type().is_dir() || filter.allows_dir(e.path()));
+        for entry in walker {
+            // Entries can vanish while the tree is walked.
+            let Ok(entry) = entry else { continue };
+            if entry.depth() == 0 || entry.file_type().is_dir() {
+                let wd = watches
+                    .add(entry.path(), MASK)
+                    .map_err(|e| anyhow!("Cannot watch {}: {}", entry.path().display(), e))?;
+                self.watched.insert(wd, entry.path().to_path_buf());
+            }
+            if entry.depth() > 0 && (entry.file_type().is_dir() || filter.allows_file(entry.path())) {
+                found.push(entry.into_path());
+            }
+        }
+        Ok(found)
+    }
+
+    /// Wait for changes and return them once they settle, merged so that
+    /// each path appears once: a file created and then modified is just
+    /// created, and one created and then deleted does not appear at all.
+    /// When the kernel drops events, every watch is set up again and each
+    /// root is reported as modified.
+    pub fn next_batch(&mut self) -> Result<Vec<Event>> {
+        let mut raw: Vec<Event> = Vec::new();
+        // Indexes into `raw` of moves waiting for their other half, and
+        // whether they moved a directory.
+        let mut moves: HashMap<u32, (usize, bool)> = HashMap::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let timeout = if raw.is_empty() { None } else { Some(self.debounce) };
+            if !self.wait(timeout)? {
+                if raw.is_empty() {
+                    continue;
+                }
+                // A directory moved out of the watched trees takes its
+                // watches along; they would report changes under a stale path.
+                for (i, is_dir) in moves.into_values() {
+                    if is_dir {
+                        self.unwatch(&raw[i].path);
+                    }
+                }
+                return Ok(coalesce(raw));
+            }
+            let events: Vec<_> = match self.inotify.read_events(&mut buffer) {
+                Ok(events) => events.map(|e| e.to_owned()).collect(),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    moves.clear();
+                    self.rescan(&mut raw)?;
+                    continue;
+                }
+                if event.mask.contains(EventMask::IGNORED) {
+                    // A watched file replaced by an atomic save: follow the
+                    // new file at the same path.
+                    if let Some(path) = self.watched.remove(&event.wd) {
+                        if self.roots.contains(&path) && path.is_file() {
+                            if let Ok(wd) = self.inotify.watches().add(&path, MASK) {
+                                self.watched.insert(wd, path.clone());
+                                raw.push(Event::new(EventKind::Modified, path));
+                            }
+                        }
+                    }
+                    continue;
+                }
+                let Some(dir) = self.watched.get(&event.wd) else {
+                    continue;
+                };
+                let path = match &event.name {
+                    Some(name) => dir.join(name),
+                    None => dir.clone(),
+                };
+                let is_dir = event.mask.contains(EventMask::ISDIR);
+                let exists = !event.mask.intersects(EventMask::DELETE | EventMask::DELETE_SELF | EventMask::MOVED_FROM);
+                let allowed = match (is_dir, exists) {
+                    (true, _) => self.filter.allows_dir(&path),
+                    (false, true) => self.filter.allows_file(&path),
+                    (false, false) => self.filter.allows_path(&path),
+                };
+                if !allowed {
+                    continue;
+                }
+
+                if event.mask.contains(EventMask::MOVED_FROM) {
+                    moves.insert(event.cookie, (raw.len(), is_dir));
+                    raw.push(Event::new(EventKind::Deleted, path));
+                } else if event.mask.contains(EventMask::MOVED_TO) {
+                    match moves.remove(&event.cookie) {
+                        Some((i, _)) => {
+                            let from = std::mem::replace(&mut raw[i].path, path.clone());
+                            raw[i].kind = EventKind::Renamed;
+                            if is_dir {
+                                self.moved(&from, &path);
+                            }
+                            raw[i].from = Some(from);
+                        }
+                        None => raw.push(Event::new(EventKind::Created, path.clone())),
+                    }
+                    if is_dir && !self.watched.values().any(|p| *p == path) {
+                        self.created_dir(&path, &mut raw)?;
+                    }
+                } else if event.mask.contains(EventMask::CREATE) {
+                    raw.push(Event::new(EventKind::Created, path.clone()));
+                    if is_dir {
+                        self.created_dir(&path, &mut raw)?;
+                    }
+                } else if event.mask.contains(EventMask::MODIFY) {
+                    raw.push(Event::new(EventKind::Modified, path));
+                } else {
+                    raw.push(Event::new(EventKind::Deleted, path));
+                }
+            }
+        }
+    }
+
+    /// Watch a directory that just appeared. Anything created inside it
+    /// before the watch was in place is reported as created.
+    fn created_dir(&mut self, dir: &Path, raw: &mut Vec<Event>) -> Result<()> {
+        for path in self.add_tree(dir)? {
+            raw.push(Event::new(EventKind::Created, path));
+        }
+        Ok(())
+    }
+
+    /// Stop watching `dir` and every directory below it.
+    fn unwatch(&mut self, dir: &Path) {
+        let gone: Vec<WatchDescriptor> = self
+            .watched
+            .iter()
+            .filter(|(_, path)| path.starts_with(dir))
+            .map(|(wd, _)| wd.clone())
+            .collect();
+        for wd in gone {
+            self.watched.remove(&wd);
+            // The watch may already be gone along with its directory.
+            let _ = self.inotify.watches().remove(wd);
+        }
+    }
+
+    /// Start over after the kernel dropped events: nothing is known about
+    /// what changed, so drop every watch, watch the roots afresh and report
+    /// each root as modified.
+    fn rescan(&mut self, raw: &mut Vec<Event>) -> Result<()> {
+        for wd in std::mem::take(&mut self.watched).into_keys() {
+            let _ = self.inotify.watches().remove(wd);
+        }
+        for root in self.roots.clone() {
+            if fs::symlink_metadata(&root).is_ok() {
+                self.add_tree(&root)?;
+            }
+            raw.push(Event::new(EventKind::Modified, root));
+        }
+        Ok(())
+    }
+
+    /// Keep the paths of watches below a renamed directory up to date.
+    fn moved(&mut self, from: &Path, to: &Path) {
+        for path in self.watched.values_mut() {
+            if let Ok(rest) = path.strip_prefix(from) {
+                *path = to.join(rest);
+            }
+        }
+    }
+
+    /// Wait until events can be read or `timeout` passes. Returns whether
+    /// there are events.
+    fn wait(&self, timeout: Option<Duration>) -> Result<bool> {
+        let mut fds = libc::pollfd {
+            fd: self.inotify.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        // SAFETY: `fds` is a single valid pollfd.
+        let rc = unsafe { libc::poll(&mut fds, 1, timeout) };
+        if rc < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(false);
+            }
+            return Err(err.into());
+        }
+        Ok(rc > 0)
+    }
+}
+
+/// Merge the changes to each path into one event, in the order each path
+/// first changed.
+fn coalesce(raw: Vec<Event>) -> Vec<Event> {
+    let mut merged: Vec<Option<Event>> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    for event in raw {
+        if let Some(from) = &event.from {
+            // A file written under a temporary name and then renamed into
+            // place is simply created.
+            if let Some(i) = index.remove(from) {
+                if merged[i].as_ref().is_some_and(|e| e.kind == EventKind::Created) {
+                    merged[i] = None;
+                    index.insert(event.path.clone(), merged.len());
+                    merged.push(Some(Event::new(EventKind::Created, event.path)));
+                    continue;
+                }
+            }
+        }
+        let Some(&i) = index.get(&event.path) else {
+            index.insert(event.path.clone(), merged.len());
+            merged.push(Some(event));
+            continue;
+        };
+        let Some(prev) = merged[i].take() else {
+            merged[i] = Some(event);
+            continue;
+        };
+        merged[i] = match (prev.kind, event.kind) {
+            (EventKind::Created, EventKind::Deleted) => None,
+            (EventKind::Created, EventKind::Modified) => Some(prev),
+            (EventKind::Renamed, EventKind::Modified) => Some(prev),
+            (EventKind::Renamed, EventKind::Deleted) => Some(Event::new(EventKind::Deleted, prev.from.unwrap_or(prev.path))),
+            (EventKind::Deleted, EventKind::Created) => Some(Event::new(EventKind::Modified, event.path)),
+            _ => Some(event),
+        };
+    }
+    merged.into_iter().flatten().collect()
+}
+
+/// What `watch` does after each batch of changes, besides printing it.
+#[derive(Debug, Default)]
+pub enum WatchAction {
+    #[default]
+    Print,
+    /// Run a shell command with the changed paths as its arguments.
+    Exec(String),
+    /// Mirror the watched directory into `dst`.
+    Sync { dst: PathBuf, opts: SyncOptions },
+}
+
+/// Options for `watch`.
+#[derive(Debug, Default)]
+pub struct WatchOptions {
+    pub debounce: Duration,
+    pub filter: Filter,
+    pub action: WatchAction,
+    /// Stop after the first batch of changes.
+    pub once: bool,
+    pub format: OutputFormat,
+}
+
+/// Watch `paths` and write every batch of changes to `out` as it settles,
+/// one line per change (or one JSON object per line), then run
+/// `opts.action`. A failed action is reported to `err` and watching goes
+/// on. Runs until interrupted unless `opts.once` is set.
+pub fn watch(paths: &[PathBuf], opts: &WatchOptions, out: &mut dyn Write, err: &mut dyn Write) -> Result<String> {
+    let mut watcher = Watcher::new(paths, opts.filter.clone(), opts.debounce)?;
+    // Mirror once up front; changes made meanwhile are already being watched.
+    if let WatchAction::Sync { dst, opts: sync } = &opts.action {
+        let [src] = paths else {
+            return Err(anyhow!("--sync needs exactly one directory to watch"));
+        };
+        writeln!(out, "{}", sync_dirs(src, dst, sync)?)?;
+    }
+    loop {
+        let events = watcher.next_batch()?;
+        if events.is_empty() {
+            continue;
+        }
+        for event in &events {
+            writeln!(out, "{}", render_event(event, opts.format)?)?;
+        }
+
+        let res = match &opts.action {
+            WatchAction::Print => Ok(String::new()),
+            WatchAction::Exec(cmd) => run_command(cmd, &events),
+            WatchAction::Sync { dst, opts: sync } => sync_dirs(&paths[0], dst, sync),
+        };
+        match res {
+            Ok(msg) if !msg.is_empty() => writeln!(out, "{}", msg)?,
+            Ok(_) => {}
+            Err(e) => writeln!(err, "Error: {}", e)?,
+        }
+        out.flush()?;
+        if opts.once {
+            return Ok(String::new());
+        }
+    }
+}
+
+fn render_event(event: &Event, format: OutputFormat) -> Result<String> {
+    if format == OutputFormat::Json {
+        return Ok(serde_json::to_string(event)?);
+    }
+    let kind = match event.kind {
+        EventKind::Created => "created",
+        EventKind::Modified => "modified",
+        EventKind::Deleted => "deleted",
+        EventKind::Renamed => "renamed",
+    };
+    let path = event.path.to_str().unwrap_or_default();
+    Ok(match &event.from {
+        Some(from) => format!("{:<8} {} -> {}", kind, from.to_str().unwrap_or_default(), path),
+        None => format!("{:<8} {}", kind, path),
+    })
+}
+
+/// Run `cmd` with `sh`, passing the changed paths as `$1`, `$2`, ...
+fn run_command(cmd: &str, events: &[Event]) -> Result<String> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .arg("sh")
+        .args(events.iter().map(|e| e.path.as_os_str()))
+        .status()
+        .map_err(|e| anyhow!("Cannot run sh: {}", e))?;
+    if !status.success() {
+        return Err(anyhow!("{} failed: {}", cmd, status));
+    }
+    Ok(String::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind, PathBuf::from(path))
+    }
+
+    fn renamed(from: &str, to: &str) -> Event {
+        Event {
+            kind: EventKind::Renamed,
+            path: PathBuf::from(to),
+            from: Some(PathBuf::from(from)),
+        }
+    }
+
+    #[test]
+    fn test_coalesce() {
+        use EventKind::*;
+        let tests = &[
+            (vec![event(Created, "a"), event(Modified, "a"), event(Modified, "b")], vec![event(Created, "a"), event(Modified, "b")]),
+            (vec![event(Created, "a"), event(Deleted, "a")], vec![]),
+            (vec![event(Deleted, "a"), event(Created, "a")], vec![event(Modified, "a")]),
+            (vec![event(Modified, "a"), event(Deleted, "a")], vec![event(Deleted, "a")]),
+            (vec![event(Created, ".a.tmp"), event(Modified, ".a.tmp"), renamed(".a.tmp", "a")], vec![event(Created, "a")]),
+            (vec![renamed("a", "b"), event(Modified, "b")], vec![renamed("a", "b")]),
+            (vec![renamed("a", "b"), event(Deleted, "b")], vec![event(Deleted, "a")]),
+        ];
+
+        for (i, (raw, expected)) in tests.iter().enumerate() {
+            assert_eq!(coalesce(raw.clone()), *expected, "test[{}]: {:?}", i, raw);
+        }
+    }
+
+    #[test]
+    fn test_watcher() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        fs::create_dir(test_dir.join("target")).unwrap();
+        fs::write(test_dir.join("old.txt"), "old").unwrap();
+        let filter = Filter::new(&[], &["target".to_owned()]).unwrap();
+        let mut watcher = Watcher::new(std::slice::from_ref(&test_dir), filter, Duration::from_millis(50)).unwrap();
+
+        // The kernel queues these until the batch is read.
+        fs::write(test_dir.join("new.txt"), "new").unwrap();
+        fs::write(test_dir.join("target/out.o"), "ignored").unwrap();
+        fs::rename(test_dir.join("old.txt"), test_dir.join("renamed.txt")).unwrap();
+        fs::create_dir_all(test_dir.join("src/nested")).unwrap();
+        fs::write(test_dir.join("src/nested/lib.rs"), "").unwrap();
+
+        let rel = |events: Vec<Event>| -> Vec<String> {
+            events
+                .iter()
+                .map(|e| render_event(e, OutputFormat::Text).unwrap().replace(&format!("{}/", test_dir.display()), ""))
+                .collect()
+        };
+        let batch = rel(watcher.next_batch().unwrap());
+        assert_eq!(batch[..3], ["created  new.txt", "renamed  old.txt -> renamed.txt", "created  src"], "{:?}", batch);
+        assert!(batch.contains(&"created  src/nested/lib.rs".to_owned()), "{:?}", batch);
+        assert!(!batch.iter().any(|e| e.contains("target")), "{:?}", batch);
+
+        // the new nested directory is watched too
+        fs::remove_file(test_dir.join("src/nested/lib.rs")).unwrap();
+        assert_eq!(rel(watcher.next_batch().unwrap()), ["deleted  src/nested/lib.rs"]);
+
+        // a directory moved out of the tree is no longer watched
+        let outside = TempDir::new().unwrap();
+        fs::rename(test_dir.join("src"), outside.join("src")).unwrap();
+        assert_eq!(rel(watcher.next_batch().unwrap()), ["deleted  src"]);
+        fs::write(outside.join("src/nested/stray.rs"), "").unwrap();
+        fs::write(test_dir.join("after.txt"), "").unwrap();
+        assert_eq!(rel(watcher.next_batch().unwrap()), ["created  after.txt"]);
+
+        // starting over after lost events reports the root and keeps watching
+        let mut raw = Vec::new();
+        watcher.rescan(&mut raw).unwrap();
+        assert_eq!(raw, [Event::new(EventKind::Modified, test_dir.clone())]);
+        fs::write(test_dir.join("after.txt"), "more").unwrap();
+        assert_eq!(rel(watcher.next_batch().unwrap()), ["modified after.txt"]);
+    }
+
+    #[test]
+    fn test_watcher_atomic_save() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let file = test_dir.join("config.toml");
+        fs::write(&file, "a = 1").unwrap();
+        let mut watcher = Watcher::new(std::slice::from_ref(&file), Filter::default(), Duration::from_millis(50)).unwrap();
+
+        for contents in ["a = 2", "a = 3"] {
+            fs::write(test_dir.join(".config.toml.tmp"), contents).unwrap();
+            fs::rename(test_dir.join(".config.toml.tmp"), &file).unwrap();
+            assert_eq!(watcher.next_batch().unwrap(), [Event::new(EventKind::Modified, file.clone())]);
+        }
+    }
+    #[test]
+    fn test_watch_once() {
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let file = test_dir.join("new.txt");
+        let writer = {
+            let file = file.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                fs::write(file, "new").unwrap();
+            })
+        };
+        let opts = WatchOptions {
+            debounce: Duration::from_millis(50),
+            action: WatchAction::Exec("exit 3".to_owned()),
+            once: true,
+            ..Default::default()
+        };
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        watch(std::slice::from_ref(&test_dir), &opts, &mut out, &mut err).unwrap();
+        writer.join().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("created  {}\n", file.display()));
+        assert!(String::from_utf8(err).unwrap().starts_with("Error: exit 3 failed"));
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{anyhow, Result};
+use std::{io::ErrorKind, path::Path};
+
+use crate::cmd::write_atomic;
+
+/// Where `write_file` puts the new text relative to the existing contents.
+#[derive(Debug, Clone)]
+pub enum WriteMode<'a> {
+    Append,
+    Prepend,
+    /// Insert before the given 1-based line; one past the last line appends.
+    InsertAt(usize),
+    /// Replace the lines between the first line containing the start marker
+    /// and the next line containing the end marker, keeping the markers.
+    ReplaceBetween(&'a str, &'a str),
+}
+
+/// Add `text` to `file_path` according to `mode`, rewriting the file
+/// atomically. Appending or prepending to a missing file creates it.
+pub fn write_file(file_path: &Path, text: &[u8], mode: &WriteMode) -> Result<String> {
+    let existing = match std::fs::read(file_path) {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == ErrorKind::NotFound && matches!(mode, WriteMode::Append | WriteMode::Prepend) => {
+            Vec::new()
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let contents = splice(&existing, text, mode)?;
+    write_atomic(file_path, &contents, true)?;
+
+    let msg = format!(
+        "Wrote file successfully: {}",
+        file_path.to_str().unwrap_or_default()
+    );
+
+    Ok(msg)
+}
+
+fn splice(existing: &[u8], text: &[u8], mode: &WriteMode) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(existing.len() + text.len() + 1);
+    match mode {
+        WriteMode::Append => {
+            out.extend_from_slice(existing);
+            out.extend_from_slice(text);
+        }
+        WriteMode::Prepend => {
+            out.extend_from_slice(text);
+            out.extend_from_slice(existing);
+        }
+        WriteMode::InsertAt(line) => {
+            let lines: Vec<&[u8]> = existing.split_inclusive(|b| *b == b'\n').collect();
+            if *line == 0 || *line > lines.len() + 1 {
+                return Err(anyhow!(
+                    "Line {} out of range, file has {} lines",
+                    line,
+                    lines.len()
+                ));
+            }
+            for l in &lines[..line - 1] {
+                out.extend_from_slice(l);
+            }
+            if !out.is_empty() && !out.ends_with(b"\n") {
+                out.push(b'\n');
+            }
+            push_line(&mut out, text);
+            for l in &lines[line - 1..] {
+                out.extend_from_slice(l);
+            }
+        }
+        WriteMode::ReplaceBetween(start, end) => {
+            let lines: Vec<&[u8]> = existing.split_inclusive(|b| *b == b'\n').collect();
+            let contains = |l: &[u8], m: &str| l.windows(m.len().max(1)).any(|w| w == m.as_bytes());
+            let first = lines
+                .iter()
+                .position(|l| contains(l, start))
+                .ok_or_else(|| anyhow!("Start marker not found: {}", start))?;
+            let last = lines[first + 1..]
+                .iter()
+                .position(|l| contains(l, end))
+                .map(|p| p + first + 1)
+                .ok_or_else(|| anyhow!("End marker not found after start marker: {}", end))?;
+
+            for l in &lines[..=first] {
+                out.extend_from_slice(l);
+            }
+            if !out.ends_with(b"\n") {
+                out.push(b'\n');
+            }
+            push_line(&mut out, text);
+            for l in &lines[last..] {
+                out.extend_from_slice(l);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Push `text` as whole lines, terminating it with a newline if needed.
+fn push_line(out: &mut Vec<u8>, text: &[u8]) {
+    out.extend_from_slice(text);
+    if !text.is_empty() && !text.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::fixture::ChildPath;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use defer::defer;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_write_file() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            path: &'a Path,
+            text: &'a str,
+            mode: WriteMode<'a>,
+            initial: Option<&'a str>,
+            contents: &'a str,
+            result: Result<()>,
+        }
+
+        let binding = TempDir::new().unwrap();
+        let test_dir = binding.to_path_buf();
+        defer!(binding.close().unwrap());
+
+        let config = "[a]\nx = 1\n# BEGIN\nold = 1\nold = 2\n# END\n";
+
+        let tests = &[
+            // failures
+            TestData {
+                path: &test_dir.join("missing.txt"),
+                text: "new",
+                mode: WriteMode::InsertAt(1),
+                initial: None,
+                contents: "",
+                result: Err(anyhow!("No such file")),
+            },
+            TestData {
+                path: &test_dir.join("range.txt"),
+                text: "new",
+                mode: WriteMode::InsertAt(9),
+                initial: Some(config),
+                contents: "",
+                result: Err(anyhow!("out of range")),
+            },
+            TestData {
+                path: &test_dir.join("nostart.txt"),
+                text: "new",
+                mode: WriteMode::ReplaceBetween("# START", "# END"),
+                initial: Some(config),
+                contents: "",
+                result: Err(anyhow!("Start marker not found")),
+            },
+            TestData {
+                path: &test_dir.join("noend.txt"),
+                text: "new",
+                mode: WriteMode::ReplaceBetween("# BEGIN", "# STOP"),
+                initial: Some(config),
+                contents: "",
+                result: Err(anyhow!("End marker not found")),
+            },
+            // successes
+            TestData {
+                path: &test_dir.join("append.txt"),
+                text: "tail",
+                mode: WriteMode::Append,
+                initial: Some("head\n"),
+                contents: "head\ntail",
+                result: Ok(()),
+            },
+            TestData {
+                path: &test_dir.join("created.txt"),
+                text: "fresh",
+                mode: WriteMode::Append,
+                initial: None,
+                contents: "fresh",
+                result: Ok(()),
+            },
+            TestData {
+                path: &test_dir.join("prepend.txt"),
+                text: "top\n",
+                mode: WriteMode::Prepend,
+                initial: Some("head\n"),
+                contents: "top\nhead\n",
+                result: Ok(()),
+            },
+            TestData {
+                path: &test_dir.join("insert.txt"),
+                text: "y = 2",
+                mode: WriteMode::InsertAt(3),
+                initial: Some(config),
+                contents: "[a]\nx = 1\ny = 2\n# BEGIN\nold = 1\nold = 2\n# END\n",
+                result: Ok(()),
+            },
+            TestData {
+                path: &test_dir.join("insert_end.txt"),
+                text: "last",
+                mode: WriteMode::InsertAt(2),
+                initial: Some("only"),
+                contents: "only\nlast\n",
+                result: Ok(()),
+            },
+            TestData {
+                path: &test_dir.join("replace.txt"),
+                text: "new = 3\n",
+                mode: WriteMode::ReplaceBetween("# BEGIN", "# END"),
+                initial: Some(config),
+                contents: "[a]\nx = 1\n# BEGIN\nnew = 3\n# END\n",
+                result: Ok(()),
+            },
+        ];
+
+        // setup the starting contents of each file
+        for d in tests.iter() {
+            if let Some(c) = d.initial {
+                std::fs::write(d.path, c).unwrap();
+            }
+        }
+        std::fs::set_permissions(test_dir.join("replace.txt"), std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        // Run the tests
+        for (i, d) in tests.iter().enumerate() {
+            let msg = format!("test[{}]: {:?}", i, d);
+            let actual_result = write_file(d.path, d.text.as_bytes(), &d.mode);
+            let msg = format!("{}, result: {:?}", msg, actual_result);
+
+            match &d.result {
+                Ok(()) => {
+                    assert!(actual_result.is_ok(), "{}", msg);
+                    ChildPath::new(d.path).assert(d.contents);
+                }
+                Err(e) => {
+                    let actual = actual_result.unwrap_err().to_string();
+                    assert!(actual.contains(&e.to_string()), "{}", msg);
+                    if let Some(c) = d.initial {
+                        ChildPath::new(d.path).assert(c);
+                    }
+                }
+            }
+        }
+
+        let mode = std::fs::metadata(test_dir.join("replace.txt")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
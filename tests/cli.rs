@@ -98,6 +98,41 @@ fn cli_subcommands() {
             stdout: None,
             stderr: Some("unrecognized subcommand"),
         },
+        TestData {
+            cmd: "create",
+            flag_args: Some("--sparse"),
+            file_args: "sparse.txt",
+            stdout: None,
+            stderr: Some("required arguments"),
+        },
+        TestData {
+            cmd: "create",
+            flag_args: Some("--mode 0999"),
+            file_args: "mode.txt",
+            stdout: None,
+            stderr: Some("Invalid octal mode"),
+        },
+        TestData {
+            cmd: "touch",
+            flag_args: None,
+            file_args: "",
+            stdout: None,
+            stderr: Some("required arguments"),
+        },
+        TestData {
+            cmd: "touch",
+            flag_args: Some("-d soon"),
+            file_args: "touched.txt",
+            stdout: None,
+            stderr: Some("Invalid date"),
+        },
+        TestData {
+            cmd: "write",
+            flag_args: Some("-t text"),
+            file_args: "out1.txt",
+            stdout: None,
+            stderr: Some("required arguments"),
+        },
         // successes
         TestData {
             cmd: "create",
@@ -113,6 +148,20 @@ fn cli_subcommands() {
             stdout: Some("Created"),
             stderr: None,
         },
+        TestData {
+            cmd: "create",
+            flag_args: Some("-p"),
+            file_args: "nested/dir/out5.txt",
+            stdout: Some("Created"),
+            stderr: None,
+        },
+        TestData {
+            cmd: "create",
+            flag_args: Some("--size 1M"),
+            file_args: "out6.bin",
+            stdout: Some("Created"),
+            stderr: None,
+        },
         TestData {
             cmd: "copy",
             flag_args: None,
@@ -127,6 +176,20 @@ fn cli_subcommands() {
             stdout: Some("Concatenated"),
             stderr: None,
         },
+        TestData {
+            cmd: "touch",
+            flag_args: Some("-d 2024-05-01T12:00:00Z"),
+            file_args: "out4.txt out7.txt",
+            stdout: Some("Touched"),
+            stderr: None,
+        },
+        TestData {
+            cmd: "touch",
+            flag_args: Some("-c"),
+            file_args: "out8.txt",
+            stdout: Some("Skipped"),
+            stderr: None,
+        },
         TestData {
             cmd: "del",
             flag_args: None,
@@ -139,10 +202,9 @@ fn cli_subcommands() {
     for test in tests.iter() {
         let mut args = vec![test.cmd.to_string()];
 
-        if test.flag_args.is_some() {
-            let fa = test.flag_args.unwrap();
+        if let Some(fa) = test.flag_args {
             if fa.contains(" ") {
-                let flags = test.flag_args.unwrap().split_once(" ").unwrap();
+                let flags = fa.split_once(" ").unwrap();
                 let mut flag_vec = vec![flags.0.to_string(), flags.1.to_string()];
                 args.append(&mut flag_vec);
             } else {
@@ -164,7 +226,6 @@ fn cli_subcommands() {
                 .assert()
                 .stdout(predicate::str::contains(s))
                 .success();
-            ()
         };
 
         if let Some(s) = test.stderr {
@@ -174,7 +235,1032 @@ fn cli_subcommands() {
                 .assert()
                 .stderr(predicate::str::contains(s))
                 .failure();
-            ()
         };
     }
 }
+
+#[test]
+fn cli_create_from_stdin() {
+    let binding = TempDir::new().unwrap();
+    let dst = binding.path().join("stdin.txt");
+    defer!(binding.close().unwrap());
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["create", "--from-stdin", "--mode", "0600", dst.to_str().unwrap()])
+        .write_stdin("piped contents")
+        .assert()
+        .stdout(predicate::str::contains("Created"))
+        .success();
+
+    assert_eq!(std::fs::read_to_string(&dst).unwrap(), "piped contents");
+}
+
+#[test]
+fn cli_create_from_template() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    let templates = test_dir.join("templates");
+    std::fs::create_dir(&templates).unwrap();
+    std::fs::write(templates.join("greeting.tmpl"), "hello {{name|world}} from {{stem}}").unwrap();
+    let dst = test_dir.join("hi.txt");
+
+    let run = |extra: &[&str]| {
+        let mut cmd = Command::cargo_bin("filey").unwrap();
+        cmd.env("FILEY_TEMPLATE_DIR", &templates)
+            .args(["create", "--template", "greeting", dst.to_str().unwrap()])
+            .args(extra);
+        cmd.assert()
+    };
+
+    run(&["--var", "name=filey"])
+        .stdout(predicate::str::contains("Created"))
+        .success();
+    assert_eq!(std::fs::read_to_string(&dst).unwrap(), "hello filey from hi");
+
+    run(&[]).stderr(predicate::str::contains("File exists")).failure();
+    run(&["--on-conflict", "skip"])
+        .stdout(predicate::str::contains("Skipped"))
+        .success();
+    run(&["--on-conflict", "overwrite"])
+        .stdout(predicate::str::contains("Created"))
+        .success();
+    assert_eq!(std::fs::read_to_string(&dst).unwrap(), "hello world from hi");
+}
+
+#[test]
+fn cli_write() {
+    let binding = TempDir::new().unwrap();
+    let dst = binding.path().join("config.ini");
+    defer!(binding.close().unwrap());
+    std::fs::write(&dst, "[main]\n# BEGIN\nold\n# END\n").unwrap();
+
+    let run = |args: &[&str]| {
+        Command::cargo_bin("filey")
+            .unwrap()
+            .arg("write")
+            .args(args)
+            .arg(&dst)
+            .assert()
+    };
+
+    run(&["--replace-between", "# BEGIN", "# END", "-t", "new"])
+        .stdout(predicate::str::contains("Wrote"))
+        .success();
+    run(&["--insert-at-line", "2", "-t", "key = 1"]).success();
+    run(&["--append", "-t", "# trailer\n"]).success();
+    run(&["--insert-at-line", "99", "-t", "x"])
+        .stderr(predicate::str::contains("out of range"))
+        .failure();
+
+    assert_eq!(
+        std::fs::read_to_string(&dst).unwrap(),
+        "[main]\nkey = 1\n# BEGIN\nnew\n# END\n# trailer\n"
+    );
+}
+
+#[test]
+fn cli_replace() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    std::fs::create_dir(test_dir.join("src")).unwrap();
+    std::fs::write(test_dir.join("src/a.rs"), "let foo = foo_bar;").unwrap();
+    std::fs::write(test_dir.join("src/b.txt"), "foo").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["replace", "foo", "baz"])
+        .arg(&test_dir)
+        .assert()
+        .stderr(predicate::str::contains("--recursive"))
+        .failure();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["replace", "-r", "--include", "*.rs", "--format", "json", "foo", "baz"])
+        .arg(&test_dir)
+        .assert()
+        .stdout(predicate::str::contains("\"total_matches\": 2"))
+        .success();
+
+    assert_eq!(std::fs::read_to_string(test_dir.join("src/a.rs")).unwrap(), "let baz = baz_bar;");
+    assert_eq!(std::fs::read_to_string(test_dir.join("src/b.txt")).unwrap(), "foo");
+}
+
+#[test]
+fn cli_split_and_cat_manifest() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    let src = test_dir.join("artifact.bin");
+    std::fs::write(&src, vec![7u8; 3000]).unwrap();
+    let parts = test_dir.join("parts");
+    std::fs::create_dir(&parts).unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["split", "--bytes", "1K", "--manifest", "-o"])
+        .args([&parts, &src])
+        .assert()
+        .stdout(predicate::str::contains("into 3 parts"))
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["cat", "--manifest"])
+        .arg(parts.join("artifact.bin.manifest"))
+        .arg("x.txt")
+        .assert()
+        .stderr(predicate::str::contains("cannot be used with"))
+        .failure();
+
+    let dst = test_dir.join("restored.bin");
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["cat", "--manifest"])
+        .arg(parts.join("artifact.bin.manifest"))
+        .arg("-o")
+        .arg(&dst)
+        .assert()
+        .stdout(predicate::str::contains("3 parts verified"))
+        .success();
+    assert_eq!(std::fs::read(&dst).unwrap(), vec![7u8; 3000]);
+}
+
+#[test]
+fn cli_pack_unpack() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    std::fs::create_dir(test_dir.join("docs")).unwrap();
+    std::fs::write(test_dir.join("docs/a.txt"), "alpha").unwrap();
+    let archive = test_dir.join("docs.tar.gz");
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("pack")
+        .args([&archive, &test_dir.join("docs")])
+        .assert()
+        .stdout(predicate::str::contains("Packed 2 entries"))
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("pack")
+        .args([&test_dir.join("docs.rar"), &test_dir.join("docs")])
+        .assert()
+        .stderr(predicate::str::contains("Unknown archive format"))
+        .failure();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("unpack")
+        .args([&archive, &test_dir])
+        .assert()
+        .stderr(predicate::str::contains("Destination file exists"))
+        .failure();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("unpack")
+        .args([&archive, &test_dir.join("out")])
+        .assert()
+        .stdout(predicate::str::contains("Unpacked 2 entries"))
+        .success();
+    assert_eq!(std::fs::read_to_string(test_dir.join("out/docs/a.txt")).unwrap(), "alpha");
+}
+
+#[test]
+fn cli_compress_and_decompress() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    let log = test_dir.join("app.log");
+    std::fs::write(&log, "hello\n").unwrap();
+    let xz = test_dir.join("app.log.xz");
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["copy", "--compress", "xz:10"])
+        .args([&log, &xz])
+        .assert()
+        .stderr(predicate::str::contains("Invalid xz level"))
+        .failure();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["copy", "--compress", "xz:9"])
+        .args([&log, &xz])
+        .assert()
+        .stdout(predicate::str::contains("Copied"))
+        .success();
+
+    let merged = test_dir.join("merged.log");
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["cat", "--decompress"])
+        .args([&xz, &log, &merged])
+        .assert()
+        .stdout(predicate::str::contains("Concatenated"))
+        .success();
+    assert_eq!(std::fs::read_to_string(&merged).unwrap(), "hello\nhello\n");
+}
+
+#[test]
+fn cli_encrypt_decrypt() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    let src = test_dir.join("report.txt");
+    std::fs::write(&src, "quarterly numbers").unwrap();
+    let pass = test_dir.join("pass.txt");
+    std::fs::write(&pass, "correct horse\n").unwrap();
+    let encrypted = test_dir.join("report.txt.age");
+    let decrypted = test_dir.join("report.out");
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("decrypt")
+        .args([&encrypted, &decrypted])
+        .assert()
+        .stderr(predicate::str::contains("No identity given"))
+        .failure();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["encrypt", "--passphrase", "--passphrase-file"])
+        .args([&pass, &src, &encrypted])
+        .assert()
+        .stdout(predicate::str::contains("Encrypted"))
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["decrypt", "--passphrase"])
+        .env("FILEY_PASSPHRASE", "correct horse")
+        .args([&encrypted, &decrypted])
+        .assert()
+        .stdout(predicate::str::contains("Decrypted"))
+        .success();
+    assert_eq!(std::fs::read_to_string(&decrypted).unwrap(), "quarterly numbers");
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["copy", "--encrypt-to", "age1invalid"])
+        .args([&src, &test_dir.join("copy.age")])
+        .assert()
+        .stderr(predicate::str::contains("Invalid recipient"))
+        .failure();
+}
+
+#[test]
+fn cli_diff() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    let a = test_dir.join("a.txt");
+    let b = test_dir.join("b.txt");
+    std::fs::write(&a, "alpha\nbeta\n").unwrap();
+    std::fs::write(&b, "alpha\ngamma\n").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("diff")
+        .args([&a, &a])
+        .assert()
+        .stdout("")
+        .code(0);
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("diff")
+        .args([&a, &b])
+        .assert()
+        .stdout(predicate::str::contains("-beta\n+gamma"))
+        .code(1);
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["diff", "--format", "json"])
+        .args([&test_dir, &test_dir])
+        .assert()
+        .stdout(predicate::str::contains("\"kind\": \"directory\""))
+        .code(0);
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("diff")
+        .args([&a, &test_dir.join("missing.txt")])
+        .assert()
+        .stderr(predicate::str::contains("No such file"))
+        .code(2);
+}
+
+#[test]
+fn cli_sync() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    let src = test_dir.join("src");
+    let dst = test_dir.join("dst");
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    std::fs::write(src.join("docs/readme.txt"), "hello").unwrap();
+    std::fs::create_dir_all(&dst).unwrap();
+    std::fs::write(dst.join("stale.txt"), "old").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["sync", "--delete"])
+        .args([&src, &dst])
+        .assert()
+        .stdout(predicate::str::contains("2 added, 0 updated, 1 deleted, 0 unchanged"))
+        .success();
+    assert_eq!(std::fs::read_to_string(dst.join("docs/readme.txt")).unwrap(), "hello");
+    assert!(!dst.join("stale.txt").exists());
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("sync")
+        .args([&src.join("docs/readme.txt"), &dst])
+        .assert()
+        .stderr(predicate::str::contains("Source is not a directory"))
+        .failure();
+}
+
+#[test]
+fn cli_dedupe() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    std::fs::create_dir_all(test_dir.join("backup")).unwrap();
+    std::fs::write(test_dir.join("report.txt"), "numbers").unwrap();
+    std::fs::write(test_dir.join("backup/report.txt"), "numbers").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("dedupe")
+        .arg(&test_dir)
+        .assert()
+        .stderr(predicate::str::contains("use --recursive"))
+        .failure();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["dedupe", "-r", "--action", "delete", "--keep", "shortest-path"])
+        .arg(&test_dir)
+        .assert()
+        .stdout(predicate::str::contains("Deduplicated 1 files successfully, reclaimed 7 bytes"))
+        .success();
+    assert!(test_dir.join("report.txt").exists());
+    assert!(!test_dir.join("backup/report.txt").exists());
+}
+
+#[test]
+fn cli_info() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    let file = test_dir.join("notes.txt");
+    std::fs::write(&file, "some notes\n").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("info")
+        .arg(&file)
+        .assert()
+        .stdout(predicate::str::contains("Size:        11 bytes").and(predicate::str::contains("text/plain")))
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["info", "--format", "json"])
+        .arg(&test_dir)
+        .assert()
+        .stdout(predicate::str::contains("\"type\": \"directory\""))
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("info")
+        .arg(test_dir.join("missing.txt"))
+        .assert()
+        .stderr(predicate::str::contains("No such file"))
+        .failure();
+}
+
+#[test]
+fn cli_type() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    std::fs::write(test_dir.join("image.jpg"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+    std::fs::write(test_dir.join("readme.txt"), "hello").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["type", "-r"])
+        .arg(&test_dir)
+        .assert()
+        .stdout(
+            predicate::str::contains("image.jpg: PNG image (image/png) warning: extension .jpg does not match PNG image")
+                .and(predicate::str::contains("readme.txt: ASCII text")),
+        )
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["type", "-r", "--type", "image"])
+        .arg(&test_dir)
+        .assert()
+        .stdout(predicate::str::contains("readme.txt").not())
+        .success();
+}
+
+#[test]
+fn cli_ls_tree() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    std::fs::create_dir_all(test_dir.join("src/bin")).unwrap();
+    std::fs::write(test_dir.join("src/main.rs"), "fn main() {}").unwrap();
+    std::fs::write(test_dir.join("src/bin/tool.rs"), "fn main() {}").unwrap();
+    std::fs::write(test_dir.join("notes.txt"), "notes").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("ls")
+        .arg(&test_dir)
+        .assert()
+        .stdout("notes.txt\nsrc\n")
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["ls", "--depth", "3", "--include", "*.rs"])
+        .arg(&test_dir)
+        .assert()
+        .stdout("src\nsrc/bin\nsrc/bin/tool.rs\nsrc/main.rs\n")
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["tree", "-L", "1"])
+        .arg(&test_dir)
+        .assert()
+        .stdout(predicate::str::ends_with("├── notes.txt\n└── src\n\n1 directory, 1 file\n"))
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .arg("ls")
+        .arg(test_dir.join("missing"))
+        .assert()
+        .stderr(predicate::str::contains("No such file"))
+        .failure();
+}
+
+#[test]
+fn cli_du() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    std::fs::create_dir_all(test_dir.join("logs")).unwrap();
+    std::fs::write(test_dir.join("logs/app.log"), "x".repeat(5000)).unwrap();
+    std::fs::write(test_dir.join("notes.txt"), "notes").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["du", "--by-extension", "--apparent", "--format", "csv"])
+        .arg(&test_dir)
+        .assert()
+        .stdout(predicate::str::starts_with("extension,files,apparent,allocated\nlog,1,5000,"))
+        .stdout(predicate::str::contains("\ntxt,1,5,"))
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["du", "-d", "0"])
+        .arg(&test_dir)
+        .assert()
+        .stdout(predicate::str::contains("logs").not())
+        .stdout(predicate::str::ends_with("2  total\n"))
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["du", "--top", "1", "--by-owner"])
+        .arg(&test_dir)
+        .assert()
+        .stderr(predicate::str::contains("cannot be used with"))
+        .failure();
+}
+
+#[test]
+fn cli_find() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    std::fs::create_dir_all(test_dir.join("src")).unwrap();
+    std::fs::write(test_dir.join("src/main.rs"), "fn main() {}").unwrap();
+    std::fs::write(test_dir.join("notes.txt"), "").unwrap();
+    std::fs::write(test_dir.join("build.log"), "log").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .current_dir(&test_dir)
+        .args(["find", "--name", "*.rs", "--or", "--empty", "--type", "f"])
+        .assert()
+        .stdout("./notes.txt\n./src/main.rs\n")
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .current_dir(&test_dir)
+        .args(["find", ".", "--type", "f", "--not", "--name", "*.rs", "--print0"])
+        .assert()
+        .stdout("./build.log\0./notes.txt\0")
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .current_dir(&test_dir)
+        .args(["find", ".", "--name", "*.log", "--delete"])
+        .assert()
+        .stdout("Deleted file successfully: ./build.log\n")
+        .success();
+    assert!(!test_dir.join("build.log").exists());
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .current_dir(&test_dir)
+        .args(["find", "src", "--type", "f", "--exec", "cat", "{}", ";"])
+        .assert()
+        .stdout("fn main() {}")
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .args(["find", ".", "--size"])
+        .assert()
+        .stderr(predicate::str::contains("--size needs a value"))
+        .failure();
+}
+
+#[test]
+fn cli_grep() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    std::fs::create_dir_all(test_dir.join("src")).unwrap();
+    std::fs::write(test_dir.join("src/main.rs"), "fn main() {\n    // TODO: args\n}\n").unwrap();
+    std::fs::write(test_dir.join("notes.txt"), "todo: tests\n").unwrap();
+    std::fs::write(test_dir.join("blob.bin"), b"\0TODO\0").unwrap();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .current_dir(&test_dir)
+        .args(["grep", "-C", "1", "TODO", "src/main.rs"])
+        .assert()
+        .stdout("1-fn main() {\n2:    // TODO: args\n3-}\n")
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .current_dir(&test_dir)
+        .args(["grep", "-ril", "todo", "."])
+        .assert()
+        .stdout("./notes.txt\n./src/main.rs\n")
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .current_dir(&test_dir)
+        .args(["grep", "-rc", "--text", "--include", "*.bin", "TODO", "."])
+        .assert()
+        .stdout("./blob.bin:1\n")
+        .success();
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .current_dir(&test_dir)
+        .args(["grep", "FIXME", "notes.txt"])
+        .assert()
+        .stdout("")
+        .code(1);
+
+    Command::cargo_bin("filey")
+        .unwrap()
+        .current_dir(&test_dir)
+        .args(["grep", "x", "missing.txt"])
+        .assert()
+        .stderr(predicate::str::contains("No such file"))
+        .code(2);
+}
+
+#[test]
+fn cli_rename_undo() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+    let journal = test_dir.join("journal.json");
+
+    std::fs::create_dir(test_dir.join("logs")).unwrap();
+    for f in ["app.LOG", "db.LOG"] {
+        std::fs::write(test_dir.join("logs").join(f), f).unwrap();
+    }
+    let rename = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("filey").unwrap();
+        cmd.current_dir(&test_dir).env("FILEY_JOURNAL", &journal).args(args);
+        cmd
+    };
+
+    rename(&["rename", "-r", "logs", "-m", r"\.LOG$", "-s", "-{n:2}.log", "--dry-run"])
+        .assert()
+        .stdout("logs/app.LOG  ->  logs/app-01.log\nlogs/db.LOG   ->  logs/db-02.log\nWould rename 2 files (0 unchanged)\n")
+        .success();
+
+    rename(&["rename", "-r", "logs", "--case", "lower"])
+        .write_stdin("")
+        .assert()
+        .stderr(predicate::str::contains("pass --yes"))
+        .failure();
+    assert!(test_dir.join("logs/app.LOG").exists());
+
+    rename(&["rename", "-r", "logs", "--case", "lower", "--yes"])
+        .assert()
+        .stdout(predicate::str::ends_with("Renamed 2 files successfully\n"))
+        .success();
+    assert!(test_dir.join("logs/db.log").exists());
+
+    rename(&["undo"])
+        .assert()
+        .stdout(predicate::str::contains("2 changes reverted"))
+        .success();
+    assert!(test_dir.join("logs/db.LOG").exists());
+
+    rename(&["undo"])
+        .assert()
+        .stderr(predicate::str::contains("Nothing to undo"))
+        .failure();
+}
+
+#[test]
+fn cli_rename_edit() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+    let journal = test_dir.join("state/journal.json");
+
+    std::fs::create_dir(test_dir.join("docs")).unwrap();
+    for f in ["a.md", "b.md", "c.md"] {
+        std::fs::write(test_dir.join("docs").join(f), f).unwrap();
+    }
+    // an "editor" that swaps a and b and drops c
+    let editor = "sed -i -e 's/a\\.md$/tmp/' -e 's/b\\.md$/a.md/' -e 's/tmp$/b.md/' -e '/c\\.md$/d'";
+    let rename = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("filey").unwrap();
+        cmd.current_dir(&test_dir)
+            .env("FILEY_JOURNAL", &journal)
+            .env_remove("VISUAL")
+            .env("EDITOR", editor)
+            .args(args);
+        cmd
+    };
+
+    rename(&["rename", "--edit", "-r", "docs", "--dry-run"])
+        .assert()
+        .stdout("docs/a.md  ->  docs/b.md\ndocs/b.md  ->  docs/a.md\ndocs/c.md  ->  (deleted)\nWould rename 2 and delete 1 files (0 unchanged)\n")
+        .success();
+
+    rename(&["rename", "--edit", "-r", "docs", "--yes"])
+        .assert()
+        .stdout(predicate::str::ends_with("Renamed 2 and deleted 1 files successfully\n"))
+        .success();
+    assert_eq!(std::fs::read_to_string(test_dir.join("docs/a.md")).unwrap(), "b.md");
+    assert!(!test_dir.join("docs/c.md").exists());
+
+    rename(&["undo"]).assert().success();
+    for f in ["a.md", "b.md", "c.md"] {
+        assert_eq!(std::fs::read_to_string(test_dir.join("docs").join(f)).unwrap(), f);
+    }
+
+    rename(&["rename", "--edit", "docs/a.md", "--yes"])
+        .env("EDITOR", "false")
+        .assert()
+        .stderr(predicate::str::contains("nothing was changed"))
+        .failure();
+
+    // a move into a missing directory is refused before c.md is deleted
+    rename(&["rename", "--edit", "-r", "docs", "--yes"])
+        .env("EDITOR", "sed -i -e 's|a\\.md$|sub/a.md|' -e '/c\\.md$/d'")
+        .assert()
+        .stderr(predicate::str::contains("is not an existing directory"))
+        .failure();
+    for f in ["a.md", "b.md", "c.md"] {
+        assert!(test_dir.join("docs").join(f).exists());
+    }
+}
+
+#[test]
+fn cli_links() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    std::fs::create_dir_all(test_dir.join("share")).unwrap();
+    std::fs::create_dir_all(test_dir.join("bin")).unwrap();
+    std::fs::write(test_dir.join("share/tool.sh"), "echo hi").unwrap();
+    let filey = || {
+        let mut cmd = Command::cargo_bin("filey").unwrap();
+        cmd.current_dir(&test_dir);
+        cmd
+    };
+
+    filey()
+        .args(["link", "-s", "-r", "share/tool.sh", "bin/tool"])
+        .assert()
+        .stdout("Linked file successfully: bin/tool -> ../share/tool.sh\n")
+        .success();
+    filey()
+        .args(["link", "-r", "share/tool.sh", "bin/other"])
+        .assert()
+        .stderr(predicate::str::contains("--symbolic"))
+        .failure();
+
+    filey()
+        .args(["readlink", "bin/tool"])
+        .assert()
+        .stdout(predicate::str::starts_with("bin/tool -> ../share/tool.sh\ncanonical: /"))
+        .stdout(predicate::str::ends_with("share/tool.sh\n"))
+        .success();
+
+    filey()
+        .args(["copy", "-P", "bin/tool", "bin/copy"])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read_link(test_dir.join("bin/copy")).unwrap(), std::path::Path::new("../share/tool.sh"));
+    filey()
+        .args(["copy", "-P", "-L", "bin/tool", "share/deref"])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read_to_string(test_dir.join("share/deref")).unwrap(), "echo hi");
+
+    std::fs::remove_file(test_dir.join("share/tool.sh")).unwrap();
+    filey()
+        .args(["links", "--broken", "bin"])
+        .assert()
+        .stdout("bin/copy -> ../share/tool.sh\nbin/tool -> ../share/tool.sh\n")
+        .success();
+    filey()
+        .args(["links", "--broken", "bin/tool"])
+        .assert()
+        .stdout("bin/tool -> ../share/tool.sh\n")
+        .success();
+}
+
+#[test]
+fn cli_chmod_chown() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    std::fs::create_dir_all(test_dir.join("site/css")).unwrap();
+    std::fs::write(test_dir.join("site/index.html"), "<p>").unwrap();
+    std::fs::write(test_dir.join("site/css/main.css"), "p {}").unwrap();
+    let mode = |p: &str| std::fs::metadata(test_dir.join(p)).unwrap().permissions().mode() & 0o7777;
+    std::fs::set_permissions(test_dir.join("site/index.html"), std::fs::Permissions::from_mode(0o600)).unwrap();
+    let filey = || {
+        let mut cmd = Command::cargo_bin("filey").unwrap();
+        cmd.current_dir(&test_dir);
+        cmd
+    };
+
+    filey()
+        .args(["chmod", "-R", "--dirs", "750", "--files", "go+r", "site"])
+        .assert()
+        .stdout(predicate::str::contains("site/index.html: 0600 (rw-------) -> 0644 (rw-r--r--)\n"))
+        .stdout(predicate::str::ends_with("Changed mode of 3 files successfully (1 unchanged)\n"))
+        .success();
+    assert_eq!((mode("site"), mode("site/css"), mode("site/index.html")), (0o750, 0o750, 0o644));
+
+    filey()
+        .args(["chmod", "-R", "--files", "640", "--dirs", "750", "site"])
+        .assert()
+        .stdout(predicate::str::ends_with("Changed mode of 2 files successfully (2 unchanged)\n"))
+        .success();
+    assert_eq!((mode("site"), mode("site/css"), mode("site/index.html")), (0o750, 0o750, 0o640));
+    std::fs::set_permissions(test_dir.join("site/index.html"), std::fs::Permissions::from_mode(0o644)).unwrap();
+    // with both --files and --dirs every argument is a path, even one that
+    // looks like a mode
+    std::fs::write(test_dir.join("755"), "").unwrap();
+    filey()
+        .args(["chmod", "--files", "600", "--dirs", "700", "755"])
+        .assert()
+        .success();
+    assert_eq!(mode("755"), 0o600);
+    filey()
+        .args(["chmod", "--files", "644", "--dirs", "755", "600", "755"])
+        .assert()
+        .stderr(predicate::str::contains("600: No such file"))
+        .failure();
+    assert_eq!(mode("755"), 0o600);
+    filey()
+        .args(["chmod", "644"])
+        .assert()
+        .stderr(predicate::str::contains("No paths given"))
+        .failure();
+
+    filey()
+        .args(["chmod", "-n", "-w", "site/index.html"])
+        .assert()
+        .stdout("site/index.html: 0644 (rw-r--r--) -> 0444 (r--r--r--)\nWould change mode of 1 files (0 unchanged)\n")
+        .success();
+    assert_eq!(mode("site/index.html"), 0o644);
+
+    filey()
+        .args(["chmod", "u+q", "site"])
+        .assert()
+        .stderr(predicate::str::contains("Invalid mode: u+q"))
+        .failure();
+
+    let uid = std::fs::metadata(test_dir.join("site")).unwrap().uid();
+    filey()
+        .args(["chown", "-R", "--format", "json", &uid.to_string(), "site"])
+        .assert()
+        .stdout(predicate::str::contains("\"unchanged\": 4"))
+        .success();
+    filey()
+        .args(["chown", "no-such-user-here:", "site"])
+        .assert()
+        .stderr(predicate::str::contains("Unknown user: no-such-user-here"))
+        .failure();
+}
+
+#[test]
+fn cli_audit() {
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    use std::os::unix::fs::PermissionsExt;
+    let perms = std::fs::Permissions::from_mode;
+    std::fs::create_dir_all(test_dir.join("app/.git")).unwrap();
+    std::fs::set_permissions(test_dir.join("app"), perms(0o755)).unwrap();
+    std::fs::write(test_dir.join("app/main.rs"), "fn main() {}").unwrap();
+    std::fs::write(test_dir.join("app/.env"), "TOKEN=x").unwrap();
+    std::fs::set_permissions(test_dir.join("app/.env"), perms(0o600)).unwrap();
+    std::fs::write(test_dir.join("app/.git/config"), "").unwrap();
+    std::fs::set_permissions(test_dir.join("app/.git/config"), perms(0o666)).unwrap();
+    let filey = || {
+        let mut cmd = Command::cargo_bin("filey").unwrap();
+        cmd.current_dir(&test_dir);
+        cmd
+    };
+
+    filey()
+        .args(["audit", "app"])
+        .assert()
+        .stdout(predicate::str::contains("HIGH   app/.git/config: world-writable (file, mode 0666)\n"))
+        .stdout(predicate::str::contains("LOW    app/.env: secret (name suggests credentials)\n"))
+        .stdout(predicate::str::ends_with("1 high, 0 medium, 1 low\n"))
+        .code(1);
+
+    filey()
+        .args(["audit", "--exclude", ".git", "--fail-on", "medium", "app"])
+        .assert()
+        .stdout("LOW    app/.env: secret (name suggests credentials)\nAudited 3 paths: 0 high, 0 medium, 1 low\n")
+        .success();
+
+    filey()
+        .args(["audit", "missing"])
+        .assert()
+        .stderr(predicate::str::contains("missing: No such file"))
+        .code(2);
+}
+
+#[test]
+fn cli_watch_sync() {
+    use std::io::{BufRead, BufReader, Read};
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    let (src, dst) = (test_dir.join("src"), test_dir.join("dst"));
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("a.txt"), "a").unwrap();
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("filey"))
+        .args(["watch", "--once", "--debounce", "50", "--delete", "--sync"])
+        .args([&dst, &src])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    // The initial sync is printed once the watch is in place.
+    let mut line = String::new();
+    while !line.starts_with("Synced") {
+        line.clear();
+        assert_ne!(stdout.read_line(&mut line).unwrap(), 0, "watch exited early");
+    }
+    std::fs::write(src.join("b.txt"), "b").unwrap();
+    std::fs::remove_file(src.join("a.txt")).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("watch did not exit");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    assert!(rest.starts_with("created "), "{}", rest);
+    assert!(rest.contains("b.txt\ndeleted  "), "{}", rest);
+    assert_eq!(std::fs::read_to_string(dst.join("b.txt")).unwrap(), "b");
+    assert!(!dst.join("a.txt").exists());
+}
+
+#[test]
+fn cli_daemon_remote() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let binding = TempDir::new().unwrap();
+    let test_dir = binding.to_path_buf();
+    defer!(binding.close().unwrap());
+
+    let socket = test_dir.join("filey.sock");
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("filey"))
+        .args(["daemon", "--jobs", "2", "--socket"])
+        .arg(&socket)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    defer!({
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert!(line.starts_with("Listening on"), "{}", line);
+
+    let filey = || {
+        let mut cmd = Command::cargo_bin("filey").unwrap();
+        cmd.current_dir(&test_dir);
+        cmd
+    };
+    std::fs::write(test_dir.join("a.txt"), "hello").unwrap();
+    filey()
+        .arg("--remote")
+        .arg(&socket)
+        .args(["copy", "a.txt", "b.txt"])
+        .assert()
+        .stdout(predicate::str::starts_with("Copied"))
+        .success();
+    assert_eq!(std::fs::read_to_string(test_dir.join("b.txt")).unwrap(), "hello");
+
+    filey()
+        .args(["del", "b.txt", "--remote"])
+        .arg(&socket)
+        .assert()
+        .success();
+    assert!(!test_dir.join("b.txt").exists());
+
+    filey()
+        .args(["copy", "missing", "c.txt", "--remote"])
+        .arg(&socket)
+        .assert()
+        .stderr(predicate::str::contains("No such file"))
+        .failure();
+
+    filey()
+        .args(["ls", "--remote"])
+        .arg(&socket)
+        .assert()
+        .stderr(predicate::str::contains("Only create, copy, cat and del can run with --remote"))
+        .failure();
+}